@@ -0,0 +1,146 @@
+//! 项目访问策略：允许 / 禁止列表（项目 ID 或项目路径 glob）与“仅当前项目”模式
+//!
+//! 由 `Config::resolve_project` 与 `Config::list_project_dirs` 统一执行：显式指定被拒绝的项目返回 `access_denied`；
+//! 列举项目（`all`、`history_projects`、错误提示中的可用项目）时直接跳过，不暴露被拒绝的项目名
+
+use crate::types::ErrorResponse;
+use crate::utils::{path_to_project_id, unqualified_project_id};
+use serde::Serialize;
+
+/// 项目匹配规则：以 `/`、`~`、盘符（`C:`）开头的是项目路径 glob，否则是项目 ID（可带 `root:` 前缀）
+///
+/// 路径 glob 按项目 ID 的转换规则编码后与 ID 匹配，所以 `-`、`_` 与 `/` 等价，`*` 也匹配路径分隔符
+#[derive(Debug, Clone)]
+pub enum ProjectRule {
+    Id(String),
+    Path { rule: String, pattern: glob::Pattern },
+}
+
+impl ProjectRule {
+    pub fn parse(rule: &str) -> Result<Self, ErrorResponse> {
+        let is_path = rule.starts_with('/') || rule.starts_with('~') || rule.get(1..2) == Some(":");
+        if !is_path {
+            return Ok(Self::Id(rule.to_string()));
+        }
+        let path = match (rule.strip_prefix("~/"), dirs::home_dir()) {
+            (Some(rest), Some(home)) => home.join(rest).to_string_lossy().to_string(),
+            _ => rule.to_string(),
+        };
+        // 编码后路径分隔符不复存在，`**` 与 `*` 等价
+        let encoded = path_to_project_id(&path).replace("**", "*");
+        let pattern = glob::Pattern::new(&encoded).map_err(|e| ErrorResponse {
+            error: "invalid_config".to_string(),
+            message: format!("无效的项目路径模式 {}: {}", rule, e),
+            available: None,
+        })?;
+        Ok(Self::Path {
+            rule: rule.to_string(),
+            pattern,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Id(id) => id,
+            Self::Path { rule, .. } => rule,
+        }
+    }
+
+    /// 限定 ID（`root:id`）的规则只匹配该根下的项目，其余规则忽略根名
+    pub fn matches(&self, project_id: &str) -> bool {
+        let bare = unqualified_project_id(project_id);
+        match self {
+            Self::Id(id) => id == project_id || id == bare,
+            Self::Path { pattern, .. } => pattern.matches(bare),
+        }
+    }
+}
+
+impl Serialize for ProjectRule {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// 访问策略；默认不限制
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AccessPolicy {
+    /// 为空时允许全部项目
+    pub allow: Vec<ProjectRule>,
+    /// 优先于 allow 与 current_only
+    pub deny: Vec<ProjectRule>,
+    /// 只允许当前项目（由工作目录推断），适合每个项目单独启动的 MCP 服务器
+    pub current_only: bool,
+}
+
+impl AccessPolicy {
+    /// 拒绝全部项目（配置文件无效时的回退）
+    pub fn deny_all() -> Self {
+        Self {
+            deny: vec![ProjectRule::Path {
+                rule: "*".to_string(),
+                pattern: glob::Pattern::new("*").expect("deny-all pattern"),
+            }],
+            ..Default::default()
+        }
+    }
+
+    pub fn parse_rules(rules: &[String]) -> Result<Vec<ProjectRule>, ErrorResponse> {
+        rules.iter().map(|r| ProjectRule::parse(r)).collect()
+    }
+
+    /// current 为当前项目 ID（仅 current_only 时使用）
+    pub fn allows(&self, project_id: &str, current: Option<&str>) -> bool {
+        if self.deny.iter().any(|r| r.matches(project_id)) {
+            return false;
+        }
+        if self.current_only && current != Some(unqualified_project_id(project_id)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|r| r.matches(project_id))
+    }
+
+    pub fn denied(project_id: &str) -> ErrorResponse {
+        ErrorResponse {
+            error: "access_denied".to_string(),
+            message: format!("访问策略不允许访问该项目: {}", project_id),
+            available: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_rules() {
+        let rules = |r: &[&str]| AccessPolicy::parse_rules(&r.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+        let policy = AccessPolicy {
+            allow: rules(&["/home/me/work/**", "-tmp-scratch"]).unwrap(),
+            deny: rules(&["/home/me/work/client_x*", "backup:-tmp-scratch"]).unwrap(),
+            current_only: false,
+        };
+        assert!(policy.allows("-home-me-work-app", None));
+        assert!(policy.allows("laptop:-home-me-work-app", None));
+        assert!(!policy.allows("-home-me-personal", None));
+        // `_` 与 `-` 在项目 ID 中无法区分
+        assert!(!policy.allows("-home-me-work-client-x-web", None));
+        assert!(policy.allows("-tmp-scratch", None));
+        assert!(!policy.allows("backup:-tmp-scratch", None));
+        assert_eq!(
+            serde_json::to_value(&policy.deny).unwrap()[0],
+            "/home/me/work/client_x*"
+        );
+
+        let policy = AccessPolicy {
+            current_only: true,
+            ..Default::default()
+        };
+        assert!(policy.allows("root:-a", Some("-a")));
+        assert!(!policy.allows("-b", Some("-a")));
+        assert!(!policy.allows("-a", None));
+
+        assert_eq!(rules(&["/a/[b"]).unwrap_err().error, "invalid_config");
+    }
+}
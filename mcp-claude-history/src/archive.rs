@@ -0,0 +1,289 @@
+//! 会话归档：把最后修改时间早于截止时间的会话文件原地压缩为 `.jsonl.gz` / `.jsonl.zst`
+//!
+//! 文件名主干不变、解压后的内容逐字节相同，因此 session ID、ref（`prefix:line`）在归档前后都有效。
+//! 压缩写入临时文件并回读校验后才替换原文件；期间原文件被改动（会话仍在写入）则放弃该文件
+
+use crate::config::Config;
+use crate::index::index_path_for;
+use crate::reader::Compression;
+use crate::search::collect_jsonl_files;
+use crate::types::*;
+use rayon::prelude::*;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+/// 归档参数
+pub struct ArchiveParams {
+    /// 只归档最后修改时间早于该时间的会话
+    pub before: chrono::DateTime<chrono::Utc>,
+    pub format: Compression,
+    /// 只列出将被归档的文件
+    pub dry_run: bool,
+}
+
+impl Default for ArchiveParams {
+    fn default() -> Self {
+        Self {
+            before: chrono::DateTime::UNIX_EPOCH,
+            format: Compression::Zstd,
+            dry_run: false,
+        }
+    }
+}
+
+/// 解析归档格式：gz / zst
+pub fn parse_format(s: &str) -> Result<Compression, ErrorResponse> {
+    match s {
+        "gz" | "gzip" => Ok(Compression::Gzip),
+        "zst" | "zstd" => Ok(Compression::Zstd),
+        _ => Err(ErrorResponse {
+            error: "invalid_format".to_string(),
+            message: format!("无效的归档格式: {}", s),
+            available: Some(serde_json::json!(["gz", "zst"])),
+        }),
+    }
+}
+
+/// 归档项目目录中的旧会话（含 subagent 会话）
+pub fn archive(
+    config: &Config,
+    project_dirs: &[(String, PathBuf)],
+    params: ArchiveParams,
+) -> Result<ArchiveResponse, ErrorResponse> {
+    let start = Instant::now();
+    if params.format == Compression::None {
+        return Err(ErrorResponse {
+            error: "invalid_format".to_string(),
+            message: "归档格式必须是压缩格式".to_string(),
+            available: Some(serde_json::json!(["gz", "zst"])),
+        });
+    }
+    let cutoff = SystemTime::from(params.before);
+
+    let mut candidates: Vec<(String, String, PathBuf, u64)> = collect_jsonl_files(project_dirs, &[], true)
+        .into_iter()
+        .filter(|(_, _, path)| Compression::of(path) == Compression::None)
+        .filter_map(|(project, session, path)| {
+            let meta = fs::metadata(&path).ok()?;
+            (meta.modified().ok()? < cutoff).then_some((project, session, path, meta.len()))
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.2.cmp(&b.2));
+
+    let mut files: Vec<ArchivedFile> = candidates
+        .par_iter()
+        .map(|(project, session, path, size)| {
+            let target = path.with_file_name(format!("{}{}", session, params.format.extension()));
+            let mut file = ArchivedFile {
+                project: project.clone(),
+                session: session.clone(),
+                path: target.clone(),
+                bytes_before: *size,
+                bytes_after: None,
+                error: None,
+            };
+            if params.dry_run {
+                return file;
+            }
+            match compress_file(path, &target, params.format) {
+                Ok(bytes_after) => {
+                    file.bytes_after = Some(bytes_after);
+                    // 旧路径的索引已无用（归档文件不建索引）
+                    if let Some(index_path) = index_path_for(config, path) {
+                        let _ = fs::remove_file(index_path);
+                    }
+                }
+                Err(e) => {
+                    file.path = path.clone();
+                    file.error = Some(e.to_string());
+                }
+            }
+            file
+        })
+        .collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let archived = files.iter().filter(|f| f.bytes_after.is_some());
+    Ok(ArchiveResponse {
+        dry_run: params.dry_run,
+        format: params.format.extension().trim_start_matches(".jsonl.").to_string(),
+        files_archived: archived.clone().count(),
+        files_failed: files.iter().filter(|f| f.error.is_some()).count(),
+        bytes_before: archived.clone().map(|f| f.bytes_before).sum(),
+        bytes_after: archived.filter_map(|f| f.bytes_after).sum(),
+        files,
+        time_ms: start.elapsed().as_millis() as u64,
+    })
+}
+
+/// 压缩单个文件并替换原文件，返回压缩后的字节数
+fn compress_file(path: &Path, target: &Path, format: Compression) -> io::Result<u64> {
+    let mut tmp_name = target.as_os_str().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = PathBuf::from(tmp_name);
+    let meta = fs::metadata(path)?;
+    let result = write_compressed(path, &tmp, &meta, format).and_then(|size| {
+        fs::rename(&tmp, target)?;
+        // 校验之后、删除之前仍可能有追加：删除前再确认一次，改动过就保留原文件、丢弃压缩副本
+        // （同名的 .jsonl 与压缩文件并存时读取方优先使用 .jsonl）
+        if !unchanged(path, &meta)? {
+            let _ = fs::remove_file(target);
+            return Err(modified_error());
+        }
+        fs::remove_file(path)?;
+        Ok(size)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+fn write_compressed(path: &Path, tmp: &Path, meta: &fs::Metadata, format: Compression) -> io::Result<u64> {
+    let mut input = File::open(path)?;
+    let output = BufWriter::new(File::create(tmp)?);
+    let output = match format {
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?
+        }
+        _ => {
+            let mut encoder = zstd::stream::write::Encoder::new(output, 0)?;
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?
+        }
+    };
+    let output = output.into_inner().map_err(|e| e.into_error())?;
+    // 保留权限与修改时间：会话列表按 mtime 排序、归档截止时间也按 mtime 判断
+    output.set_permissions(meta.permissions())?;
+    output.set_modified(meta.modified()?)?;
+    output.sync_all()?;
+
+    if !unchanged(path, meta)? {
+        return Err(modified_error());
+    }
+    if !same_content(BufReader::new(File::open(path)?), format.reader(File::open(tmp)?)?)? {
+        return Err(io::Error::other("压缩结果校验失败"));
+    }
+    Ok(fs::metadata(tmp)?.len())
+}
+
+/// 文件的大小和修改时间是否仍与 meta 一致
+fn unchanged(path: &Path, meta: &fs::Metadata) -> io::Result<bool> {
+    let after = fs::metadata(path)?;
+    Ok(after.len() == meta.len() && after.modified()? == meta.modified()?)
+}
+
+fn modified_error() -> io::Error {
+    io::Error::other("压缩期间文件被修改（会话仍在写入）")
+}
+
+/// 逐块比较两个流的内容
+fn same_content(mut a: impl BufRead, mut b: impl BufRead) -> io::Result<bool> {
+    loop {
+        let chunk = a.fill_buf()?;
+        if chunk.is_empty() {
+            return Ok(b.fill_buf()?.is_empty());
+        }
+        let len = chunk.len();
+        let mut other = vec![0u8; len];
+        let mut read = 0;
+        while read < len {
+            let n = b.read(&mut other[read..])?;
+            if n == 0 {
+                return Ok(false);
+            }
+            read += n;
+        }
+        if chunk != other.as_slice() {
+            return Ok(false);
+        }
+        a.consume(len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::SessionReader;
+    use crate::utils::session_id_from_filename;
+
+    #[test]
+    fn test_archive_keeps_refs() {
+        let home = std::env::temp_dir().join(format!("mcp-archive-test-{}", std::process::id()));
+        let project = home.join("projects").join("-p");
+        let subagents = project.join("aaaa1111-0000").join("subagents");
+        fs::create_dir_all(&subagents).unwrap();
+        let data = concat!(
+            r#"{"type":"user","timestamp":"2026-05-01T00:00:00Z","message":{"role":"user","content":"one"}}"#,
+            "\n\n",
+            r#"{"type":"user","timestamp":"2026-05-01T00:00:01Z","message":{"role":"user","content":"three"}}"#,
+            "\n",
+        );
+        fs::write(project.join("aaaa1111-0000.jsonl"), data).unwrap();
+        fs::write(project.join("bbbb2222-0000.jsonl"), data).unwrap();
+        fs::write(subagents.join("agent-x.jsonl"), data).unwrap();
+        let config = Config::for_test(&home);
+        let dirs = vec![("-p".to_string(), project.clone())];
+        let future = chrono::Utc::now() + chrono::Duration::days(1);
+
+        // 截止时间之前没有文件
+        let params = ArchiveParams::default();
+        assert_eq!(archive(&config, &dirs, params).unwrap().files.len(), 0);
+
+        let params = ArchiveParams {
+            before: future,
+            dry_run: true,
+            ..Default::default()
+        };
+        let response = archive(&config, &dirs, params).unwrap();
+        assert_eq!((response.files.len(), response.files_archived), (3, 0));
+        assert!(project.join("aaaa1111-0000.jsonl").exists());
+
+        let params = ArchiveParams {
+            before: future,
+            format: Compression::Gzip,
+            ..Default::default()
+        };
+        let response = archive(&config, &dirs, params).unwrap();
+        assert_eq!((response.files_archived, response.files_failed), (3, 0));
+        assert!(!project.join("aaaa1111-0000.jsonl").exists());
+
+        let params = ArchiveParams {
+            before: future,
+            ..Default::default()
+        };
+        assert_eq!(archive(&config, &dirs, params).unwrap().files.len(), 0);
+
+        let mut files = collect_jsonl_files(&dirs, &[], true);
+        files.sort_by(|a, b| a.1.cmp(&b.1));
+        let sessions: Vec<&str> = files.iter().map(|f| f.1.as_str()).collect();
+        assert_eq!(sessions, ["aaaa1111-0000", "agent-x", "bbbb2222-0000"]);
+        let lines: Vec<usize> = SessionReader::open(&files[0].2)
+            .unwrap()
+            .map(|(line, _)| line)
+            .collect();
+        assert_eq!(lines, [1, 3]);
+        assert_eq!(
+            session_id_from_filename("cccc3333.jsonl.zst").as_deref(),
+            Some("cccc3333")
+        );
+
+        // 归档中途失败时原文件仍在：只读取原文件
+        fs::write(project.join("bbbb2222-0000.jsonl"), data).unwrap();
+        let files = collect_jsonl_files(&dirs, &["bbbb2222".to_string()], false);
+        assert_eq!(files.len(), 1);
+        assert_eq!(Compression::of(&files[0].2), Compression::None);
+
+        // 删除原文件前的复查能发现追加写入
+        let meta = fs::metadata(&files[0].2).unwrap();
+        assert!(unchanged(&files[0].2, &meta).unwrap());
+        fs::write(&files[0].2, format!("{}{}", data, data)).unwrap();
+        assert!(!unchanged(&files[0].2, &meta).unwrap());
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+}
@@ -1,56 +1,527 @@
-use crate::types::ErrorResponse;
-use crate::utils::project_id_to_display_path;
+//! 配置：环境变量 + TOML 配置文件 + 命令行参数，按此顺序合并（后者覆盖前者）
+//!
+//! 配置文件默认在 XDG 配置目录下的 `mcp-claude-history/config.toml`，可用 `MCP_CLAUDE_HISTORY_CONFIG` 指定；
+//! 每项生效值的来源记录在 `Config::sources`，供 `config show` 展示
+
+use crate::access::AccessPolicy;
+use crate::time::TimeZone;
+use crate::types::{ConfigSetting, ConfigShowResponse, ConfigValidateResponse, ErrorResponse};
+use crate::utils::{path_to_project_id, project_id_to_display_path, unqualified_project_id};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// 指定配置文件路径的环境变量
+pub const CONFIG_ENV: &str = "MCP_CLAUDE_HISTORY_CONFIG";
+
+/// 历史根目录：一个 Claude 配置目录（`~/.claude`、`CLAUDE_CONFIG_DIR`，或其他机器历史的备份），会话在其 `projects/` 下
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Root {
+    /// 根名称；项目 ID 在多个根中重名时写作 `name:project-id`
+    pub name: String,
+    pub projects_dir: PathBuf,
+}
+
+impl Root {
+    /// 由配置目录创建；未指定名称时取目录名（去掉开头的 `.`，如 `~/.claude` → `claude`）
+    pub fn new(name: Option<&str>, claude_dir: &Path) -> Self {
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => claude_dir
+                .file_name()
+                .map(|n| n.to_string_lossy().trim_start_matches('.').to_string())
+                .filter(|n| !n.is_empty())
+                .unwrap_or_else(|| "root".to_string()),
+        };
+        Self {
+            // `:` 是项目 ID 的限定分隔符
+            name: name.replace(':', "-"),
+            projects_dir: claude_dir.join("projects"),
+        }
+    }
+
+    /// 解析 `--root` 参数：`name=path` 或 `path`
+    pub fn parse(spec: &str) -> Self {
+        match spec.split_once('=') {
+            Some((name, path)) if !name.is_empty() => Self::new(Some(name), &expand_home(path)),
+            _ => Self::new(None, &expand_home(spec)),
+        }
+    }
+}
+
+/// 输出默认值：CLI 与 MCP 未显式传参时使用，可由配置文件 `[defaults]` 覆盖
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Defaults {
+    /// 单条消息的最大字符数
+    pub max_content: usize,
+    /// 单次响应的最大总字符数
+    pub max_total: usize,
+    /// search 中 tool_result 消息的最大字符数
+    pub max_content_tool_result: usize,
+    /// search / tail 默认的消息类型
+    pub types: Vec<String>,
+    /// search 全局命中硬上限（防止 OOM；超过即截断并在响应里标 truncated）
+    pub result_cap: usize,
+    /// get 直接返回的最大字符数，更大的内容需用 output 或 range
+    pub max_direct_size: usize,
+    /// file_versions 各版本内容的总字符数上限
+    pub file_versions_max_total: usize,
+    /// 解释不带偏移的 since / until 与渲染时间（local_timestamp、timeline 分桶、usage 按天分组、导出）所用的时区
+    pub timezone: TimeZone,
+}
+
+impl Default for Defaults {
+    fn default() -> Self {
+        Self {
+            max_content: 4000,
+            max_total: 40000,
+            max_content_tool_result: 500,
+            types: vec!["assistant".to_string(), "user".to_string(), "summary".to_string()],
+            result_cap: 50_000,
+            max_direct_size: 100_000,
+            file_versions_max_total: 100_000,
+            timezone: TimeZone::Utc,
+        }
+    }
+}
+
+/// 配置文件 `[[roots]]` 中的一项
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RootEntry {
+    #[serde(default)]
+    name: Option<String>,
+    path: String,
+}
+
+/// 配置文件（TOML）；未知键视为错误，避免拼写错误被静默忽略
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    index_dir: Option<String>,
+    pricing_file: Option<String>,
+    redact_file: Option<String>,
+    http_token: Option<String>,
+    roots: Vec<RootEntry>,
+    output_roots: Vec<String>,
+    defaults: DefaultsSection,
+    access: AccessSection,
+    redact: RedactSection,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DefaultsSection {
+    max_content: Option<usize>,
+    max_total: Option<usize>,
+    max_content_tool_result: Option<usize>,
+    types: Option<Vec<String>>,
+    result_cap: Option<usize>,
+    max_direct_size: Option<usize>,
+    file_versions_max_total: Option<usize>,
+    timezone: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AccessSection {
+    allow_projects: Option<Vec<String>>,
+    deny_projects: Option<Vec<String>>,
+    current_only: Option<bool>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RedactSection {
+    patterns: BTreeMap<String, String>,
+}
 
 /// 配置
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub projects_dir: PathBuf,
+    /// 历史根目录，按优先级排列：第一个是主根（`CLAUDE_CONFIG_DIR` 或 `~/.claude`），其后是配置文件和 `--root`
+    pub roots: Vec<Root>,
+    /// 持久化搜索索引目录（默认与主根的 projects 目录同级）
+    pub index_dir: PathBuf,
+    /// 用量统计的价格表（不存在时不估算费用）
+    pub pricing_file: Option<PathBuf>,
+    /// 用户自定义脱敏规则（不存在时只用内置规则）
+    pub redact_file: Option<PathBuf>,
+    /// 配置文件中的脱敏规则：`{"名称": "正则"}`，与 redact_file 合并
+    pub redact_patterns: BTreeMap<String, String>,
+    /// 除当前工作目录和受控临时目录外，允许写入导出文件的目录
+    pub output_roots: Vec<PathBuf>,
+    /// 项目访问策略（允许 / 禁止列表、仅当前项目）
+    pub access: AccessPolicy,
+    pub defaults: Defaults,
+    /// --http 模式的 bearer token（MCP_CLAUDE_HISTORY_TOKEN），未设置时不校验
+    pub http_token: Option<String>,
+    /// 读取的配置文件（不存在时仍记录查找路径）
+    pub config_file: Option<PathBuf>,
+    /// 各配置项的来源：default、env:NAME、file、cli:--flag
+    pub sources: BTreeMap<String, String>,
 }
 
 impl Config {
-    pub fn from_env() -> Self {
-        let claude_dir = dirs::home_dir()
-            .map(|h| h.join(".claude"))
-            .unwrap_or_else(|| PathBuf::from(".claude"));
+    /// 以 claude_dir 为主根、其余取默认值；不读环境变量和配置文件
+    pub fn new(claude_dir: &Path) -> Self {
+        let app_dir = dirs::config_dir().map(|d| d.join("mcp-claude-history"));
+        Self {
+            roots: vec![Root::new(None, claude_dir)],
+            index_dir: claude_dir.join("history-index"),
+            pricing_file: app_dir.as_ref().map(|d| d.join("pricing.json")),
+            redact_file: app_dir.as_ref().map(|d| d.join("redact.json")),
+            redact_patterns: BTreeMap::new(),
+            output_roots: Vec::new(),
+            access: AccessPolicy::default(),
+            defaults: Defaults::default(),
+            http_token: None,
+            config_file: None,
+            sources: BTreeMap::new(),
+        }
+    }
 
+    /// 测试用：同 new，但不加载用户目录下的价格表和脱敏规则，结果不受运行环境影响
+    #[cfg(test)]
+    pub(crate) fn for_test(claude_dir: &Path) -> Self {
         Self {
-            projects_dir: claude_dir.join("projects"),
+            pricing_file: None,
+            redact_file: None,
+            ..Self::new(claude_dir)
+        }
+    }
+
+    /// 环境变量 + 配置文件；配置文件有误时返回 invalid_config
+    pub fn load() -> Result<Self, ErrorResponse> {
+        let mut config = Self::env_only();
+        if let Some(path) = config.config_file.clone().filter(|p| p.exists()) {
+            config.apply_file(&path)?;
+        }
+        Ok(config)
+    }
+
+    /// 同 load；配置文件有误时告警并忽略配置文件，但无法确认其中的访问策略，因此拒绝访问全部项目
+    pub fn from_env() -> Self {
+        Self::load().unwrap_or_else(|e| Self::env_only().fail_closed(&e))
+    }
+
+    /// 配置文件无效时的回退：拒绝全部项目，避免策略文件的笔误放开所有项目
+    fn fail_closed(mut self, error: &ErrorResponse) -> Self {
+        eprintln!("[config] {}（配置文件无效，已拒绝访问全部项目）", error.message);
+        self.access = AccessPolicy::deny_all();
+        self.set_source("access.deny_projects", "invalid_config");
+        self
+    }
+
+    /// 只读环境变量：`CLAUDE_CONFIG_DIR`（否则 `~/.claude`）、`MCP_CLAUDE_HISTORY_TOKEN`（优先于配置文件的 http_token）
+    fn env_only() -> Self {
+        let claude_dir = env::var_os("CLAUDE_CONFIG_DIR").filter(|d| !d.is_empty());
+        let mut config = match &claude_dir {
+            Some(dir) => Self::new(Path::new(dir)),
+            None => Self::new(&dirs::home_dir().unwrap_or_default().join(".claude")),
+        };
+        if claude_dir.is_some() {
+            config.set_source("roots", "env:CLAUDE_CONFIG_DIR");
+            config.set_source("index_dir", "env:CLAUDE_CONFIG_DIR");
+        }
+        config.http_token = env::var("MCP_CLAUDE_HISTORY_TOKEN").ok().filter(|t| !t.is_empty());
+        if config.http_token.is_some() {
+            config.set_source("http_token", "env:MCP_CLAUDE_HISTORY_TOKEN");
+        }
+        config.config_file = config_file_path();
+        config
+    }
+
+    /// 合并配置文件；相对路径相对于配置文件所在目录
+    pub fn apply_file(&mut self, path: &Path) -> Result<(), ErrorResponse> {
+        let file = read_config_file(path)?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        let resolve = |p: &str| base.join(expand_home(p));
+
+        if let Some(dir) = &file.index_dir {
+            self.index_dir = resolve(dir);
+            self.set_source("index_dir", "file");
+        }
+        if let Some(p) = &file.pricing_file {
+            self.pricing_file = Some(resolve(p));
+            self.set_source("pricing_file", "file");
+        }
+        if let Some(p) = &file.redact_file {
+            self.redact_file = Some(resolve(p));
+            self.set_source("redact_file", "file");
+        }
+        // 环境变量 MCP_CLAUDE_HISTORY_TOKEN 优先
+        if let Some(token) = file.http_token.as_ref().filter(|t| !t.is_empty())
+            && self.http_token.is_none()
+        {
+            self.http_token = Some(token.clone());
+            self.set_source("http_token", "file");
+        }
+        for entry in &file.roots {
+            self.add_root(Root::new(entry.name.as_deref(), &resolve(&entry.path)));
+            self.set_source("roots", "file");
+        }
+        if !file.output_roots.is_empty() {
+            self.output_roots = file.output_roots.iter().map(|p| resolve(p)).collect();
+            self.set_source("output_roots", "file");
+        }
+        if !file.redact.patterns.is_empty() {
+            self.redact_patterns = file.redact.patterns;
+            self.set_source("redact.patterns", "file");
+        }
+        if let Some(rules) = &file.access.allow_projects {
+            self.access.allow = AccessPolicy::parse_rules(rules)?;
+            self.set_source("access.allow_projects", "file");
+        }
+        if let Some(rules) = &file.access.deny_projects {
+            self.access.deny = AccessPolicy::parse_rules(rules)?;
+            self.set_source("access.deny_projects", "file");
+        }
+        if let Some(current_only) = file.access.current_only {
+            self.access.current_only = current_only;
+            self.set_source("access.current_only", "file");
+        }
+
+        let d = file.defaults;
+        let defaults = &mut self.defaults;
+        let mut set = Vec::new();
+        for (key, value, target) in [
+            ("max_content", d.max_content, &mut defaults.max_content),
+            ("max_total", d.max_total, &mut defaults.max_total),
+            (
+                "max_content_tool_result",
+                d.max_content_tool_result,
+                &mut defaults.max_content_tool_result,
+            ),
+            ("result_cap", d.result_cap, &mut defaults.result_cap),
+            ("max_direct_size", d.max_direct_size, &mut defaults.max_direct_size),
+            (
+                "file_versions_max_total",
+                d.file_versions_max_total,
+                &mut defaults.file_versions_max_total,
+            ),
+        ] {
+            if let Some(value) = value {
+                *target = value;
+                set.push(key);
+            }
+        }
+        if let Some(types) = d.types {
+            defaults.types = types;
+            set.push("types");
+        }
+        if let Some(timezone) = &d.timezone {
+            defaults.timezone = TimeZone::parse(timezone).map_err(|e| ErrorResponse {
+                error: "invalid_config".to_string(),
+                message: format!("defaults.timezone: {}", e.message),
+                available: None,
+            })?;
+            set.push("timezone");
+        }
+        for key in set {
+            self.set_source(&format!("defaults.{}", key), "file");
+        }
+        Ok(())
+    }
+
+    /// 记录配置项来源；同一项有多个来源（如 roots）时依次追加
+    pub fn set_source(&mut self, key: &str, source: &str) {
+        let entry = self.sources.entry(key.to_string()).or_default();
+        if entry.is_empty() {
+            *entry = source.to_string();
+        } else if !entry.split(", ").any(|s| s == source) {
+            entry.push_str(", ");
+            entry.push_str(source);
+        }
+    }
+
+    /// 项目是否允许访问（见 [`AccessPolicy`]）
+    pub fn project_allowed(&self, project_id: &str) -> bool {
+        let current = if self.access.current_only {
+            cwd_project_id()
+        } else {
+            None
+        };
+        self.access.allows(project_id, current.as_deref())
+    }
+
+    /// 生效配置及其来源（`config show`）；http_token 只显示是否设置
+    pub fn show(&self) -> ConfigShowResponse {
+        let mut settings = BTreeMap::new();
+        let mut put = |key: &str, value: serde_json::Value| {
+            let source = self.sources.get(key).cloned().unwrap_or_else(|| "default".to_string());
+            settings.insert(key.to_string(), ConfigSetting { value, source });
+        };
+        let roots: Vec<_> = self
+            .roots
+            .iter()
+            .map(|r| serde_json::json!({ "name": r.name, "projects_dir": r.projects_dir }))
+            .collect();
+        put("roots", serde_json::json!(roots));
+        put("index_dir", serde_json::json!(self.index_dir));
+        put("pricing_file", serde_json::json!(self.pricing_file));
+        put("redact_file", serde_json::json!(self.redact_file));
+        put("redact.patterns", serde_json::json!(self.redact_patterns));
+        put("output_roots", serde_json::json!(self.output_roots));
+        put("access.allow_projects", serde_json::json!(self.access.allow));
+        put("access.deny_projects", serde_json::json!(self.access.deny));
+        put("access.current_only", serde_json::json!(self.access.current_only));
+        put(
+            "http_token",
+            serde_json::json!(self.http_token.as_ref().map(|_| "<set>")),
+        );
+        let defaults = serde_json::to_value(&self.defaults).unwrap_or_default();
+        if let serde_json::Value::Object(defaults) = defaults {
+            for (key, value) in defaults {
+                put(&format!("defaults.{}", key), value);
+            }
+        }
+        ConfigShowResponse {
+            config_file: self.config_file.clone(),
+            config_file_exists: self.config_file.as_ref().is_some_and(|p| p.exists()),
+            settings,
+        }
+    }
+
+    /// 追加根目录：同一目录只保留一次，重名时追加序号
+    pub fn add_root(&mut self, root: Root) {
+        if self.roots.iter().any(|r| r.projects_dir == root.projects_dir) {
+            return;
+        }
+        let mut name = root.name.clone();
+        let mut n = 2;
+        while self.roots.iter().any(|r| r.name == name) {
+            name = format!("{}-{}", root.name, n);
+            n += 1;
+        }
+        self.roots.push(Root { name, ..root });
+    }
+
+    /// 文件所在根目录的名称；只有一个根时返回 None（输出中省略 root 字段）
+    pub fn root_label(&self, path: &Path) -> Option<String> {
+        if self.roots.len() < 2 {
+            return None;
         }
+        self.roots
+            .iter()
+            .find(|r| path.starts_with(&r.projects_dir))
+            .map(|r| r.name.clone())
     }
 
     /// 获取当前项目 ID（从 CWD 推断）
     pub fn current_project_id(&self) -> Option<String> {
-        let cwd = env::current_dir().ok()?;
-        // Claude Code 的转换规则：/、\、:、_ 都变成 -
-        let project_id = cwd.to_string_lossy().replace(['\\', '/', ':', '_'], "-");
+        let project_id = cwd_project_id()?;
 
-        // 检查该项目目录是否存在
-        if self.projects_dir.join(&project_id).exists() {
+        // 检查该项目目录是否存在（任一根目录下）
+        if self.roots.iter().any(|r| r.projects_dir.join(&project_id).exists()) {
             Some(project_id)
         } else {
             None
         }
     }
 
-    /// 获取项目目录,project_id 必须只含字母、数字、`_`、`-`,不允许 `/`、`\`、`..`、首字符 `.`
+    /// 获取项目目录；多个根目录都有该项目时取第一个，可用 `root:project-id` 指定
     pub fn project_dir(&self, project_id: &str) -> Result<PathBuf, ErrorResponse> {
+        Ok(self.resolve_project(project_id)?.swap_remove(0).1)
+    }
+
+    /// 解析项目 ID 为 (展示 ID, 目录) 列表，project_id 必须只含字母、数字、`_`、`-`（可带 `root:` 前缀），
+    /// 不允许 `/`、`\`、`..`、首字符 `.`
+    ///
+    /// 不带前缀时匹配所有含该项目的根目录，多于一个时展示 ID 带上根名；都不存在时返回主根下的路径，由调用方报 project_not_found
+    ///
+    /// 访问策略先于存在性检查：被拒绝的项目无论是否存在都报 access_denied；只在部分根中被拒绝时跳过这些根
+    pub fn resolve_project(&self, project_id: &str) -> Result<Vec<(String, PathBuf)>, ErrorResponse> {
+        if !self.project_allowed(project_id) {
+            return Err(AccessPolicy::denied(project_id));
+        }
+        if let Some((root_name, id)) = project_id.split_once(':') {
+            validate_project_id(id)?;
+            let root = self
+                .roots
+                .iter()
+                .find(|r| r.name == root_name)
+                .ok_or_else(|| ErrorResponse {
+                    error: "root_not_found".to_string(),
+                    message: format!("历史根目录不存在: {}", root_name),
+                    available: Some(serde_json::json!(
+                        self.roots.iter().map(|r| &r.name).collect::<Vec<_>>()
+                    )),
+                })?;
+            return Ok(vec![(project_id.to_string(), root.projects_dir.join(id))]);
+        }
+
         validate_project_id(project_id)?;
-        Ok(self.projects_dir.join(project_id))
+        let existing: Vec<(&Root, PathBuf)> = self
+            .roots
+            .iter()
+            .map(|r| (r, r.projects_dir.join(project_id)))
+            .filter(|(_, dir)| dir.is_dir())
+            .collect();
+        let found: Vec<(&Root, PathBuf)> = existing
+            .iter()
+            .filter(|(root, _)| self.project_allowed(&format!("{}:{}", root.name, project_id)))
+            .cloned()
+            .collect();
+        if found.is_empty() && !existing.is_empty() {
+            return Err(AccessPolicy::denied(project_id));
+        }
+        Ok(match found.len() {
+            0 => vec![(project_id.to_string(), self.roots[0].projects_dir.join(project_id))],
+            1 => found
+                .into_iter()
+                .map(|(_, dir)| (project_id.to_string(), dir))
+                .collect(),
+            _ => found
+                .into_iter()
+                .map(|(root, dir)| (format!("{}:{}", root.name, project_id), dir))
+                .collect(),
+        })
     }
 
-    /// 列出所有项目目录
+    /// 列出所有根目录下允许访问的项目目录；同一项目 ID 出现在多个根中时带上根名（`root:project-id`）
     pub fn list_project_dirs(&self) -> std::io::Result<Vec<(String, PathBuf)>> {
-        let mut dirs = Vec::new();
-        for entry in fs::read_dir(&self.projects_dir)?.flatten() {
-            if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
-                let id = entry.file_name().to_string_lossy().to_string();
-                dirs.push((id, entry.path()));
+        let mut found: Vec<(&str, String, PathBuf)> = Vec::new();
+        let mut error = None;
+        let mut readable = false;
+        for root in &self.roots {
+            let entries = match fs::read_dir(&root.projects_dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    error.get_or_insert(e);
+                    continue;
+                }
+            };
+            readable = true;
+            for entry in entries.flatten() {
+                if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                    let id = entry.file_name().to_string_lossy().to_string();
+                    if !self.project_allowed(&format!("{}:{}", root.name, id)) {
+                        continue;
+                    }
+                    found.push((&root.name, id, entry.path()));
+                }
             }
         }
-        Ok(dirs)
+        // 所有根都不可读时报第一个错误；部分根（如未挂载的备份）缺失不影响其余根
+        if !readable && let Some(e) = error {
+            return Err(e);
+        }
+
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for (_, id, _) in &found {
+            *counts.entry(id.as_str()).or_default() += 1;
+        }
+        let qualified = |root: &str, id: &str| match counts[id] {
+            1 => id.to_string(),
+            _ => format!("{}:{}", root, id),
+        };
+        Ok(found
+            .iter()
+            .map(|(root, id, path)| (qualified(root, id), path.clone()))
+            .collect())
     }
 
     /// 列出可用项目（用于错误提示）
@@ -59,15 +530,144 @@ impl Config {
             .list_project_dirs()
             .unwrap_or_default()
             .into_iter()
-            .map(|(id, _)| {
-                let path = project_id_to_display_path(&id);
-                serde_json::json!({ "id": id, "path": path })
+            .map(|(id, dir)| {
+                let path = project_id_to_display_path(unqualified_project_id(&id));
+                match self.root_label(&dir) {
+                    Some(root) => serde_json::json!({ "id": id, "path": path, "root": root }),
+                    None => serde_json::json!({ "id": id, "path": path }),
+                }
             })
             .collect();
         serde_json::json!(projects)
     }
 }
 
+/// 由工作目录得到的项目 ID（不检查项目是否存在）
+fn cwd_project_id() -> Option<String> {
+    let cwd = env::current_dir().ok()?;
+    Some(path_to_project_id(&cwd.to_string_lossy()))
+}
+
+/// 配置文件路径：`MCP_CLAUDE_HISTORY_CONFIG`，否则 XDG 配置目录下的 `mcp-claude-history/config.toml`
+pub fn config_file_path() -> Option<PathBuf> {
+    match env::var_os(CONFIG_ENV).filter(|p| !p.is_empty()) {
+        Some(path) => Some(PathBuf::from(path)),
+        None => dirs::config_dir().map(|d| d.join("mcp-claude-history").join("config.toml")),
+    }
+}
+
+fn read_config_file(path: &Path) -> Result<ConfigFile, ErrorResponse> {
+    let invalid = |message: String| ErrorResponse {
+        error: "invalid_config".to_string(),
+        message,
+        available: None,
+    };
+    let text = fs::read_to_string(path).map_err(|e| invalid(format!("无法读取配置文件 {}: {}", path.display(), e)))?;
+    toml::from_str(&text).map_err(|e| invalid(format!("配置文件格式错误 {}: {}", path.display(), e)))
+}
+
+/// 校验配置文件（`config validate`）：语法与未知键是错误，正则无法编译是错误，目录不存在是警告
+pub fn validate_config_file(path: &Path) -> ConfigValidateResponse {
+    let mut response = ConfigValidateResponse {
+        config_file: path.to_path_buf(),
+        valid: false,
+        errors: Vec::new(),
+        warnings: Vec::new(),
+    };
+    if !path.exists() {
+        response.errors.push(format!("配置文件不存在: {}", path.display()));
+        return response;
+    }
+    let file = match read_config_file(path) {
+        Ok(file) => file,
+        Err(e) => {
+            response.errors.push(e.message);
+            return response;
+        }
+    };
+
+    for (name, pattern) in &file.redact.patterns {
+        if let Err(e) = regex::Regex::new(pattern) {
+            response
+                .errors
+                .push(format!("redact.patterns.{} 正则无效: {}", name, e));
+        }
+    }
+    let d = &file.defaults;
+    for (key, value) in [
+        ("max_content", d.max_content),
+        ("max_total", d.max_total),
+        ("result_cap", d.result_cap),
+        ("max_direct_size", d.max_direct_size),
+        ("file_versions_max_total", d.file_versions_max_total),
+    ] {
+        if value == Some(0) {
+            response.errors.push(format!("defaults.{} 必须大于 0", key));
+        }
+    }
+    if d.types.as_ref().is_some_and(|t| t.is_empty()) {
+        response.errors.push("defaults.types 不能为空".to_string());
+    }
+    if file.http_token.as_ref().is_some_and(|t| t.is_empty()) {
+        response.errors.push("http_token 不能为空".to_string());
+    }
+    #[cfg(unix)]
+    if file.http_token.is_some()
+        && let Ok(meta) = fs::metadata(path)
+        && std::os::unix::fs::PermissionsExt::mode(&meta.permissions()) & 0o077 != 0
+    {
+        response
+            .warnings
+            .push("配置文件含 http_token，但组或其他用户可读（建议 chmod 600）".to_string());
+    }
+
+    let mut config = Config::new(Path::new(""));
+    config.roots.clear();
+    if let Err(e) = config.apply_file(path) {
+        response.errors.push(e.message);
+    } else {
+        for root in &config.roots {
+            if !root.projects_dir.is_dir() {
+                response.warnings.push(format!(
+                    "历史根目录 {} 下没有 projects 目录: {}",
+                    root.name,
+                    root.projects_dir.display()
+                ));
+            }
+        }
+        for dir in &config.output_roots {
+            if !dir.is_dir() {
+                response.warnings.push(format!("输出目录不存在: {}", dir.display()));
+            }
+        }
+        for (key, file) in [
+            ("pricing_file", &config.pricing_file),
+            ("redact_file", &config.redact_file),
+        ] {
+            if config.sources.contains_key(key)
+                && let Some(file) = file
+                && !file.exists()
+            {
+                response.warnings.push(format!("{} 不存在: {}", key, file.display()));
+            }
+        }
+    }
+    if let Some(both) = file
+        .access
+        .deny_projects
+        .iter()
+        .flatten()
+        .find(|p| file.access.allow_projects.iter().flatten().any(|a| a == *p))
+    {
+        response.warnings.push(format!(
+            "项目同时出现在 allow_projects 和 deny_projects 中（以 deny 为准）: {}",
+            both
+        ));
+    }
+    response.valid = response.errors.is_empty();
+    response
+}
+
 /// 校验 project_id 字符白名单,拒绝路径注入字符
 fn validate_project_id(project_id: &str) -> Result<(), ErrorResponse> {
     if project_id.is_empty() {
@@ -94,3 +694,194 @@ fn validate_project_id(project_id: &str) -> Result<(), ErrorResponse> {
     }
     Ok(())
 }
+
+/// 展开路径开头的 `~/`
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roots_qualify_ambiguous_projects() {
+        let home = std::env::temp_dir().join(format!("mcp-config-test-{}", std::process::id()));
+        for dir in [
+            ".claude/projects/-a",
+            ".claude/projects/-shared",
+            "backup/projects/-shared",
+        ] {
+            fs::create_dir_all(home.join(dir)).unwrap();
+        }
+        let mut config = Config::new(&home.join(".claude"));
+        config.add_root(Root::parse(&format!("laptop={}", home.join("backup").display())));
+        // 同一目录不重复添加，重名自动追加序号
+        config.add_root(Root::parse(&home.join("backup").display().to_string()));
+        config.add_root(Root::new(Some("laptop"), &home.join("other")));
+        let names: Vec<&str> = config.roots.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["claude", "laptop", "laptop-2"]);
+
+        let mut ids: Vec<String> = config
+            .list_project_dirs()
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, ["-a", "claude:-shared", "laptop:-shared"]);
+
+        let resolved = config.resolve_project("-shared").unwrap();
+        assert_eq!(resolved.len(), 2);
+        assert_eq!(config.root_label(&resolved[1].1).as_deref(), Some("laptop"));
+        assert_eq!(
+            config.project_dir("laptop:-shared").unwrap(),
+            home.join("backup/projects/-shared")
+        );
+        assert_eq!(config.resolve_project("-a").unwrap()[0].0, "-a");
+        assert_eq!(config.resolve_project("nope:-a").unwrap_err().error, "root_not_found");
+        assert!(config.resolve_project("laptop:../x").is_err());
+
+        // 只在一个根中被拒绝的项目：其余根照常可见，不再需要限定
+        config.access.deny = AccessPolicy::parse_rules(&["laptop:-shared".to_string()]).unwrap();
+        let ids: Vec<String> = config
+            .list_project_dirs()
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&"-shared".to_string()));
+        assert_eq!(config.resolve_project("-shared").unwrap().len(), 1);
+        assert_eq!(
+            config.resolve_project("laptop:-shared").unwrap_err().error,
+            "access_denied"
+        );
+        config.access.deny = AccessPolicy::parse_rules(&["/**".to_string()]).unwrap();
+        assert_eq!(config.available_projects_json(), serde_json::json!([]));
+        assert_eq!(config.resolve_project("-a").unwrap_err().error, "access_denied");
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn test_config_file_merge_and_validate() {
+        let home = std::env::temp_dir().join(format!("mcp-config-file-test-{}", std::process::id()));
+        for dir in [
+            ".claude/projects/-a",
+            ".claude/projects/-secret",
+            "backup/projects/-b",
+            "exports",
+        ] {
+            fs::create_dir_all(home.join(dir)).unwrap();
+        }
+        let path = home.join("config.toml");
+        fs::write(
+            &path,
+            r#"
+index_dir = "index"
+output_roots = ["exports", "missing"]
+http_token = "from-file"
+
+[[roots]]
+name = "laptop"
+path = "backup"
+
+[defaults]
+max_content = 1000
+types = ["user"]
+timezone = "Asia/Shanghai"
+
+[access]
+deny_projects = ["-secret"]
+
+[redact.patterns]
+ticket = 'TICKET-\d+'
+"#,
+        )
+        .unwrap();
+
+        let mut config = Config::new(&home.join(".claude"));
+        config.apply_file(&path).unwrap();
+        // 相对路径相对于配置文件所在目录
+        assert_eq!(config.index_dir, home.join("index"));
+        assert_eq!(config.roots[1].projects_dir, home.join("backup/projects"));
+        assert_eq!(config.defaults.max_content, 1000);
+        assert_eq!(config.defaults.max_total, 40000);
+        assert_eq!(config.defaults.types, ["user"]);
+        assert_eq!(config.redact_patterns["ticket"], r"TICKET-\d+");
+
+        let mut ids: Vec<String> = config
+            .list_project_dirs()
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, ["-a", "-b"]);
+        assert_eq!(config.resolve_project("-secret").unwrap_err().error, "access_denied");
+
+        let show = config.show();
+        assert_eq!(show.settings["defaults.max_content"].source, "file");
+        assert_eq!(show.settings["defaults.max_total"].source, "default");
+        assert_eq!(show.settings["defaults.max_total"].value, serde_json::json!(40000));
+        assert_eq!(show.settings["defaults.timezone"].value, "Asia/Shanghai");
+        assert_eq!(config.http_token.as_deref(), Some("from-file"));
+        assert_eq!(show.settings["http_token"].source, "file");
+
+        // 环境变量中的 token 优先于配置文件
+        let mut env_config = Config::new(&home.join(".claude"));
+        env_config.http_token = Some("from-env".to_string());
+        env_config.set_source("http_token", "env:MCP_CLAUDE_HISTORY_TOKEN");
+        env_config.apply_file(&path).unwrap();
+        assert_eq!(env_config.http_token.as_deref(), Some("from-env"));
+        assert_eq!(
+            env_config.show().settings["http_token"].source,
+            "env:MCP_CLAUDE_HISTORY_TOKEN"
+        );
+
+        #[cfg(unix)]
+        fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o600)).unwrap();
+        let response = validate_config_file(&path);
+        assert!(response.valid, "{:?}", response.errors);
+        assert_eq!(response.warnings.len(), 1);
+        #[cfg(unix)]
+        {
+            fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o644)).unwrap();
+            assert!(
+                validate_config_file(&path)
+                    .warnings
+                    .iter()
+                    .any(|w| w.contains("http_token"))
+            );
+        }
+
+        // 配置文件无效时（from_env）拒绝全部项目
+        let error = read_config_file(&home.join("missing.toml")).err().unwrap();
+        let denied = Config::new(&home.join(".claude")).fail_closed(&error);
+        assert!(
+            !Config::new(&home.join(".claude"))
+                .list_project_dirs()
+                .unwrap()
+                .is_empty()
+        );
+        assert!(denied.list_project_dirs().unwrap().is_empty());
+        assert_eq!(denied.resolve_project("-a").unwrap_err().error, "access_denied");
+
+        // 未知键和无效正则都是错误
+        fs::write(&path, "max_content = 10\n[redact.patterns]\nbad = '('\n").unwrap();
+        assert_eq!(config.apply_file(&path).unwrap_err().error, "invalid_config");
+        fs::write(&path, "[redact.patterns]\nbad = '('\n").unwrap();
+        let response = validate_config_file(&path);
+        assert!(!response.valid);
+        assert!(response.errors[0].contains("redact.patterns.bad"));
+        fs::write(&path, "[defaults]\ntimezone = 'Mars/Base'\n").unwrap();
+        let response = validate_config_file(&path);
+        assert!(response.errors[0].contains("defaults.timezone"));
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+}
@@ -1,10 +1,14 @@
-use crate::config::Config;
+use crate::config::{Config, Defaults};
 use crate::get::find_session_file;
+use crate::query::{MessageFields, Query};
+use crate::reader::SessionReader;
+use crate::redact::Redactor;
+use crate::subagents::{TaskCalls, subagent_link, subagents_dir};
+use crate::thread::SessionThread;
 use crate::types::*;
 use crate::utils::*;
 use regex::{Regex, RegexBuilder};
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::collections::HashMap;
 
 /// Context 参数
 pub struct ContextParams {
@@ -13,31 +17,41 @@ pub struct ContextParams {
     pub after: Option<usize>,
     pub until_type: Option<String>,
     pub direction: String,
+    /// 消息顺序：file = 文件行序（默认），parent = 沿 parentUuid 链（跳过废弃分支）
+    pub follow: String,
     pub project: Option<String>,
     pub types: Vec<String>,
     pub max_content: usize,
     pub max_total: usize,
-    /// 内容过滤 pattern（before/after 只计数匹配的消息）
+    /// 内容过滤（查询语法同 search；regex=true 时为正则），before/after 只计数匹配的消息
     pub pattern: Option<String>,
     pub regex: bool,
     pub case_sensitive: bool,
+    /// 消息内容附带 `[THINKING]` 段，pattern 也匹配思考内容
+    pub include_thinking: bool,
+    /// 返回前脱敏（先于截断进行）
+    pub redactor: Option<Redactor>,
 }
 
 impl Default for ContextParams {
     fn default() -> Self {
+        let defaults = Defaults::default();
         Self {
             r#ref: String::new(),
             before: None,
             after: None,
             until_type: None,
             direction: "forward".to_string(),
+            follow: "file".to_string(),
             project: None,
             types: vec![],
-            max_content: 4000,
-            max_total: 40000,
+            max_content: defaults.max_content,
+            max_total: defaults.max_total,
             pattern: None,
             regex: false,
             case_sensitive: false,
+            include_thinking: false,
+            redactor: None,
         }
     }
 }
@@ -48,6 +62,14 @@ struct ClassifiedMessage {
     effective_type: &'static str,
     subtype: &'static str,
     content: String,
+    /// 仅当查询含字段条件时提取
+    fields: Option<MessageFields>,
+}
+
+/// 内容过滤条件
+enum ContentFilter {
+    Regex(Regex),
+    Query(Query),
 }
 
 /// 检查消息类型是否匹配
@@ -55,57 +77,47 @@ fn matches_types(effective_type: &str, types: &[String]) -> bool {
     types.is_empty() || types.iter().any(|t| t == effective_type)
 }
 
-/// 检查消息内容是否匹配 pattern
-fn matches_pattern(
-    content: &str,
-    pattern: &Option<Regex>,
-    plain_pattern: &Option<String>,
-    case_sensitive: bool,
-) -> bool {
-    if let Some(re) = pattern {
-        return re.is_match(content);
-    }
-    if let Some(p) = plain_pattern {
-        if case_sensitive {
-            return content.contains(p.as_str());
-        }
-        // plain_pattern 在 case-insensitive 时已预先 to_lowercase，直接比较
-        return content.to_lowercase().contains(p.as_str());
+/// 检查消息是否匹配过滤条件（无过滤条件时匹配所有）
+fn matches_filter(msg: &ClassifiedMessage, filter: &Option<ContentFilter>) -> bool {
+    match filter {
+        Some(ContentFilter::Regex(re)) => re.is_match(&msg.content),
+        Some(ContentFilter::Query(query)) => query.matches(&msg.content, msg.fields.as_ref()).0,
+        None => true,
     }
-    true // 无 pattern 时匹配所有
 }
 
 /// 获取上下文
 pub fn context(config: &Config, params: ContextParams) -> Result<ContextResponse, ErrorResponse> {
-    // 编译 pattern
-    let compiled_regex: Option<Regex> = if let Some(ref pat) = params.pattern {
-        if params.regex {
-            match RegexBuilder::new(pat).case_insensitive(!params.case_sensitive).build() {
-                Ok(r) => Some(r),
-                Err(e) => {
-                    return Err(ErrorResponse {
-                        error: "invalid_regex".to_string(),
-                        message: format!("无效的正则表达式: {}", e),
-                        available: None,
-                    });
-                }
-            }
-        } else {
-            None
+    let follow_parent = match params.follow.as_str() {
+        "file" => false,
+        "parent" => true,
+        other => {
+            return Err(ErrorResponse {
+                error: "invalid_follow".to_string(),
+                message: format!("无效的 follow: {}，可选 file / parent", other),
+                available: None,
+            });
         }
-    } else {
-        None
     };
-    // case-insensitive 时预先 to_lowercase，避免每次调用 matches_pattern 时重复分配
-    let plain_pattern: Option<String> = if params.pattern.is_some() && !params.regex {
-        if params.case_sensitive {
-            params.pattern.clone()
-        } else {
-            params.pattern.as_ref().map(|p| p.to_lowercase())
-        }
-    } else {
-        None
+
+    // 编译 pattern：正则或查询语法（与 search 共用解析器）
+    let filter = match params.pattern.as_deref() {
+        Some(pat) if params.regex => match RegexBuilder::new(pat).case_insensitive(!params.case_sensitive).build() {
+            Ok(r) => Some(ContentFilter::Regex(r)),
+            Err(e) => {
+                return Err(ErrorResponse {
+                    error: "invalid_regex".to_string(),
+                    message: format!("无效的正则表达式: {}", e),
+                    available: None,
+                });
+            }
+        },
+        Some(pat) => Some(Query::parse(pat, params.case_sensitive, config.defaults.timezone)?)
+            .filter(|q| !q.is_empty())
+            .map(ContentFilter::Query),
+        None => None,
     };
+    let needs_fields = matches!(&filter, Some(ContentFilter::Query(q)) if q.has_fields());
     // 解析 ref
     let parsed_ref = ParsedRef::parse(&params.r#ref).ok_or_else(|| ErrorResponse {
         error: "ref_invalid".to_string(),
@@ -118,51 +130,65 @@ pub fn context(config: &Config, params: ContextParams) -> Result<ContextResponse
         find_session_file(config, &parsed_ref.session_prefix, params.project.as_deref())?;
 
     // 读取文件
-    let file = File::open(&path).map_err(|e| ErrorResponse {
+    let reader = SessionReader::open(&path).map_err(|e| ErrorResponse {
         error: "io_error".to_string(),
         message: format!("无法打开文件: {}", e),
         available: None,
     })?;
-
-    let reader = BufReader::new(file);
     let prefix = ref_prefix(&session_id);
 
     // 收集所有消息（带分类信息）
     let mut all_messages: Vec<ClassifiedMessage> = Vec::new();
+    let mut records: Vec<MessageRecord> = Vec::new();
     let mut anchor_idx = None;
+    let mut task_calls = TaskCalls::default();
 
-    for (line_num, line) in reader.lines().enumerate() {
-        let line_num = line_num + 1; // 1-based
-        let line = match line {
-            Ok(l) => l,
-            Err(_) => continue,
-        };
-
-        let record: MessageRecord = match serde_json::from_str(&line) {
-            Ok(r) => r,
-            Err(_) => continue,
-        };
-
-        let (effective_type, subtype) = classify_message(&record);
-        let content = replace_images_with_placeholders(&record);
+    for (line_num, record) in reader {
+        task_calls.observe(line_num, &record);
+        let (effective_type, subtype) = record.classify();
+        let (content, _) = render_content(&record, params.include_thinking);
         all_messages.push(ClassifiedMessage {
             line_num,
             effective_type,
             subtype,
             content,
+            fields: needs_fields.then(|| MessageFields::from_record(&record, effective_type, subtype)),
         });
 
         if line_num == parsed_ref.line {
             anchor_idx = Some(all_messages.len() - 1);
         }
+        if follow_parent {
+            records.push(record);
+        }
     }
 
-    let anchor_idx = anchor_idx.ok_or_else(|| ErrorResponse {
+    let mut anchor_idx = anchor_idx.ok_or_else(|| ErrorResponse {
         error: "ref_not_found".to_string(),
         message: format!("ref 不存在: {}", params.r#ref),
         available: None,
     })?;
 
+    // subagent 跳转：父会话中的 Task 调用及结果指向子会话，子会话指回 Task 调用
+    let (parent_ref, subagent_refs) = if session_id.starts_with("agent-") {
+        (
+            subagent_link(&path, &session_id).and_then(|link| link.parent_ref),
+            HashMap::new(),
+        )
+    } else {
+        let links = task_calls.link(&session_id, &subagents_dir(&path, &session_id));
+        (None, task_calls.jump_refs(&links))
+    };
+
+    // follow=parent：按真实父子链（祖先 → anchor → 活动/最新分支上的后代）重排，替代文件行序
+    if follow_parent {
+        let record_refs: Vec<&MessageRecord> = records.iter().collect();
+        let order = SessionThread::build(&record_refs).lineage(anchor_idx);
+        let mut slots: Vec<Option<ClassifiedMessage>> = all_messages.into_iter().map(Some).collect();
+        all_messages = order.iter().filter_map(|&i| slots[i].take()).collect();
+        anchor_idx = order.iter().position(|&i| i == anchor_idx).unwrap_or(0);
+    }
+
     // 确定上下文范围
     let (start_idx, end_idx) = if let Some(until_type) = &params.until_type {
         // until_type 模式：遇到指定类型就停止
@@ -195,12 +221,7 @@ pub fn context(config: &Config, params: ContextParams) -> Result<ContextResponse
             let mut count = 0;
             for i in (0..anchor_idx).rev() {
                 let type_ok = matches_types(all_messages[i].effective_type, &params.types);
-                let pattern_ok = matches_pattern(
-                    &all_messages[i].content,
-                    &compiled_regex,
-                    &plain_pattern,
-                    params.case_sensitive,
-                );
+                let pattern_ok = matches_filter(&all_messages[i], &filter);
                 if type_ok && pattern_ok {
                     count += 1;
                     start = i;
@@ -216,7 +237,7 @@ pub fn context(config: &Config, params: ContextParams) -> Result<ContextResponse
             let mut count = 0;
             for (i, msg) in all_messages.iter().enumerate().skip(anchor_idx + 1) {
                 let type_ok = matches_types(msg.effective_type, &params.types);
-                let pattern_ok = matches_pattern(&msg.content, &compiled_regex, &plain_pattern, params.case_sensitive);
+                let pattern_ok = matches_filter(msg, &filter);
                 if type_ok && pattern_ok {
                     count += 1;
                     end = i + 1;
@@ -234,6 +255,7 @@ pub fn context(config: &Config, params: ContextParams) -> Result<ContextResponse
     let mut messages = Vec::new();
     let mut total_chars = 0;
     let mut truncated_by_total = false;
+    let mut redactions = 0;
 
     for (i, msg) in all_messages.iter().enumerate().take(end_idx).skip(start_idx) {
         let is_anchor = i == anchor_idx;
@@ -241,14 +263,15 @@ pub fn context(config: &Config, params: ContextParams) -> Result<ContextResponse
             continue;
         }
         // pattern 过滤（anchor 消息始终保留）
-        let has_pattern_filter = compiled_regex.is_some() || plain_pattern.is_some();
-        let pattern_ok = !has_pattern_filter
-            || matches_pattern(&msg.content, &compiled_regex, &plain_pattern, params.case_sensitive);
-        if !is_anchor && !pattern_ok {
+        if !is_anchor && !matches_filter(msg, &filter) {
             continue;
         }
 
-        let (truncated_content, _) = truncate_content(&msg.content, params.max_content);
+        let mut content = msg.content.clone();
+        if let Some(redactor) = &params.redactor {
+            redactions += redactor.redact(&mut content);
+        }
+        let (truncated_content, _) = truncate_content(&content, params.max_content);
         let truncated_len = truncated_content.chars().count();
 
         if total_chars + truncated_len > params.max_total {
@@ -263,12 +286,16 @@ pub fn context(config: &Config, params: ContextParams) -> Result<ContextResponse
             subtype: msg.subtype.to_string(),
             content: truncated_content,
             is_anchor: if is_anchor { Some(true) } else { None },
+            subagent_ref: subagent_refs.get(&msg.line_num).cloned(),
         });
     }
 
     Ok(ContextResponse {
         anchor_ref: params.r#ref,
+        root: config.root_label(&path),
+        parent_ref,
         messages,
         truncated: if truncated_by_total { Some(true) } else { None },
+        redactions: params.redactor.as_ref().map(|_| redactions),
     })
 }
@@ -0,0 +1,189 @@
+//! 行级 unified diff（Myers 算法）
+//!
+//! 先去掉公共前后缀再求最短编辑脚本，输出与 `diff -u` 相同的 hunk 格式（默认 3 行上下文）
+
+/// 每个 hunk 前后保留的上下文行数
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// 生成 unified diff；内容相同时返回空字符串
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let script = edit_script(&a, &b);
+    if script.iter().all(|(e, _)| *e == Edit::Equal) {
+        return String::new();
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_label, new_label);
+    // 每个位置对应的 (a 行号, b 行号)，均为 0 起始、指向该操作之前
+    let mut positions = Vec::with_capacity(script.len());
+    let (mut ai, mut bi) = (0, 0);
+    for (edit, _) in &script {
+        positions.push((ai, bi));
+        match edit {
+            Edit::Equal => {
+                ai += 1;
+                bi += 1;
+            }
+            Edit::Delete => ai += 1,
+            Edit::Insert => bi += 1,
+        }
+    }
+
+    let changes: Vec<usize> = (0..script.len()).filter(|&i| script[i].0 != Edit::Equal).collect();
+    let mut i = 0;
+    while i < changes.len() {
+        // 相邻改动之间的相同行不超过 2 * CONTEXT 时合并为一个 hunk
+        let mut j = i;
+        while j + 1 < changes.len() && changes[j + 1] - changes[j] <= 2 * CONTEXT + 1 {
+            j += 1;
+        }
+        let start = changes[i].saturating_sub(CONTEXT);
+        let end = (changes[j] + CONTEXT + 1).min(script.len());
+        let hunk = &script[start..end];
+
+        let old_len = hunk.iter().filter(|(e, _)| *e != Edit::Insert).count();
+        let new_len = hunk.iter().filter(|(e, _)| *e != Edit::Delete).count();
+        let (old_start, new_start) = positions[start];
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_len),
+            hunk_range(new_start, new_len)
+        ));
+        for (edit, line) in hunk {
+            let sign = match edit {
+                Edit::Equal => ' ',
+                Edit::Delete => '-',
+                Edit::Insert => '+',
+            };
+            out.push(sign);
+            out.push_str(line);
+            out.push('\n');
+        }
+        i = j + 1;
+    }
+    out
+}
+
+/// hunk 头中的 `start,len`；空区间按惯例写成前一行的行号
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+/// 最短编辑脚本：(操作, 行内容) 序列
+fn edit_script<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<(Edit, &'a str)> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (mid_a, mid_b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut script: Vec<(Edit, &str)> = a[..prefix].iter().map(|l| (Edit::Equal, *l)).collect();
+    script.extend(myers(mid_a, mid_b));
+    script.extend(a[a.len() - suffix..].iter().map(|l| (Edit::Equal, *l)));
+    script
+}
+
+/// Myers O((N+M)D)：记录每一步的 V 数组，再从终点回溯
+fn myers<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<(Edit, &'a str)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    let offset = max as isize;
+    let mut v = vec![0isize; 2 * max + 2];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max as isize {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut script = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[(k - 1 + offset) as usize] < v[(k + 1 + offset) as usize]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            script.push((Edit::Equal, a[(x - 1) as usize]));
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                script.push((Edit::Insert, b[(y - 1) as usize]));
+            } else {
+                script.push((Edit::Delete, a[(x - 1) as usize]));
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    script.reverse();
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "v1", "v2"), "");
+        assert_eq!(
+            unified_diff("", "x\ny\n", "v0", "v1"),
+            "--- v0\n+++ v1\n@@ -0,0 +1,2 @@\n+x\n+y\n"
+        );
+
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14\n15\n16\n";
+        let new = "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n15\n16\n17\n";
+        assert_eq!(
+            unified_diff(old, new, "a", "b"),
+            concat!(
+                "--- a\n+++ b\n",
+                "@@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n",
+                "@@ -11,6 +11,6 @@\n 11\n 12\n 13\n-14\n 15\n 16\n+17\n",
+            )
+        );
+
+        // 相隔不超过 2 * CONTEXT 行的改动合并为一个 hunk
+        let diff = unified_diff("a\nb\nc\nd\ne\nf\ng\nh\n", "A\nb\nc\nd\ne\nf\ng\nH\n", "a", "b");
+        assert_eq!(diff.matches("@@ -").count(), 1);
+        assert!(diff.contains("@@ -1,8 +1,8 @@\n-a\n+A\n"));
+    }
+}
@@ -0,0 +1,683 @@
+//! 会话导出：把整个 session（或行范围 / 对话链）渲染为 Markdown、自包含 HTML 或 JSON
+//!
+//! 输出目录沿用 get 的受控目录规则（resolve_output_dir），导出文件不能逃出允许的根目录
+
+use crate::config::Config;
+use crate::get::{create_output_dir, find_session_file, resolve_output_dir, write_private_file};
+use crate::model::{ContentBlock, ImageSource};
+use crate::redact::Redactor;
+use crate::thread::{SessionThread, load_records};
+use crate::types::*;
+use crate::utils::*;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// 导出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Markdown,
+    Html,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Result<Self, ErrorResponse> {
+        match s {
+            "markdown" | "md" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            "json" => Ok(Self::Json),
+            _ => Err(ErrorResponse {
+                error: "invalid_format".to_string(),
+                message: format!("无效的导出格式: {}", s),
+                available: Some(serde_json::json!(["markdown", "html", "json"])),
+            }),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Markdown => "markdown",
+            Self::Html => "html",
+            Self::Json => "json",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Html => "html",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// 图片处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageMode {
+    /// data URI 内联，导出文件自包含
+    #[default]
+    Inline,
+    /// 写到导出文件旁边，用相对文件名引用
+    Files,
+    /// 只保留占位
+    None,
+}
+
+impl ImageMode {
+    pub fn parse(s: &str) -> Result<Self, ErrorResponse> {
+        match s {
+            "inline" => Ok(Self::Inline),
+            "files" => Ok(Self::Files),
+            "none" => Ok(Self::None),
+            _ => Err(ErrorResponse {
+                error: "invalid_images".to_string(),
+                message: format!("无效的图片处理方式: {}", s),
+                available: Some(serde_json::json!(["inline", "files", "none"])),
+            }),
+        }
+    }
+}
+
+/// Export 参数：session 与 thread 二选一
+pub struct ExportParams {
+    /// session ID 或前缀，导出整个会话（可用 lines 限定范围）
+    pub session: Option<String>,
+    /// ref，导出以该消息结尾的对话链（按 parentUuid 回溯）
+    pub thread: Option<String>,
+    pub project: Option<String>,
+    pub format: ExportFormat,
+    pub images: ImageMode,
+    pub lines: Vec<Range>,
+    /// 导出的消息类型（空 = 除内部记录外全部）
+    pub types: Vec<String>,
+    /// 输出目录，规则同 get 的 output
+    pub output: String,
+    /// 脱敏器；作用于文本、工具输入和结果，图片数据不变
+    pub redactor: Option<Redactor>,
+    /// 导出思考块（redacted_thinking 为占位符）；默认不导出，只有思考的消息随之省略
+    pub include_thinking: bool,
+}
+
+impl Default for ExportParams {
+    fn default() -> Self {
+        Self {
+            session: None,
+            thread: None,
+            project: None,
+            format: ExportFormat::Markdown,
+            images: ImageMode::Inline,
+            lines: vec![],
+            types: vec![],
+            output: "exports".to_string(),
+            redactor: None,
+            include_thinking: false,
+        }
+    }
+}
+
+/// 消息中的一个内容块
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Block {
+    Text {
+        text: String,
+    },
+    Thinking {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        is_error: bool,
+    },
+    Image {
+        media_type: String,
+        /// data URI 或相对文件名；images=none 时为空
+        #[serde(skip_serializing_if = "Option::is_none")]
+        src: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct ExportMessage {
+    r#ref: String,
+    uuid: String,
+    r#type: String,
+    subtype: String,
+    timestamp: String,
+    /// 按配置时区渲染的 timestamp（时区为 UTC 时省略）；Markdown / HTML 显示它
+    #[serde(skip_serializing_if = "Option::is_none")]
+    local_timestamp: Option<String>,
+    blocks: Vec<Block>,
+}
+
+impl ExportMessage {
+    fn display_time(&self) -> &str {
+        self.local_timestamp.as_deref().unwrap_or(&self.timestamp)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ExportDocument {
+    session: String,
+    project: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thread: Option<String>,
+    messages: Vec<ExportMessage>,
+}
+
+impl ExportDocument {
+    fn redact(&mut self, redactor: &Redactor) -> usize {
+        let mut count = 0;
+        for block in self.messages.iter_mut().flat_map(|m| m.blocks.iter_mut()) {
+            count += match block {
+                Block::Text { text } | Block::Thinking { text } => redactor.redact(text),
+                Block::ToolUse { input, .. } => redactor.redact_value(input),
+                Block::ToolResult { content, .. } => redactor.redact(content),
+                Block::Image { .. } => 0,
+            };
+        }
+        count
+    }
+}
+
+/// 图片输出：按 ImageMode 生成 data URI 或写文件
+struct ImageSink<'a> {
+    mode: ImageMode,
+    dir: &'a Path,
+    count: usize,
+    files: Vec<PathBuf>,
+}
+
+impl ImageSink<'_> {
+    fn block(&mut self, source: &ImageSource, name: &str) -> Result<Option<Block>, ErrorResponse> {
+        if source.data.is_empty() {
+            return Ok(None);
+        }
+        let (media_type, data) = (source.media_type.clone(), &source.data);
+        self.count += 1;
+
+        let src = match self.mode {
+            ImageMode::Inline => Some(format!("data:{};base64,{}", media_type, data)),
+            ImageMode::Files => {
+                let Ok(bytes) = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, data) else {
+                    return Ok(Some(Block::Image { media_type, src: None }));
+                };
+                let file_name = format!("{}.{}", name, image_extension(&media_type));
+                let path = self.dir.join(&file_name);
+                write_private_file(&path, &bytes)?;
+                self.files.push(path);
+                Some(file_name)
+            }
+            ImageMode::None => None,
+        };
+        Ok(Some(Block::Image { media_type, src }))
+    }
+}
+
+/// 导出会话
+pub fn export(config: &Config, params: ExportParams) -> Result<ExportResponse, ErrorResponse> {
+    let output_dir = resolve_output_dir(config, &params.output)?;
+    create_output_dir(&output_dir)?;
+
+    let mut sink = ImageSink {
+        mode: params.images,
+        dir: &output_dir,
+        count: 0,
+        files: Vec::new(),
+    };
+    let (mut document, anchor) = build_document(config, &params, &mut sink)?;
+    let redactions = params.redactor.as_ref().map(|r| document.redact(r));
+    let rendered = match params.format {
+        ExportFormat::Markdown => render_markdown(&document),
+        ExportFormat::Html => render_html(&document),
+        ExportFormat::Json => serde_json::to_string_pretty(&document).unwrap_or_default(),
+    };
+
+    let ref_prefix = ref_prefix(&document.session);
+    let file_name = match anchor {
+        Some(line) => format!("{}_{}_thread.{}", ref_prefix, line, params.format.extension()),
+        None => format!("{}.{}", ref_prefix, params.format.extension()),
+    };
+    let file_path = output_dir.join(file_name);
+    write_private_file(&file_path, rendered.as_bytes())?;
+
+    Ok(ExportResponse {
+        session: document.session,
+        project: document.project,
+        format: params.format.name().to_string(),
+        path: file_path,
+        size: rendered.len(),
+        message_count: document.messages.len(),
+        image_count: sink.count,
+        image_files: sink.files,
+        redactions,
+    })
+}
+
+/// 整个会话渲染为 Markdown（不写文件，图片省略），供 MCP resource 使用
+pub fn session_markdown(
+    config: &Config,
+    session: &str,
+    project: Option<&str>,
+    redactor: Option<&Redactor>,
+) -> Result<String, ErrorResponse> {
+    let params = ExportParams {
+        session: Some(session.to_string()),
+        project: project.map(str::to_string),
+        images: ImageMode::None,
+        ..Default::default()
+    };
+    let mut sink = ImageSink {
+        mode: ImageMode::None,
+        dir: Path::new(""),
+        count: 0,
+        files: Vec::new(),
+    };
+    let (mut document, _) = build_document(config, &params, &mut sink)?;
+    if let Some(redactor) = redactor {
+        document.redact(redactor);
+    }
+    Ok(render_markdown(&document))
+}
+
+/// 选出要导出的消息并拆成内容块；返回文档和 thread 锚点行号
+fn build_document(
+    config: &Config,
+    params: &ExportParams,
+    sink: &mut ImageSink,
+) -> Result<(ExportDocument, Option<usize>), ErrorResponse> {
+    let (prefix, anchor) = match (&params.session, &params.thread) {
+        (Some(session), None) => (session.clone(), None),
+        (None, Some(r#ref)) => {
+            let parsed = ParsedRef::parse(r#ref).ok_or_else(|| ErrorResponse {
+                error: "ref_invalid".to_string(),
+                message: format!("无效的 ref 格式: {}", r#ref),
+                available: None,
+            })?;
+            (parsed.session_prefix, Some(parsed.line))
+        }
+        _ => {
+            return Err(ErrorResponse {
+                error: "invalid_params".to_string(),
+                message: "session 与 thread 必须且只能指定一个".to_string(),
+                available: None,
+            });
+        }
+    };
+
+    let (project_id, session_id, path) = find_session_file(config, &prefix, params.project.as_deref())?;
+    let records = load_records(&path)?;
+
+    // 选出要导出的记录（文件顺序，或对话链顺序）
+    let selected: Vec<usize> = match anchor {
+        Some(line) => {
+            let anchor_idx = records
+                .iter()
+                .position(|(l, _)| *l == line)
+                .ok_or_else(|| ErrorResponse {
+                    error: "ref_not_found".to_string(),
+                    message: format!("ref 不存在: {}", params.thread.as_deref().unwrap_or_default()),
+                    available: None,
+                })?;
+            let record_refs: Vec<&MessageRecord> = records.iter().map(|(_, r)| r).collect();
+            SessionThread::build(&record_refs).chain_to(anchor_idx)
+        }
+        None => (0..records.len()).collect(),
+    };
+
+    let ref_prefix = ref_prefix(&session_id);
+    let mut messages = Vec::new();
+    for idx in selected {
+        let (line, record) = &records[idx];
+        if !line_in_ranges(*line, &params.lines) {
+            continue;
+        }
+        let (effective_type, subtype) = record.classify();
+        let included = if params.types.is_empty() {
+            effective_type != "other"
+        } else {
+            params.types.iter().any(|t| t == effective_type)
+        };
+        if !included {
+            continue;
+        }
+        let r#ref = format!("{}:{}", ref_prefix, line);
+        let blocks = message_blocks(record, &r#ref.replace(':', "_"), params.include_thinking, sink)?;
+        if blocks.is_empty() {
+            continue;
+        }
+        messages.push(ExportMessage {
+            r#ref,
+            uuid: record.uuid.clone(),
+            r#type: effective_type.to_string(),
+            subtype: subtype.to_string(),
+            timestamp: record.timestamp.clone(),
+            local_timestamp: config.defaults.timezone.localize(&record.timestamp),
+            blocks,
+        });
+    }
+
+    let document = ExportDocument {
+        session: session_id,
+        project: project_id,
+        thread: params.thread.clone(),
+        messages,
+    };
+    Ok((document, anchor))
+}
+
+/// 把一条记录拆成内容块；图片名形如 `{prefix}_{line}_img{n}`
+fn message_blocks(
+    record: &MessageRecord,
+    name: &str,
+    include_thinking: bool,
+    sink: &mut ImageSink,
+) -> Result<Vec<Block>, ErrorResponse> {
+    let mut blocks = Vec::new();
+    if let Some(summary) = &record.summary {
+        blocks.push(Block::Text { text: summary.clone() });
+    }
+    let mut image_no = 0;
+    let mut image = |source: &ImageSource, sink: &mut ImageSink| {
+        image_no += 1;
+        sink.block(source, &format!("{}_img{}", name, image_no))
+    };
+    for block in record.blocks() {
+        match block {
+            ContentBlock::Text { text } => {
+                if !text.is_empty() {
+                    blocks.push(Block::Text { text: text.clone() });
+                }
+            }
+            ContentBlock::Thinking { thinking } => {
+                if include_thinking {
+                    blocks.push(Block::Thinking { text: thinking.clone() });
+                }
+            }
+            ContentBlock::RedactedThinking => {
+                if include_thinking {
+                    blocks.push(Block::Thinking {
+                        text: REDACTED_THINKING_PLACEHOLDER.to_string(),
+                    });
+                }
+            }
+            ContentBlock::ToolUse { id, name, input } => blocks.push(Block::ToolUse {
+                id: id.clone(),
+                name: name.clone(),
+                input: input.clone(),
+            }),
+            ContentBlock::ToolResult {
+                tool_use_id,
+                content,
+                is_error,
+            } => {
+                blocks.push(Block::ToolResult {
+                    tool_use_id: tool_use_id.clone(),
+                    content: extract_tool_result_texts(content).join("\n"),
+                    is_error: *is_error,
+                });
+                // tool_result 里嵌套的图片（如 Read 截图）紧跟在结果之后
+                for sub in content {
+                    if let ContentBlock::Image { source } = sub
+                        && let Some(block) = image(source, sink)?
+                    {
+                        blocks.push(block);
+                    }
+                }
+            }
+            ContentBlock::Image { source } => {
+                if let Some(block) = image(source, sink)? {
+                    blocks.push(block);
+                }
+            }
+            ContentBlock::Other => {}
+        }
+    }
+    Ok(blocks)
+}
+
+/// 消息标题中的角色名
+fn role_label(r#type: &str, subtype: &str) -> &'static str {
+    match (r#type, subtype) {
+        ("user", "tool_result") => "Tool result",
+        ("user", "meta") => "User (meta)",
+        ("user", _) => "User",
+        ("assistant", _) => "Assistant",
+        ("summary", _) => "Summary",
+        ("system", _) => "System",
+        _ => "Other",
+    }
+}
+
+/// 选择比内容中最长反引号串更长的 fence，保证代码块不被内容提前闭合
+fn fence(content: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in content.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+    "`".repeat((longest + 1).max(3))
+}
+
+fn render_markdown(doc: &ExportDocument) -> String {
+    let mut out = format!("# Session {}\n\n- Project: `{}`\n", doc.session, doc.project);
+    if let Some(thread) = &doc.thread {
+        out.push_str(&format!("- Thread: `{}`\n", thread));
+    }
+    out.push_str(&format!("- Messages: {}\n", doc.messages.len()));
+
+    for msg in &doc.messages {
+        out.push_str(&format!(
+            "\n---\n\n## {} · {} · `{}`\n",
+            role_label(&msg.r#type, &msg.subtype),
+            msg.display_time(),
+            msg.r#ref
+        ));
+        for block in &msg.blocks {
+            out.push('\n');
+            match block {
+                Block::Text { text } => out.push_str(&format!("{}\n", text)),
+                Block::Thinking { text } => out.push_str(&format!(
+                    "<details>\n<summary>Thinking</summary>\n\n{}\n\n</details>\n",
+                    text
+                )),
+                Block::ToolUse { name, input, .. } => {
+                    let input = serde_json::to_string_pretty(input).unwrap_or_default();
+                    let f = fence(&input);
+                    out.push_str(&format!(
+                        "<details>\n<summary>Tool: {}</summary>\n\n{}json\n{}\n{}\n\n</details>\n",
+                        name, f, input, f
+                    ));
+                }
+                Block::ToolResult { content, is_error, .. } => {
+                    let f = fence(content);
+                    let title = if *is_error { "Result (error)" } else { "Result" };
+                    out.push_str(&format!(
+                        "<details>\n<summary>{}</summary>\n\n{}\n{}\n{}\n\n</details>\n",
+                        title, f, content, f
+                    ));
+                }
+                Block::Image { src: Some(src), .. } => out.push_str(&format!("![image]({})\n", src)),
+                Block::Image { src: None, .. } => out.push_str("*[image omitted]*\n"),
+            }
+        }
+    }
+    out
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 文本块：``` 围起来的部分渲染为代码块，其余保留换行
+fn render_text_html(text: &str) -> String {
+    let mut out = String::new();
+    let mut prose = Vec::new();
+    let mut code: Option<Vec<&str>> = None;
+    for line in text.lines() {
+        let is_fence = line.trim_start().starts_with("```");
+        match code.as_mut() {
+            Some(lines) if is_fence => {
+                out.push_str(&format!("<pre><code>{}</code></pre>\n", escape_html(&lines.join("\n"))));
+                code = None;
+            }
+            Some(lines) => lines.push(line),
+            None if is_fence => {
+                if !prose.is_empty() {
+                    out.push_str(&format!(
+                        "<div class=\"text\">{}</div>\n",
+                        escape_html(&prose.join("\n"))
+                    ));
+                    prose.clear();
+                }
+                code = Some(Vec::new());
+            }
+            None => prose.push(line),
+        }
+    }
+    // 未闭合的代码块按代码输出
+    if let Some(lines) = code {
+        out.push_str(&format!("<pre><code>{}</code></pre>\n", escape_html(&lines.join("\n"))));
+    }
+    if !prose.is_empty() {
+        out.push_str(&format!(
+            "<div class=\"text\">{}</div>\n",
+            escape_html(&prose.join("\n"))
+        ));
+    }
+    out
+}
+
+const HTML_STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:960px;margin:2em auto;padding:0 1em;\
+color:#1f2328}header.doc{border-bottom:1px solid #d0d7de;margin-bottom:1em}.msg{border:1px solid #d0d7de;\
+border-radius:6px;margin:1em 0;padding:.5em 1em}.msg.user{background:#f6f8fa}.msg>header{font-size:.85em;\
+color:#57606a;margin-bottom:.5em}.role{font-weight:600;color:#1f2328}.text{white-space:pre-wrap}\
+pre{background:#f6f8fa;padding:.75em;overflow-x:auto;border-radius:4px}details{margin:.5em 0}\
+summary{cursor:pointer;color:#57606a}details.error summary{color:#cf222e}img{max-width:100%}";
+
+fn render_html(doc: &ExportDocument) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Session {}</title>\n<style>{}</style>\n</head>\n<body>\n",
+        escape_html(&doc.session),
+        HTML_STYLE
+    );
+    out.push_str(&format!(
+        "<header class=\"doc\"><h1>Session {}</h1><p>Project <code>{}</code>{} · {} messages</p></header>\n",
+        escape_html(&doc.session),
+        escape_html(&doc.project),
+        doc.thread
+            .as_ref()
+            .map(|t| format!(" · thread <code>{}</code>", escape_html(t)))
+            .unwrap_or_default(),
+        doc.messages.len()
+    ));
+
+    for msg in &doc.messages {
+        out.push_str(&format!(
+            "<section class=\"msg {}\">\n<header><span class=\"role\">{}</span> · <time>{}</time> · <code>{}</code></header>\n",
+            escape_html(&msg.r#type),
+            role_label(&msg.r#type, &msg.subtype),
+            escape_html(msg.display_time()),
+            escape_html(&msg.r#ref)
+        ));
+        for block in &msg.blocks {
+            match block {
+                Block::Text { text } => out.push_str(&render_text_html(text)),
+                Block::Thinking { text } => out.push_str(&format!(
+                    "<details class=\"thinking\"><summary>Thinking</summary>\n{}</details>\n",
+                    render_text_html(text)
+                )),
+                Block::ToolUse { name, input, .. } => out.push_str(&format!(
+                    "<details class=\"tool\"><summary>Tool: {}</summary>\n<pre><code>{}</code></pre></details>\n",
+                    escape_html(name),
+                    escape_html(&serde_json::to_string_pretty(input).unwrap_or_default())
+                )),
+                Block::ToolResult { content, is_error, .. } => out.push_str(&format!(
+                    "<details class=\"{}\"><summary>{}</summary>\n<pre>{}</pre></details>\n",
+                    if *is_error { "result error" } else { "result" },
+                    if *is_error { "Result (error)" } else { "Result" },
+                    escape_html(content)
+                )),
+                Block::Image { src: Some(src), .. } => {
+                    out.push_str(&format!("<img src=\"{}\" alt=\"image\">\n", escape_html(src)))
+                }
+                Block::Image { src: None, .. } => out.push_str("<p><em>[image omitted]</em></p>\n"),
+            }
+        }
+        out.push_str("</section>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_blocks() {
+        let record: MessageRecord = serde_json::from_value(serde_json::json!({
+            "type": "assistant",
+            "uuid": "u1",
+            "timestamp": "2026-05-01T00:00:00Z",
+            "message": {"content": [
+                {"type": "thinking", "thinking": "plan"},
+                {"type": "text", "text": "see:\n```rust\nlet a = 1 < 2;\n```"},
+                {"type": "tool_use", "id": "t1", "name": "Bash", "input": {"command": "echo ```"}},
+                {"type": "image", "source": {"media_type": "image/png", "data": "AAAA"}},
+            ]},
+        }))
+        .unwrap();
+        let dir = std::env::temp_dir();
+        let mut sink = ImageSink {
+            mode: ImageMode::None,
+            dir: &dir,
+            count: 0,
+            files: Vec::new(),
+        };
+        // 默认不导出思考块
+        assert_eq!(message_blocks(&record, "aaaa_1", false, &mut sink).unwrap().len(), 3);
+        let blocks = message_blocks(&record, "aaaa_1", true, &mut sink).unwrap();
+        assert_eq!(blocks.len(), 4);
+        assert_eq!(sink.count, 2);
+
+        let doc = ExportDocument {
+            session: "aaaa".to_string(),
+            project: "p".to_string(),
+            thread: None,
+            messages: vec![ExportMessage {
+                r#ref: "aaaa:1".to_string(),
+                uuid: "u1".to_string(),
+                r#type: "assistant".to_string(),
+                subtype: "text".to_string(),
+                timestamp: record.timestamp.clone(),
+                local_timestamp: crate::time::TimeZone::parse("+08:00")
+                    .unwrap()
+                    .localize(&record.timestamp),
+                blocks,
+            }],
+        };
+
+        let md = render_markdown(&doc);
+        assert!(md.contains("<summary>Thinking</summary>"));
+        // 输入里有 ``` 时 fence 需要更长
+        assert!(md.contains("````json"));
+        assert!(md.contains("*[image omitted]*"));
+        assert!(md.contains(" · 2026-05-01T08:00:00+08:00 · "));
+
+        let html = render_html(&doc);
+        assert!(html.contains("<pre><code>let a = 1 &lt; 2;</code></pre>"));
+        assert!(html.contains("<summary>Tool: Bash</summary>"));
+    }
+}
@@ -0,0 +1,531 @@
+//! 文件版本重建：按时间重放 Write / Edit / MultiEdit 工具调用，恢复文件在会话中的每个中间版本
+//!
+//! Write 记录了完整内容，Edit 记录了 old_string / new_string，因此只要有一个已知的起点即可逐步重放。
+//! 起点来自 Write、完整读取的 Read 结果（toolUseResult.file），或 Edit / Write 结果中的 originalFile；
+//! 这些观察到的内容与重放结果不一致时（文件在工具之外被改动，如 git checkout）记为一个 snapshot 版本
+//!
+//! 失败（is_error）、没有结果（被中断）的调用不生效；old_string 找不到记为 conflict，起点未知记为 no_base，
+//! 都不改变当前内容
+
+use crate::config::{Config, Defaults};
+use crate::diff::unified_diff;
+use crate::files::{PathMatcher, touched_paths};
+use crate::model::ContentBlock;
+use crate::reader::SessionReader;
+use crate::search::{collect_jsonl_files, get_project_dirs};
+use crate::types::*;
+use crate::utils::*;
+use rayon::prelude::*;
+use serde::Deserialize;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+/// 文件版本查询参数
+pub struct FileVersionsParams {
+    /// 文件路径或 glob（规则同 history_files），必须唯一确定一个文件
+    pub path: String,
+    pub projects: Vec<String>,
+    pub all_projects: bool,
+    pub sessions: Vec<String>,
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    pub until: Option<chrono::DateTime<chrono::Utc>>,
+    pub subagents: bool,
+    /// 只返回该版本的内容
+    pub version: Option<usize>,
+    /// 返回两个版本之间的 unified diff（版本 0 = 空文件），不返回内容
+    pub diff: Option<(usize, usize)>,
+    /// 返回内容的总字符数上限，超出后的版本只列元数据
+    pub max_total: usize,
+}
+
+impl Default for FileVersionsParams {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            projects: Vec::new(),
+            all_projects: false,
+            sessions: Vec::new(),
+            since: None,
+            until: None,
+            subagents: false,
+            version: None,
+            diff: None,
+            max_total: Defaults::default().file_versions_max_total,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StepKind {
+    Read,
+    Write,
+    Edit,
+    MultiEdit,
+}
+
+impl StepKind {
+    fn from_tool(name: &str) -> Option<Self> {
+        match name {
+            "Read" => Some(Self::Read),
+            "Write" => Some(Self::Write),
+            "Edit" => Some(Self::Edit),
+            "MultiEdit" => Some(Self::MultiEdit),
+            _ => None,
+        }
+    }
+
+    fn op(self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Edit => "edit",
+            Self::MultiEdit => "multi_edit",
+        }
+    }
+}
+
+/// 一次涉及目标文件的工具调用
+struct Step {
+    session: String,
+    line: usize,
+    block: usize,
+    r#ref: String,
+    timestamp: String,
+    path: String,
+    kind: StepKind,
+    tool: String,
+    tool_use_id: String,
+    input: Value,
+}
+
+/// 工具调用的结果
+struct Outcome {
+    is_error: bool,
+    /// 记录级的 toolUseResult（Read 的 file.content、Edit / Write 的 originalFile 等）
+    result: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct ToolUseResultLine {
+    #[serde(rename = "toolUseResult", default)]
+    tool_use_result: Option<Value>,
+}
+
+/// 重建文件版本
+pub fn file_versions(config: &Config, params: FileVersionsParams) -> Result<FileVersionsResponse, ErrorResponse> {
+    let matcher = PathMatcher::parse(&params.path)?;
+    let project_dirs = get_project_dirs(config, &params.projects, params.all_projects)?;
+    let jsonl_files = collect_jsonl_files(&project_dirs, &params.sessions, params.subagents);
+
+    let scanned: Vec<(Vec<Step>, HashMap<String, Outcome>)> = jsonl_files
+        .par_iter()
+        .map(|(_, session_id, path)| scan_steps(session_id, path, &matcher))
+        .collect();
+    let mut steps = Vec::new();
+    let mut outcomes = HashMap::new();
+    for (file_steps, file_outcomes) in scanned {
+        steps.extend(file_steps);
+        outcomes.extend(file_outcomes);
+    }
+    steps.retain(|s| time_in_range(&s.timestamp, params.since.as_ref(), params.until.as_ref()));
+
+    let paths: BTreeSet<&str> = steps.iter().map(|s| s.path.as_str()).collect();
+    let path = match paths.len() {
+        0 => {
+            return Err(ErrorResponse {
+                error: "file_not_found".to_string(),
+                message: format!("历史中没有对该路径的 Read / Write / Edit 记录: {}", params.path),
+                available: None,
+            });
+        }
+        1 => paths.first().map(|p| p.to_string()).unwrap_or_default(),
+        _ => {
+            return Err(ErrorResponse {
+                error: "ambiguous_path".to_string(),
+                message: format!("路径匹配到多个文件，请指定更完整的路径: {}", params.path),
+                available: Some(serde_json::json!(paths)),
+            });
+        }
+    };
+
+    steps.sort_by(|a, b| {
+        a.timestamp
+            .cmp(&b.timestamp)
+            .then_with(|| a.session.cmp(&b.session))
+            .then_with(|| a.line.cmp(&b.line))
+            .then_with(|| a.block.cmp(&b.block))
+    });
+    let mut sessions: Vec<String> = Vec::new();
+    for step in &steps {
+        if !sessions.contains(&step.session) {
+            sessions.push(step.session.clone());
+        }
+    }
+
+    let (mut versions, contents) = replay(&steps, &outcomes);
+    let version_count = contents.len();
+
+    let mut response = FileVersionsResponse {
+        path,
+        sessions,
+        version_count,
+        versions: Vec::new(),
+        diff: None,
+        truncated: None,
+        redactions: None,
+    };
+
+    let check_version = |v: usize| {
+        if v > version_count {
+            Err(ErrorResponse {
+                error: "invalid_version".to_string(),
+                message: format!("版本 {} 不存在（共 {} 个版本）", v, version_count),
+                available: None,
+            })
+        } else {
+            Ok(())
+        }
+    };
+
+    if let Some((from, to)) = params.diff {
+        check_version(from)?;
+        check_version(to)?;
+        let content_of = |v: usize| if v == 0 { "" } else { contents[v - 1].as_str() };
+        let label = |v: usize| match v {
+            0 => "/dev/null".to_string(),
+            v => format!("{} (v{})", response.path, v),
+        };
+        response.diff = Some(unified_diff(content_of(from), content_of(to), &label(from), &label(to)));
+    } else if let Some(v) = params.version {
+        check_version(v)?;
+        if v == 0 {
+            return Err(ErrorResponse {
+                error: "invalid_version".to_string(),
+                message: "版本号从 1 开始".to_string(),
+                available: None,
+            });
+        }
+        if let Some(entry) = versions.iter_mut().find(|e| e.version == Some(v)) {
+            entry.content = Some(contents[v - 1].clone());
+        }
+    } else {
+        let mut total = 0;
+        for entry in versions.iter_mut() {
+            let Some(v) = entry.version else {
+                continue;
+            };
+            let size = contents[v - 1].chars().count();
+            if total + size > params.max_total && total > 0 {
+                response.truncated = Some(true);
+                break;
+            }
+            total += size;
+            entry.content = Some(contents[v - 1].clone());
+        }
+    }
+
+    response.versions = versions;
+    Ok(response)
+}
+
+/// 扫描单个 session 文件：涉及目标路径的调用，以及所有工具结果（按 tool_use_id）
+fn scan_steps(session_id: &str, path: &Path, matcher: &PathMatcher) -> (Vec<Step>, HashMap<String, Outcome>) {
+    let mut steps = Vec::new();
+    let mut outcomes = HashMap::new();
+    let Ok(reader) = SessionReader::open(path) else {
+        return (steps, outcomes);
+    };
+    let prefix = ref_prefix(session_id);
+
+    // toolUseResult 不在消息模型中，只对含它的行单独解析一次
+    let pending: RefCell<Option<Value>> = RefCell::new(None);
+    let reader = reader.filter_lines(|_, line| {
+        if !line.contains("\"tool_use") {
+            return false;
+        }
+        *pending.borrow_mut() = if line.contains("\"toolUseResult\"") {
+            serde_json::from_str::<ToolUseResultLine>(line)
+                .ok()
+                .and_then(|l| l.tool_use_result)
+        } else {
+            None
+        };
+        true
+    });
+
+    for (line_num, record) in reader {
+        let tool_use_result = pending.borrow_mut().take();
+        for (block_idx, block) in record.blocks().iter().enumerate() {
+            match block {
+                ContentBlock::ToolUse { id, name, input } => {
+                    let Some(kind) = StepKind::from_tool(name) else {
+                        continue;
+                    };
+                    for (file_path, _) in touched_paths(name, input, record.cwd.as_deref()) {
+                        if !matcher.matches(&file_path) {
+                            continue;
+                        }
+                        steps.push(Step {
+                            session: session_id.to_string(),
+                            line: line_num,
+                            block: block_idx,
+                            r#ref: format!("{}:{}", prefix, line_num),
+                            timestamp: record.timestamp.clone(),
+                            path: file_path,
+                            kind,
+                            tool: name.clone(),
+                            tool_use_id: id.clone(),
+                            input: input.clone(),
+                        });
+                    }
+                }
+                ContentBlock::ToolResult {
+                    tool_use_id, is_error, ..
+                } => {
+                    outcomes.insert(
+                        tool_use_id.clone(),
+                        Outcome {
+                            is_error: *is_error,
+                            result: tool_use_result.clone(),
+                        },
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+    (steps, outcomes)
+}
+
+/// 按顺序重放，返回 (每一步的记录, 各版本内容)
+fn replay(steps: &[Step], outcomes: &HashMap<String, Outcome>) -> (Vec<FileVersion>, Vec<String>) {
+    let mut versions = Vec::new();
+    let mut contents: Vec<String> = Vec::new();
+    let mut current: Option<String> = None;
+
+    let entry = |step: &Step, op: &str, status: &str| FileVersion {
+        version: None,
+        r#ref: step.r#ref.clone(),
+        session: step.session.clone(),
+        op: op.to_string(),
+        tool: step.tool.clone(),
+        timestamp: step.timestamp.clone(),
+        status: status.to_string(),
+        size: None,
+        lines: None,
+        content: None,
+    };
+    // 生效的内容成为新版本
+    let mut commit = |mut version: FileVersion, content: String, current: &mut Option<String>| {
+        contents.push(content.clone());
+        version.version = Some(contents.len());
+        version.size = Some(content.chars().count());
+        version.lines = Some(content.lines().count());
+        *current = Some(content);
+        version
+    };
+
+    for step in steps {
+        let outcome = outcomes.get(&step.tool_use_id);
+        let observed = outcome
+            .filter(|o| !o.is_error)
+            .and_then(|o| observed_content(step.kind, o));
+        if let Some(observed) = observed
+            && !current.as_deref().is_some_and(|c| same_content(c, &observed))
+        {
+            versions.push(commit(entry(step, "snapshot", "applied"), observed, &mut current));
+        }
+        if step.kind == StepKind::Read {
+            continue;
+        }
+
+        let status = match outcome {
+            None => "no_result",
+            Some(o) if o.is_error => "failed",
+            Some(_) => match apply(step.kind, &step.input, current.as_deref()) {
+                Ok(content) => {
+                    versions.push(commit(entry(step, step.kind.op(), "applied"), content, &mut current));
+                    continue;
+                }
+                Err(status) => status,
+            },
+        };
+        versions.push(entry(step, step.kind.op(), status));
+    }
+    (versions, contents)
+}
+
+/// 工具结果中观察到的文件内容：完整读取的 Read，或 Edit / Write 的 originalFile（操作前的内容）
+fn observed_content(kind: StepKind, outcome: &Outcome) -> Option<String> {
+    let result = outcome.result.as_ref()?;
+    match kind {
+        StepKind::Read => {
+            let file = result.get("file")?;
+            let start_line = file.get("startLine").and_then(Value::as_u64).unwrap_or(1);
+            let num_lines = file.get("numLines").and_then(Value::as_u64)?;
+            let total_lines = file.get("totalLines").and_then(Value::as_u64)?;
+            // 分段读取无法代表整个文件
+            if start_line > 1 || num_lines < total_lines {
+                return None;
+            }
+            file.get("content").and_then(Value::as_str).map(str::to_string)
+        }
+        _ => result.get("originalFile").and_then(Value::as_str).map(str::to_string),
+    }
+}
+
+/// 忽略末尾换行的差异（Read 结果不保留文件末尾的换行）
+fn same_content(a: &str, b: &str) -> bool {
+    a.trim_end_matches('\n') == b.trim_end_matches('\n')
+}
+
+/// 对当前内容应用一次调用；失败返回状态
+fn apply(kind: StepKind, input: &Value, current: Option<&str>) -> Result<String, &'static str> {
+    match kind {
+        StepKind::Write => input
+            .get("content")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or("conflict"),
+        StepKind::Edit => apply_edit(current, input),
+        // MultiEdit 原子生效：任一处失败则整体不生效
+        StepKind::MultiEdit => {
+            let edits = input.get("edits").and_then(Value::as_array).ok_or("conflict")?;
+            let mut content = current.map(str::to_string);
+            for edit in edits {
+                content = Some(apply_edit(content.as_deref(), edit)?);
+            }
+            content.ok_or("no_base")
+        }
+        StepKind::Read => current.map(str::to_string).ok_or("no_base"),
+    }
+}
+
+fn apply_edit(current: Option<&str>, edit: &Value) -> Result<String, &'static str> {
+    let field = |key: &str| edit.get(key).and_then(Value::as_str).unwrap_or_default();
+    let (old, new) = (field("old_string"), field("new_string"));
+    let replace_all = edit.get("replace_all").and_then(Value::as_bool).unwrap_or(false);
+    match current {
+        // old_string 为空的 Edit 用于创建新文件
+        None if old.is_empty() => Ok(new.to_string()),
+        None => Err("no_base"),
+        Some(content) if old.is_empty() || !content.contains(old) => Err("conflict"),
+        Some(content) if replace_all => Ok(content.replace(old, new)),
+        Some(content) => Ok(content.replacen(old, new, 1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(line: usize, kind: StepKind, input: Value) -> Step {
+        Step {
+            session: "s1".to_string(),
+            line,
+            block: 0,
+            r#ref: format!("s1:{}", line),
+            timestamp: format!("2026-05-01T00:00:{:02}Z", line),
+            path: "/w/a.txt".to_string(),
+            kind,
+            tool: format!("{:?}", kind),
+            tool_use_id: format!("t{}", line),
+            input,
+        }
+    }
+
+    fn ok(result: Option<Value>) -> Outcome {
+        Outcome {
+            is_error: false,
+            result,
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let steps = vec![
+            // 起点未知的 Edit
+            step(
+                1,
+                StepKind::Edit,
+                serde_json::json!({"old_string": "x", "new_string": "y"}),
+            ),
+            step(2, StepKind::Write, serde_json::json!({"content": "one\ntwo\n"})),
+            step(
+                3,
+                StepKind::Edit,
+                serde_json::json!({"old_string": "two", "new_string": "TWO"}),
+            ),
+            step(
+                4,
+                StepKind::Edit,
+                serde_json::json!({"old_string": "missing", "new_string": "z"}),
+            ),
+            step(
+                5,
+                StepKind::Edit,
+                serde_json::json!({"old_string": "one", "new_string": "1"}),
+            ),
+            // 文件在工具之外被还原，originalFile 给出真实内容
+            step(
+                6,
+                StepKind::MultiEdit,
+                serde_json::json!({"edits": [
+                    {"old_string": "n", "new_string": "N", "replace_all": true},
+                    {"old_string": "two", "new_string": "2"},
+                ]}),
+            ),
+            // 完整 Read 与当前内容一致（仅差末尾换行）：不产生版本
+            step(7, StepKind::Read, serde_json::json!({})),
+            step(
+                8,
+                StepKind::Edit,
+                serde_json::json!({"old_string": "2", "new_string": "3"}),
+            ),
+        ];
+        let mut outcomes = HashMap::new();
+        outcomes.insert("t1".to_string(), ok(None));
+        outcomes.insert("t2".to_string(), ok(None));
+        outcomes.insert("t3".to_string(), ok(None));
+        outcomes.insert("t4".to_string(), ok(None));
+        outcomes.insert(
+            "t5".to_string(),
+            Outcome {
+                is_error: true,
+                result: None,
+            },
+        );
+        outcomes.insert(
+            "t6".to_string(),
+            ok(Some(serde_json::json!({"originalFile": "one\ntwo\n"}))),
+        );
+        outcomes.insert(
+            "t7".to_string(),
+            ok(Some(serde_json::json!({"file": {
+                "content": "oNe\n2", "numLines": 2, "startLine": 1, "totalLines": 2
+            }}))),
+        );
+
+        let (versions, contents) = replay(&steps, &outcomes);
+        let summary: Vec<(&str, &str, Option<usize>)> = versions
+            .iter()
+            .map(|v| (v.op.as_str(), v.status.as_str(), v.version))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("edit", "no_base", None),
+                ("write", "applied", Some(1)),
+                ("edit", "applied", Some(2)),
+                ("edit", "conflict", None),
+                ("edit", "failed", None),
+                ("snapshot", "applied", Some(3)),
+                ("multi_edit", "applied", Some(4)),
+                ("edit", "no_result", None),
+            ]
+        );
+        assert_eq!(contents, ["one\ntwo\n", "one\nTWO\n", "one\ntwo\n", "oNe\n2\n"]);
+    }
+}
@@ -0,0 +1,352 @@
+//! 文件触及视图：从 tool_use 输入中提取读写过的文件路径
+//!
+//! Read / Write / Edit / MultiEdit / NotebookEdit 取 file_path / notebook_path；Bash 从命令中挑出像路径的参数
+//! （绝对路径、`./` `../` `~/` 开头，或带扩展名的文件名）。相对路径按消息的 cwd 补全为绝对路径
+//!
+//! 既能回答"会话 X 动过哪些文件"（files 汇总），也能回答"哪些会话 / ref 动过路径 P"（touches 明细，path 支持 glob）
+
+use crate::config::Config;
+use crate::model::ContentBlock;
+use crate::reader::SessionReader;
+use crate::search::{collect_jsonl_files, get_project_dirs};
+use crate::types::*;
+use crate::utils::*;
+use glob::{MatchOptions, Pattern};
+use rayon::prelude::*;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+use std::time::Instant;
+
+/// 对文件的操作类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FileOp {
+    Read,
+    Write,
+    Edit,
+    /// Bash 命令中引用的路径（无法区分读写）
+    Bash,
+}
+
+impl FileOp {
+    pub fn parse(s: &str) -> Result<Self, ErrorResponse> {
+        match s {
+            "read" => Ok(Self::Read),
+            "write" => Ok(Self::Write),
+            "edit" => Ok(Self::Edit),
+            "bash" => Ok(Self::Bash),
+            _ => Err(ErrorResponse {
+                error: "invalid_op".to_string(),
+                message: format!("无效的操作类型: {}", s),
+                available: Some(serde_json::json!(["read", "write", "edit", "bash"])),
+            }),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Edit => "edit",
+            Self::Bash => "bash",
+        }
+    }
+}
+
+/// 文件查询参数
+pub struct FilesParams {
+    pub projects: Vec<String>,
+    pub all_projects: bool,
+    pub sessions: Vec<String>,
+    /// 路径 glob；绝对路径匹配完整路径，相对路径匹配路径末尾的若干段（`src/*.rs`、`*.toml`）
+    pub path: Option<String>,
+    /// 操作类型过滤（空 = 全部）
+    pub ops: Vec<FileOp>,
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    pub until: Option<chrono::DateTime<chrono::Utc>>,
+    pub subagents: bool,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+impl Default for FilesParams {
+    fn default() -> Self {
+        Self {
+            projects: Vec::new(),
+            all_projects: false,
+            sessions: Vec::new(),
+            path: None,
+            ops: Vec::new(),
+            since: None,
+            until: None,
+            subagents: false,
+            offset: 0,
+            limit: 100,
+        }
+    }
+}
+
+/// 编译后的路径 glob
+pub(crate) struct PathMatcher {
+    pattern: Pattern,
+    absolute: bool,
+}
+
+impl PathMatcher {
+    pub(crate) fn parse(raw: &str) -> Result<Self, ErrorResponse> {
+        let pattern = Pattern::new(raw.trim_start_matches("./")).map_err(|e| ErrorResponse {
+            error: "invalid_pattern".to_string(),
+            message: format!("无效的路径 glob: {}", e),
+            available: None,
+        })?;
+        Ok(Self {
+            pattern,
+            absolute: raw.starts_with('/'),
+        })
+    }
+
+    pub(crate) fn matches(&self, path: &str) -> bool {
+        // `*` 不跨越目录，`**` 跨越任意层
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        if self.absolute {
+            return self.pattern.matches_with(path, options);
+        }
+        // 相对 glob 与任意以 `/` 分隔的路径后缀比较
+        std::iter::once(path)
+            .chain(path.match_indices('/').map(|(i, _)| &path[i + 1..]))
+            .any(|suffix| self.pattern.matches_with(suffix, options))
+    }
+}
+
+/// 从单个 tool_use 提取 (路径, 操作)；cwd 用于补全相对路径
+pub fn touched_paths(name: &str, input: &Value, cwd: Option<&str>) -> Vec<(String, FileOp)> {
+    let field = |key: &str| input.get(key).and_then(Value::as_str).filter(|s| !s.is_empty());
+    let mut paths: Vec<(&str, FileOp)> = Vec::new();
+    match name {
+        "Read" => paths.extend(field("file_path").map(|p| (p, FileOp::Read))),
+        "Write" => paths.extend(field("file_path").map(|p| (p, FileOp::Write))),
+        "Edit" | "MultiEdit" => paths.extend(field("file_path").map(|p| (p, FileOp::Edit))),
+        "NotebookEdit" => paths.extend(field("notebook_path").map(|p| (p, FileOp::Edit))),
+        "Bash" => {
+            if let Some(command) = field("command") {
+                paths.extend(command_paths(command).into_iter().map(|p| (p, FileOp::Bash)));
+            }
+        }
+        _ => {}
+    }
+
+    let mut seen = BTreeSet::new();
+    paths
+        .into_iter()
+        .map(|(path, op)| (normalize_path(path, cwd), op))
+        .filter(|touch| seen.insert(touch.clone()))
+        .collect()
+}
+
+/// 从 shell 命令中挑出像文件路径的参数
+fn command_paths(command: &str) -> Vec<&str> {
+    command
+        .split(|c: char| c.is_whitespace() || matches!(c, ';' | '|' | '&' | '<' | '>' | '(' | ')'))
+        .map(|token| token.trim_matches(|c| matches!(c, '"' | '\'' | '`')))
+        .filter(|token| looks_like_path(token))
+        .collect()
+}
+
+fn looks_like_path(token: &str) -> bool {
+    if token.is_empty()
+        || token.starts_with('-')
+        || token.contains("://")
+        || token.contains(['$', '=', '*', '?', '{', '}', '\\'])
+    {
+        return false;
+    }
+    if token.starts_with('/') || token.starts_with("./") || token.starts_with("../") || token.starts_with("~/") {
+        return token.len() > 1;
+    }
+    // 其余只认带扩展名的文件名（扩展名含字母，排除 1.0.0、3.14 之类）
+    let file_name = token.rsplit('/').next().unwrap_or(token);
+    match file_name.rsplit_once('.') {
+        Some((stem, ext)) => {
+            !stem.is_empty()
+                && !ext.is_empty()
+                && ext.len() <= 10
+                && ext.chars().all(|c| c.is_ascii_alphanumeric())
+                && ext.chars().any(|c| c.is_ascii_alphabetic())
+        }
+        None => false,
+    }
+}
+
+/// 相对路径按 cwd 补全并去掉 `.` / `..`（纯字面处理，不访问文件系统）
+fn normalize_path(path: &str, cwd: Option<&str>) -> String {
+    let path = Path::new(path);
+    let joined = match cwd {
+        Some(cwd) if path.is_relative() && !path.starts_with("~") => Path::new(cwd).join(path),
+        _ => path.to_path_buf(),
+    };
+    let mut normalized = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized.to_string_lossy().to_string()
+}
+
+/// 查询文件触及记录
+pub fn files(config: &Config, params: FilesParams) -> Result<FilesResponse, ErrorResponse> {
+    let start = Instant::now();
+    let matcher = params.path.as_deref().map(PathMatcher::parse).transpose()?;
+
+    let project_dirs = get_project_dirs(config, &params.projects, params.all_projects)?;
+    let jsonl_files = collect_jsonl_files(&project_dirs, &params.sessions, params.subagents);
+
+    let file_results: Vec<Vec<FileTouch>> = jsonl_files
+        .par_iter()
+        .map(|(project_id, session_id, path)| scan_touches(project_id, session_id, path))
+        .collect();
+    let files_scanned = file_results.len();
+
+    let mut touches: Vec<FileTouch> = file_results
+        .into_iter()
+        .flatten()
+        .filter(|t| matcher.as_ref().is_none_or(|m| m.matches(&t.path)))
+        .filter(|t| params.ops.is_empty() || params.ops.iter().any(|op| op.name() == t.op))
+        .filter(|t| time_in_range(&t.timestamp, params.since.as_ref(), params.until.as_ref()))
+        .collect();
+    touches.sort_by(|a, b| {
+        a.timestamp
+            .cmp(&b.timestamp)
+            .then_with(|| a.session.cmp(&b.session))
+            .then_with(|| a.line.cmp(&b.line))
+    });
+
+    // 汇总覆盖全部命中（不受分页影响）
+    let mut by_path: BTreeMap<&str, FileSummary> = BTreeMap::new();
+    for touch in &touches {
+        let summary = by_path.entry(&touch.path).or_insert_with(|| FileSummary {
+            path: touch.path.clone(),
+            touches: 0,
+            ops: BTreeMap::new(),
+            sessions: Vec::new(),
+            first_timestamp: touch.timestamp.clone(),
+            last_timestamp: String::new(),
+            last_ref: String::new(),
+        });
+        summary.touches += 1;
+        *summary.ops.entry(touch.op.clone()).or_default() += 1;
+        if !summary.sessions.contains(&touch.session) {
+            summary.sessions.push(touch.session.clone());
+        }
+        summary.last_timestamp = touch.timestamp.clone();
+        summary.last_ref = touch.r#ref.clone();
+    }
+    let file_summaries: Vec<FileSummary> = by_path.into_values().collect();
+
+    let total_touches = touches.len();
+    let page: Vec<FileTouch> = touches.into_iter().skip(params.offset).take(params.limit).collect();
+    let returned_count = page.len();
+    Ok(FilesResponse {
+        stats: FilesStats {
+            files_scanned,
+            total_touches,
+            returned_count,
+            time_ms: start.elapsed().as_millis() as u64,
+        },
+        files: file_summaries,
+        touches: page,
+        has_more: params.offset + returned_count < total_touches,
+        next_offset: params.offset + returned_count,
+    })
+}
+
+/// 扫描单个 session 文件中的 tool_use
+fn scan_touches(project_id: &str, session_id: &str, path: &Path) -> Vec<FileTouch> {
+    let Ok(reader) = SessionReader::open(path) else {
+        return Vec::new();
+    };
+    let prefix = ref_prefix(session_id);
+
+    let mut touches = Vec::new();
+    // 快速预过滤，只解析含 tool_use 的行
+    for (line_num, record) in reader.filter_lines(|_, line| line.contains("\"tool_use\"")) {
+        for block in record.blocks() {
+            let ContentBlock::ToolUse { name, input, .. } = block else {
+                continue;
+            };
+            for (path, op) in touched_paths(name, input, record.cwd.as_deref()) {
+                touches.push(FileTouch {
+                    r#ref: format!("{}:{}", prefix, line_num),
+                    session: session_id.to_string(),
+                    project: project_id.to_string(),
+                    line: line_num,
+                    path,
+                    op: op.name().to_string(),
+                    tool: name.clone(),
+                    timestamp: record.timestamp.clone(),
+                });
+            }
+        }
+    }
+    touches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_touched_paths_and_glob() {
+        let cwd = Some("/work/app");
+        assert_eq!(
+            touched_paths("Edit", &serde_json::json!({"file_path": "/work/app/src/lib.rs"}), cwd),
+            [("/work/app/src/lib.rs".to_string(), FileOp::Edit)]
+        );
+        assert_eq!(
+            touched_paths("NotebookEdit", &serde_json::json!({"notebook_path": "nb/a.ipynb"}), cwd),
+            [("/work/app/nb/a.ipynb".to_string(), FileOp::Edit)]
+        );
+        assert!(touched_paths("Read", &serde_json::json!({}), cwd).is_empty());
+
+        let bash = serde_json::json!({
+            "command": "cargo fmt && sed -i 's/a/b/' ./src/main.rs ../other/Cargo.toml > /tmp/out.log; \
+                        git push origin/main -v 1.2.3 $HOME/x.rs https://x.io/a.rs src/*.rs"
+        });
+        let paths: Vec<String> = touched_paths("Bash", &bash, cwd).into_iter().map(|(p, _)| p).collect();
+        assert_eq!(
+            paths,
+            ["/work/app/src/main.rs", "/work/other/Cargo.toml", "/tmp/out.log"]
+        );
+
+        let m = PathMatcher::parse("src/*.rs").unwrap();
+        assert!(m.matches("/work/app/src/main.rs"));
+        assert!(!m.matches("/work/app/src/bin/cli.rs"));
+        assert!(!m.matches("/work/app/mysrc/main.rs"));
+        assert!(
+            PathMatcher::parse("src/**/*.rs")
+                .unwrap()
+                .matches("/work/app/src/bin/cli.rs")
+        );
+        assert!(PathMatcher::parse("*.toml").unwrap().matches("/work/other/Cargo.toml"));
+        assert!(
+            PathMatcher::parse("/work/*/Cargo.toml")
+                .unwrap()
+                .matches("/work/other/Cargo.toml")
+        );
+        assert!(
+            !PathMatcher::parse("/other/*")
+                .unwrap()
+                .matches("/work/other/Cargo.toml")
+        );
+        assert_eq!(FileOp::parse("delete").unwrap_err().error, "invalid_op");
+    }
+}
@@ -1,9 +1,11 @@
 use crate::config::Config;
+use crate::reader::SessionReader;
+use crate::redact::Redactor;
 use crate::types::*;
 use crate::utils::*;
 use std::env;
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader, Write};
+use std::io::Write;
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Component, Path, PathBuf};
@@ -12,7 +14,7 @@ const TMP_PATH_PREFIX: &str = "tmp:";
 const CWD_PATH_PREFIX: &str = "cwd:";
 
 #[cfg(unix)]
-fn set_private_permissions(path: &Path, mode: u32, target: &str) -> Result<(), ErrorResponse> {
+pub fn set_private_permissions(path: &Path, mode: u32, target: &str) -> Result<(), ErrorResponse> {
     fs::set_permissions(path, fs::Permissions::from_mode(mode)).map_err(|e| ErrorResponse {
         error: "io_error".to_string(),
         message: format!("无法设置{}权限: {}", target, e),
@@ -21,7 +23,7 @@ fn set_private_permissions(path: &Path, mode: u32, target: &str) -> Result<(), E
 }
 
 #[cfg(not(unix))]
-fn set_private_permissions(_path: &Path, _mode: u32, _target: &str) -> Result<(), ErrorResponse> {
+pub fn set_private_permissions(_path: &Path, _mode: u32, _target: &str) -> Result<(), ErrorResponse> {
     Ok(())
 }
 
@@ -31,66 +33,37 @@ pub struct GetParams {
     pub range: Option<(usize, usize)>,
     pub output: Option<String>,
     pub project: Option<String>,
+    /// 脱敏器；在 range 切片和写文件之前作用于完整内容
+    pub redactor: Option<Redactor>,
+    /// 在正文前附带 `[THINKING]` 段（range 按附带后的内容计算）
+    pub include_thinking: bool,
 }
 
 /// 获取完整内容
 pub fn get(config: &Config, params: GetParams) -> Result<GetResponse, ErrorResponse> {
-    // 解析 ref
-    let parsed_ref = ParsedRef::parse(&params.r#ref).ok_or_else(|| ErrorResponse {
-        error: "ref_invalid".to_string(),
-        message: format!("无效的 ref 格式: {}，应为 session前8位:行号", params.r#ref),
-        available: None,
-    })?;
-
-    // 查找 session 文件
-    let (_project_id, _session_id, path) =
-        find_session_file(config, &parsed_ref.session_prefix, params.project.as_deref())?;
-
-    // 读取指定行
-    let file = File::open(&path).map_err(|e| ErrorResponse {
-        error: "io_error".to_string(),
-        message: format!("无法打开文件: {}", e),
-        available: None,
-    })?;
-
-    let reader = BufReader::new(file);
-    let mut target_line = None;
-
-    for (line_num, line) in reader.lines().enumerate() {
-        if line_num + 1 == parsed_ref.line {
-            target_line = Some(line.map_err(|e| ErrorResponse {
-                error: "io_error".to_string(),
-                message: format!("读取行失败: {}", e),
-                available: None,
-            })?);
-            break;
-        }
-    }
-
-    let line = target_line.ok_or_else(|| ErrorResponse {
-        error: "ref_not_found".to_string(),
-        message: format!("ref 不存在: {}", params.r#ref),
-        available: None,
-    })?;
-
-    // 解析消息
-    let record: MessageRecord = serde_json::from_str(&line).map_err(|e| ErrorResponse {
-        error: "parse_error".to_string(),
-        message: format!("解析消息失败: {}", e),
-        available: None,
-    })?;
+    let (path, record) = read_record(config, &params.r#ref, params.project.as_deref())?;
+    let root = config.root_label(&path);
 
     // 提取内容和图片
-    let (effective_type, _) = classify_message(&record);
-    let content = replace_images_with_placeholders(&record);
+    let (effective_type, _) = record.classify();
+    let (mut content, _) = render_content(&record, params.include_thinking);
+    let redactions = params.redactor.as_ref().map(|r| r.redact(&mut content));
     let images = extract_images(&record);
     let image_count = images.len();
     let content_size = content.chars().count();
 
     // 如果指定了 output，写入文件
     if let Some(output_dir) = params.output {
-        let resolved_output = resolve_output_dir(&output_dir)?;
-        return write_output(&resolved_output, &params.r#ref, &record, &content, image_count);
+        let resolved_output = resolve_output_dir(config, &output_dir)?;
+        return write_output(
+            &resolved_output,
+            &params.r#ref,
+            &record,
+            &content,
+            image_count,
+            root,
+            redactions,
+        );
     }
 
     // 如果指定了 range，返回部分内容
@@ -120,17 +93,19 @@ pub fn get(config: &Config, params: GetParams) -> Result<GetResponse, ErrorRespo
             content: partial_content,
             content_size: partial_size,
             image_count,
+            root,
+            redactions,
         });
     }
 
     // 检查内容大小（字符数）
-    const MAX_DIRECT_SIZE: usize = 100_000;
-    if content_size > MAX_DIRECT_SIZE {
+    let max_direct_size = config.defaults.max_direct_size;
+    if content_size > max_direct_size {
         return Ok(GetResponse::TooLarge {
             error: "content_too_large".to_string(),
             r#ref: params.r#ref,
             size: content_size,
-            suggestion: format!("使用 --output 导出到文件，或用 --range 0-{} 分块获取", MAX_DIRECT_SIZE),
+            suggestion: format!("使用 --output 导出到文件，或用 --range 0-{} 分块获取", max_direct_size),
         });
     }
 
@@ -140,10 +115,54 @@ pub fn get(config: &Config, params: GetParams) -> Result<GetResponse, ErrorRespo
         content,
         content_size,
         image_count,
+        root,
+        redactions,
+    })
+}
+
+/// 按 ref 读取并解析单条消息记录，同时返回所在的会话文件
+pub fn read_record(
+    config: &Config,
+    r#ref: &str,
+    project: Option<&str>,
+) -> Result<(PathBuf, MessageRecord), ErrorResponse> {
+    // 解析 ref
+    let parsed_ref = ParsedRef::parse(r#ref).ok_or_else(|| ErrorResponse {
+        error: "ref_invalid".to_string(),
+        message: format!("无效的 ref 格式: {}，应为 session前8位:行号", r#ref),
+        available: None,
+    })?;
+
+    // 查找 session 文件
+    let (_project_id, _session_id, path) = find_session_file(config, &parsed_ref.session_prefix, project)?;
+
+    // 读取指定行（只解析目标行）
+    let reader = SessionReader::open(&path).map_err(|e| ErrorResponse {
+        error: "io_error".to_string(),
+        message: format!("无法打开文件: {}", e),
+        available: None,
+    })?;
+    let mut reader = reader.filter_lines(|line_num, _| line_num == parsed_ref.line);
+    if let Some((_, record)) = reader.next() {
+        return Ok((path, record));
+    }
+
+    // 目标行存在但无法解析，与行号越界区分开
+    if reader.malformed() > 0 {
+        return Err(ErrorResponse {
+            error: "parse_error".to_string(),
+            message: format!("解析消息失败: {} 不是有效的消息记录", r#ref),
+            available: None,
+        });
+    }
+    Err(ErrorResponse {
+        error: "ref_not_found".to_string(),
+        message: format!("ref 不存在: {}", r#ref),
+        available: None,
     })
 }
 
-/// 查找 session 文件
+/// 查找 session 文件；只在访问策略允许的项目中查找，其余项目的 session 视为不存在
 pub fn find_session_file(
     config: &Config,
     session_prefix: &str,
@@ -151,19 +170,19 @@ pub fn find_session_file(
 ) -> Result<(String, String, PathBuf), ErrorResponse> {
     // 确定要搜索的项目
     let project_dirs: Vec<(String, PathBuf)> = if let Some(pid) = project_id {
-        let dir = config.project_dir(pid)?;
-        if !dir.exists() {
+        let dirs = config.resolve_project(pid)?;
+        if !dirs.iter().all(|(_, dir)| dir.exists()) {
             return Err(ErrorResponse {
                 error: "project_not_found".to_string(),
                 message: format!("项目不存在: {}", pid),
                 available: None,
             });
         }
-        vec![(pid.to_string(), dir)]
+        dirs
     } else if let Some(pid) = config.current_project_id() {
-        // 优先搜当前项目（current_project_id 返回的 id 已通过 cwd 转码,可信）
-        match config.project_dir(&pid) {
-            Ok(dir) => vec![(pid.clone(), dir)],
+        // 优先搜当前项目（current_project_id 返回的 id 已通过 cwd 转码,可信），各根目录下的同名项目都算
+        match config.resolve_project(&pid) {
+            Ok(dirs) => dirs,
             Err(_) => config.list_project_dirs().unwrap_or_default(),
         }
     } else {
@@ -177,18 +196,18 @@ pub fn find_session_file(
 
     // 当前项目未找到时，fallback 到搜索所有项目
     // （search 返回的 ref 可能来自其他项目，用户未必传 project 参数）
-    if project_id.is_none() {
-        if let Ok(all_dirs) = config.list_project_dirs() {
-            // 排除已搜过的当前项目，避免重复扫描
-            let remaining: Vec<_> = all_dirs
-                .into_iter()
-                .filter(|(id, _)| !project_dirs.iter().any(|(pid, _)| pid == id))
-                .collect();
-            if !remaining.is_empty() {
-                if let Some(result) = search_session_in_dirs(&remaining, session_prefix) {
-                    return Ok(result);
-                }
-            }
+    if project_id.is_none()
+        && let Ok(all_dirs) = config.list_project_dirs()
+    {
+        // 排除已搜过的当前项目，避免重复扫描
+        let remaining: Vec<_> = all_dirs
+            .into_iter()
+            .filter(|(_, dir)| !project_dirs.iter().any(|(_, searched)| searched == dir))
+            .collect();
+        if !remaining.is_empty()
+            && let Some(result) = search_session_in_dirs(&remaining, session_prefix)
+        {
+            return Ok(result);
         }
     }
 
@@ -205,19 +224,16 @@ fn search_session_in_dirs(
     session_prefix: &str,
 ) -> Option<(String, String, PathBuf)> {
     for (project_id, dir) in project_dirs {
-        // 搜索主目录的 .jsonl 文件
+        // 搜索主目录的会话文件（含归档）
         if let Ok(entries) = fs::read_dir(dir) {
             for entry in entries.flatten() {
                 let path = entry.path();
-                if !path.extension().map(|e| e == "jsonl").unwrap_or(false) {
-                    continue;
-                }
-
                 let filename = entry.file_name().to_string_lossy().to_string();
-                if let Some(session_id) = session_id_from_filename(&filename) {
-                    if ref_prefix(&session_id) == session_prefix {
-                        return Some((project_id.clone(), session_id, path));
-                    }
+                if let Some(session_id) = session_id_from_filename(&filename)
+                    && ref_prefix(&session_id) == session_prefix
+                    && !shadowed_by_plain(&path, &session_id)
+                {
+                    return Some((project_id.clone(), session_id, path));
                 }
             }
         }
@@ -229,15 +245,13 @@ fn search_session_in_dirs(
                 if let Ok(sub_entries) = fs::read_dir(&subdir) {
                     for entry in sub_entries.flatten() {
                         let path = entry.path();
-                        if !path.extension().map(|e| e == "jsonl").unwrap_or(false) {
-                            continue;
-                        }
                         let filename = entry.file_name().to_string_lossy().to_string();
-                        if filename.starts_with("agent-") {
-                            let session_id = filename.strip_suffix(".jsonl").unwrap_or(&filename).to_string();
-                            if ref_prefix(&session_id) == session_prefix {
-                                return Some((project_id.clone(), session_id, path));
-                            }
+                        if let Some(session_id) = session_file_stem(&filename)
+                            && session_id.starts_with("agent-")
+                            && ref_prefix(session_id) == session_prefix
+                            && !shadowed_by_plain(&path, session_id)
+                        {
+                            return Some((project_id.clone(), session_id.to_string(), path));
                         }
                     }
                 }
@@ -249,14 +263,8 @@ fn search_session_in_dirs(
 }
 
 /// 写入输出文件
-fn write_output(
-    output_dir: &PathBuf,
-    r#ref: &str,
-    record: &MessageRecord,
-    content: &str,
-    image_count: usize,
-) -> Result<GetResponse, ErrorResponse> {
-    // 仅当目录是新建时才 chmod 0o700，避免 chmod 用户已有目录
+/// 创建输出目录；仅当目录是新建时才 chmod 0o700，避免 chmod 用户已有目录
+pub fn create_output_dir(output_dir: &Path) -> Result<(), ErrorResponse> {
     let was_new = !output_dir.exists();
     fs::create_dir_all(output_dir).map_err(|e| ErrorResponse {
         error: "io_error".to_string(),
@@ -266,22 +274,40 @@ fn write_output(
     if was_new {
         set_private_permissions(output_dir, 0o700, "目录")?;
     }
+    Ok(())
+}
 
-    let safe_ref = r#ref.replace(':', "_");
-
-    // 写入内容文件（mode 0o600）
-    let content_path = output_dir.join(format!("{}.txt", safe_ref));
-    let mut file = File::create(&content_path).map_err(|e| ErrorResponse {
+/// 以 0o600 权限写入文件（先 chmod 再写内容）
+pub fn write_private_file(path: &Path, data: &[u8]) -> Result<(), ErrorResponse> {
+    let mut file = File::create(path).map_err(|e| ErrorResponse {
         error: "io_error".to_string(),
         message: format!("无法创建文件: {}", e),
         available: None,
     })?;
-    set_private_permissions(&content_path, 0o600, "文件")?;
-    file.write_all(content.as_bytes()).map_err(|e| ErrorResponse {
+    set_private_permissions(path, 0o600, "文件")?;
+    file.write_all(data).map_err(|e| ErrorResponse {
         error: "io_error".to_string(),
         message: format!("写入文件失败: {}", e),
         available: None,
-    })?;
+    })
+}
+
+fn write_output(
+    output_dir: &Path,
+    r#ref: &str,
+    record: &MessageRecord,
+    content: &str,
+    image_count: usize,
+    root: Option<String>,
+    redactions: Option<usize>,
+) -> Result<GetResponse, ErrorResponse> {
+    create_output_dir(output_dir)?;
+
+    let safe_ref = r#ref.replace(':', "_");
+
+    // 写入内容文件（mode 0o600）
+    let content_path = output_dir.join(format!("{}.txt", safe_ref));
+    write_private_file(&content_path, content.as_bytes())?;
 
     // 导出图片（失败时记录 warning，不中断）
     let mut image_paths = Vec::new();
@@ -317,10 +343,14 @@ fn write_output(
         },
         content_size: content.chars().count(),
         image_count,
+        root,
+        redactions,
     })
 }
 
-fn resolve_output_dir(raw_output: &str) -> Result<PathBuf, ErrorResponse> {
+/// 解析输出目录：相对路径默认落在受控临时目录，`cwd:` 前缀落在当前工作目录，
+/// 绝对路径必须位于两者或配置的 output_roots 之内
+pub fn resolve_output_dir(config: &Config, raw_output: &str) -> Result<PathBuf, ErrorResponse> {
     let trimmed = raw_output.trim();
     if trimmed.is_empty() {
         return Err(ErrorResponse {
@@ -335,11 +365,11 @@ fn resolve_output_dir(raw_output: &str) -> Result<PathBuf, ErrorResponse> {
         message: format!("无法获取当前工作目录: {}", e),
         available: None,
     })?)
-        .map_err(|e| ErrorResponse {
-            error: "io_error".to_string(),
-            message: format!("canonicalize cwd 失败: {}", e),
-            available: None,
-        })?;
+    .map_err(|e| ErrorResponse {
+        error: "io_error".to_string(),
+        message: format!("canonicalize cwd 失败: {}", e),
+        available: None,
+    })?;
 
     let temp_root = controlled_temp_root()?;
 
@@ -352,7 +382,10 @@ fn resolve_output_dir(raw_output: &str) -> Result<PathBuf, ErrorResponse> {
 
     let raw_path = PathBuf::from(trimmed);
     if raw_path.is_absolute() {
-        return resolve_absolute_output_dir(&raw_path, &cwd_root, &temp_root);
+        // 配置的输出目录不存在时忽略
+        let mut roots = vec![cwd_root, temp_root];
+        roots.extend(config.output_roots.iter().filter_map(|r| fs::canonicalize(r).ok()));
+        return resolve_absolute_output_dir(&raw_path, &roots);
     }
 
     resolve_relative_output_dir(trimmed, &temp_root, TMP_PATH_PREFIX)
@@ -409,21 +442,21 @@ fn resolve_relative_output_dir(relative: &str, root: &Path, prefix: &str) -> Res
     Ok(candidate)
 }
 
-fn resolve_absolute_output_dir(absolute: &Path, cwd_root: &Path, temp_root: &Path) -> Result<PathBuf, ErrorResponse> {
+fn resolve_absolute_output_dir(absolute: &Path, roots: &[PathBuf]) -> Result<PathBuf, ErrorResponse> {
     let canonical_target = canonicalize_or_ancestor(absolute).map_err(|e| ErrorResponse {
         error: "invalid_output_dir".to_string(),
         message: format!("canonicalize 输出路径失败: {}", e),
         available: None,
     })?;
 
-    if is_within_root(&canonical_target, cwd_root) || is_within_root(&canonical_target, temp_root) {
+    if roots.iter().any(|root| is_within_root(&canonical_target, root)) {
         return Ok(absolute.to_path_buf());
     }
 
     Err(ErrorResponse {
         error: "invalid_output_dir".to_string(),
         message: format!(
-            "输出目录超出允许范围: {}，仅允许当前工作目录或受控临时目录",
+            "输出目录超出允许范围: {}，仅允许当前工作目录、受控临时目录或配置的 output_roots",
             canonical_target.display()
         ),
         available: None,
@@ -478,3 +511,42 @@ fn canonicalize_or_ancestor(path: &Path) -> std::io::Result<PathBuf> {
         format!("无法 canonicalize 路径: {}", path.display()),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_session_shadows_archive() {
+        let home = std::env::temp_dir().join(format!("mcp-get-test-{}", std::process::id()));
+        let project = home.join("projects").join("-p");
+        fs::create_dir_all(&project).unwrap();
+        let line = |text: &str| {
+            format!(
+                r#"{{"type":"user","timestamp":"2026-05-01T00:00:00Z","message":{{"role":"user","content":"{text}"}}}}"#
+            )
+        };
+        fs::write(project.join("aaaa1111-0000.jsonl"), line("live")).unwrap();
+        // 归档中途留下的旧压缩副本
+        let gz = File::create(project.join("aaaa1111-0000.jsonl.gz")).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(gz, flate2::Compression::default());
+        encoder.write_all(line("stale").as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        let config = Config::for_test(&home);
+        let params = GetParams {
+            r#ref: "aaaa1111:1".to_string(),
+            range: None,
+            output: None,
+            project: Some("-p".to_string()),
+            redactor: None,
+            include_thinking: false,
+        };
+        match get(&config, params).unwrap() {
+            GetResponse::Success { content, .. } => assert_eq!(content, "live"),
+            _ => panic!("unexpected response"),
+        }
+
+        fs::remove_dir_all(&home).ok();
+    }
+}
@@ -0,0 +1,897 @@
+//! 持久化倒排索引
+//!
+//! 每个 session jsonl 对应一个索引文件（镜像 projects 目录结构，存放在 `Config::index_dir` 下，小端二进制编码），
+//! 记录 token → 行号的倒排表、每行的字节偏移，以及 size/mtime/offset 水位线：
+//! - 文件只追加时，增量构建只解析 offset 之后的新行；搜索时前缀走索引、尾部线性扫描
+//! - 文件被截断或原地改写时视为过期，搜索回退到线性扫描，构建时整文件重建
+//!
+//! token 为小写后内容中的最长字母数字串。查询词中的每个字母数字串必然是命中行某个 token 的子串，
+//! 因此"token 包含查询串"是命中的必要条件，候选行集合只会多不会少，最终仍由 search_file 逐行校验。
+//! 词表按字典序存放并附带全部 token 后缀的排序数组，"token 包含查询串"即"某个后缀以查询串开头"，二分即可定位，
+//! 无需遍历词表。
+//! 思考内容单独建倒排表：普通词只查正文，include_thinking 时正文与思考都查，`thinking:` 字段只查思考
+
+use crate::config::Config;
+use crate::get::set_private_permissions;
+use crate::query::{FieldFilter, Query, QueryExpr};
+use crate::reader::Compression;
+use crate::search::collect_jsonl_files;
+use crate::types::*;
+use crate::utils::*;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, UNIX_EPOCH};
+
+/// 索引格式版本，结构变化时递增，旧版本索引视为缺失
+const INDEX_VERSION: u32 = 4;
+
+/// 索引文件头，用于区分其他格式（如旧版本的 JSON 索引）
+const INDEX_MAGIC: &[u8; 8] = b"MCHIDX\0\0";
+
+/// 超过该长度的 token 不入索引（base64、长哈希等会撑爆词表），所在行记为"始终候选"
+const MAX_TOKEN_LEN: usize = 256;
+
+/// 单个 session 文件的索引
+#[derive(Debug, Default)]
+pub struct FileIndex {
+    pub version: u32,
+    /// 建索引时的文件大小
+    pub size: u64,
+    /// 建索引时的文件 mtime（毫秒）
+    pub mtime_ms: u64,
+    /// 已索引到的字节偏移（最后一个完整行之后），增量构建从这里继续
+    pub offset: u64,
+    /// 已索引的完整行数
+    pub lines: usize,
+    /// 每行起始字节偏移（下标 = 行号 - 1）
+    pub line_offsets: Vec<u64>,
+    /// 正文词表
+    pub content: Vocabulary,
+    /// 思考内容词表，与 render_content 的 `[THINKING]` 段一致
+    pub thinking: Vocabulary,
+    /// 含超长 token 的行，无法通过词表裁剪，查询时始终作为候选
+    pub unindexed: Vec<u32>,
+}
+
+/// 一张倒排表：升序词表、各词的行号，以及用于子串查询的后缀数组
+///
+/// 各部分都首尾相接存放在少数几个数组里，载入时不必为每个 token 单独分配
+#[derive(Debug, Default)]
+pub struct Vocabulary {
+    /// 升序去重的 token 首尾相接
+    text: String,
+    /// 第 i 个 token 在 text 中的结束位置（起点为上一个的结束位置）
+    token_ends: Vec<u32>,
+    /// 各 token 的行号（升序）首尾相接
+    lines: Vec<u32>,
+    /// 第 i 个 token 的行号在 lines 中的结束位置
+    line_ends: Vec<u32>,
+    /// 全部 token 的后缀（token 下标，字节偏移），按后缀字典序排列
+    suffixes: Vec<(u32, u16)>,
+}
+
+impl Vocabulary {
+    fn from_map(map: HashMap<String, Vec<u32>>) -> Self {
+        let mut entries: Vec<_> = map.into_iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        let mut vocabulary = Self::default();
+        for (token, lines) in &entries {
+            vocabulary.text.push_str(token);
+            vocabulary.token_ends.push(vocabulary.text.len() as u32);
+            vocabulary.lines.extend_from_slice(lines);
+            vocabulary.line_ends.push(vocabulary.lines.len() as u32);
+        }
+        let mut suffixes: Vec<(u32, u16)> = entries
+            .iter()
+            .enumerate()
+            .flat_map(|(i, (token, _))| token.char_indices().map(move |(offset, _)| (i as u32, offset as u16)))
+            .collect();
+        suffixes.sort_unstable_by(|&a, &b| vocabulary.suffix(a).cmp(vocabulary.suffix(b)));
+        vocabulary.suffixes = suffixes;
+        vocabulary
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        put_array(out, self.text.as_bytes(), |b| [b]);
+        put_array(out, &self.token_ends, |n| n.to_le_bytes());
+        put_array(out, &self.lines, |n| n.to_le_bytes());
+        put_array(out, &self.line_ends, |n| n.to_le_bytes());
+        put_array(out, &self.suffixes, |(token, offset)| {
+            let mut bytes = [0u8; 6];
+            bytes[..4].copy_from_slice(&token.to_le_bytes());
+            bytes[4..].copy_from_slice(&offset.to_le_bytes());
+            bytes
+        });
+    }
+
+    fn decode(input: &mut Decoder) -> Option<Self> {
+        Some(Self {
+            text: String::from_utf8(input.array(|[b]: [u8; 1]| b)?).ok()?,
+            token_ends: input.array(u32::from_le_bytes)?,
+            lines: input.array(u32::from_le_bytes)?,
+            line_ends: input.array(u32::from_le_bytes)?,
+            suffixes: input.array(|b: [u8; 6]| {
+                (
+                    u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+                    u16::from_le_bytes([b[4], b[5]]),
+                )
+            })?,
+        })
+    }
+
+    /// 还原为可增量追加的 token → 行号表
+    fn into_map(self) -> HashMap<String, Vec<u32>> {
+        (0..self.token_ends.len())
+            .map(|i| (self.token(i).to_string(), self.token_lines(i).to_vec()))
+            .collect()
+    }
+
+    /// 各偏移表单调且不越界，后缀落在字符边界上；损坏的索引据此丢弃，不会在查询时越界
+    fn is_consistent(&self) -> bool {
+        let monotonic = |ends: &[u32], len: usize| {
+            ends.windows(2).all(|w| w[0] <= w[1]) && ends.last().map_or(len == 0, |&end| end as usize == len)
+        };
+        monotonic(&self.token_ends, self.text.len())
+            && monotonic(&self.line_ends, self.lines.len())
+            && self.token_ends.len() == self.line_ends.len()
+            && self
+                .token_ends
+                .iter()
+                .all(|&end| self.text.is_char_boundary(end as usize))
+            && self.suffixes.iter().all(|&(token, offset)| {
+                (token as usize) < self.token_ends.len() && {
+                    let t = self.token(token as usize);
+                    (offset as usize) < t.len() && t.is_char_boundary(offset as usize)
+                }
+            })
+    }
+
+    fn token(&self, i: usize) -> &str {
+        let start = i.checked_sub(1).map_or(0, |j| self.token_ends[j] as usize);
+        &self.text[start..self.token_ends[i] as usize]
+    }
+
+    fn token_lines(&self, i: usize) -> &[u32] {
+        let start = i.checked_sub(1).map_or(0, |j| self.line_ends[j] as usize);
+        &self.lines[start..self.line_ends[i] as usize]
+    }
+
+    fn suffix(&self, (token, offset): (u32, u16)) -> &str {
+        &self.token(token as usize)[offset as usize..]
+    }
+
+    /// 把包含 run 的 token 所在行追加到 out：二分找到以 run 开头的后缀区间
+    fn lines_containing(&self, run: &str, out: &mut Vec<u32>) {
+        let start = self.suffixes.partition_point(|&s| self.suffix(s) < run);
+        let mut ids: Vec<u32> = self.suffixes[start..]
+            .iter()
+            .take_while(|&&s| self.suffix(s).starts_with(run))
+            .map(|&(token, _)| token)
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        for id in ids {
+            out.extend_from_slice(self.token_lines(id as usize));
+        }
+    }
+}
+
+/// 索引相对当前文件的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freshness {
+    /// 与文件完全一致
+    Fresh,
+    /// 文件在 offset 之后追加了内容，前缀仍然有效
+    Appended,
+    /// 文件被截断或改写
+    Stale,
+    /// 索引不存在或版本不匹配
+    Missing,
+}
+
+impl FileIndex {
+    /// 读取索引文件；不存在、损坏或版本不符时返回 None
+    pub fn load(path: &Path) -> Option<Self> {
+        let data = fs::read(path).ok()?;
+        let index = Self::decode(&mut Decoder(&data))?;
+        (index.version == INDEX_VERSION && index.content.is_consistent() && index.thinking.is_consistent())
+            .then_some(index)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = INDEX_MAGIC.to_vec();
+        out.extend_from_slice(&self.version.to_le_bytes());
+        for n in [self.size, self.mtime_ms, self.offset, self.lines as u64] {
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+        put_array(&mut out, &self.line_offsets, |n| n.to_le_bytes());
+        self.content.encode(&mut out);
+        self.thinking.encode(&mut out);
+        put_array(&mut out, &self.unindexed, |n| n.to_le_bytes());
+        out
+    }
+
+    fn decode(input: &mut Decoder) -> Option<Self> {
+        if input.take(INDEX_MAGIC.len())? != INDEX_MAGIC {
+            return None;
+        }
+        let version = u32::from_le_bytes(input.take_array()?);
+        if version != INDEX_VERSION {
+            return None;
+        }
+        Some(Self {
+            version,
+            size: input.u64()?,
+            mtime_ms: input.u64()?,
+            offset: input.u64()?,
+            lines: input.u64()? as usize,
+            line_offsets: input.array(u64::from_le_bytes)?,
+            content: Vocabulary::decode(input)?,
+            thinking: Vocabulary::decode(input)?,
+            unindexed: input.array(u32::from_le_bytes)?,
+        })
+    }
+
+    /// 原子写入：先写临时文件再 rename，避免并发搜索读到半截索引
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("idx.tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp)?);
+            writer.write_all(&self.encode())?;
+            writer.flush()?;
+        }
+        let _ = set_private_permissions(&tmp, 0o600, "索引文件");
+        fs::rename(&tmp, path)
+    }
+
+    /// 判断索引相对文件当前 size/mtime 的状态
+    pub fn freshness(&self, size: u64, mtime_ms: u64) -> Freshness {
+        if size < self.offset || size < self.size {
+            return Freshness::Stale;
+        }
+        if size == self.size {
+            return if mtime_ms == self.mtime_ms {
+                Freshness::Fresh
+            } else {
+                Freshness::Stale
+            };
+        }
+        Freshness::Appended
+    }
+
+    /// 把一行内容加入索引（词表先还原为 token → 行号表，extend_from 结束时再排序）
+    fn add_line(&mut self, line_num: usize, line_offset: u64, line: &str, postings: &mut Postings) {
+        self.line_offsets.push(line_offset);
+        self.lines = line_num;
+
+        let Some(record) = MessageRecord::parse(line) else {
+            return;
+        };
+        let (content, _) = extract_and_replace_images(&record);
+        let thinking = with_thinking_section(String::new(), &extract_thinking(&record));
+
+        let overlong =
+            add_postings(&mut postings.0, &content, line_num) | add_postings(&mut postings.1, &thinking, line_num);
+        if overlong {
+            self.unindexed.push(line_num as u32);
+        }
+    }
+
+    /// 从 self.offset 开始读取新的完整行并入索引，返回新增行数
+    fn extend_from(&mut self, path: &Path, size: u64, mtime_ms: u64) -> std::io::Result<usize> {
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut reader = BufReader::new(file);
+
+        let mut postings = (
+            std::mem::take(&mut self.content).into_map(),
+            std::mem::take(&mut self.thinking).into_map(),
+        );
+        let mut added = 0;
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            let n = reader.read_until(b'\n', &mut buf)?;
+            // 末尾不完整的行（写入中）留到下次
+            if n == 0 || buf.last() != Some(&b'\n') {
+                break;
+            }
+            let line_offset = self.offset;
+            self.offset += n as u64;
+            added += 1;
+            let line = String::from_utf8_lossy(&buf);
+            self.add_line(
+                self.lines + 1,
+                line_offset,
+                line.trim_end_matches(['\n', '\r']),
+                &mut postings,
+            );
+        }
+        self.content = Vocabulary::from_map(postings.0);
+        self.thinking = Vocabulary::from_map(postings.1);
+
+        self.size = size;
+        self.mtime_ms = mtime_ms;
+        Ok(added)
+    }
+
+    /// 计算满足查询的候选行号（升序），仅覆盖已索引的前 `self.lines` 行
+    pub fn candidates(&self, query: &IndexQuery) -> Vec<usize> {
+        let mut result: Option<Vec<u32>> = None;
+        for clause in &query.clauses {
+            let mut lines: Vec<u32> = Vec::new();
+            for (scope, run) in clause {
+                if *scope != IndexScope::Thinking {
+                    self.content.lines_containing(run, &mut lines);
+                }
+                if *scope != IndexScope::Content {
+                    self.thinking.lines_containing(run, &mut lines);
+                }
+            }
+            lines.sort_unstable();
+            lines.dedup();
+            result = Some(match result {
+                None => lines,
+                Some(prev) => intersect_sorted(&prev, &lines),
+            });
+        }
+
+        let mut lines = result.unwrap_or_default();
+        lines.extend_from_slice(&self.unindexed);
+        lines.sort_unstable();
+        lines.dedup();
+        lines.into_iter().map(|n| n as usize).collect()
+    }
+}
+
+/// 写入定长元素数组：元素个数（u64）+ 各元素的小端字节
+fn put_array<T: Copy, const N: usize>(out: &mut Vec<u8>, items: &[T], bytes: impl Fn(T) -> [u8; N]) {
+    out.extend_from_slice(&(items.len() as u64).to_le_bytes());
+    out.reserve(items.len() * N);
+    for &item in items {
+        out.extend_from_slice(&bytes(item));
+    }
+}
+
+/// 索引文件读取游标；长度字段超出剩余数据时返回 None，损坏的文件不会导致超量分配
+struct Decoder<'a>(&'a [u8]);
+
+impl Decoder<'_> {
+    fn take(&mut self, n: usize) -> Option<&[u8]> {
+        if n > self.0.len() {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take_array()?))
+    }
+
+    fn array<T, const N: usize>(&mut self, item: impl Fn([u8; N]) -> T) -> Option<Vec<T>> {
+        let len = usize::try_from(self.u64()?).ok()?;
+        let bytes = self.take(len.checked_mul(N)?)?;
+        Some(
+            bytes
+                .chunks_exact(N)
+                .map(|chunk| item(chunk.try_into().unwrap_or([0; N])))
+                .collect(),
+        )
+    }
+}
+
+/// 构建中的（正文，思考内容）token → 行号表
+type Postings = (HashMap<String, Vec<u32>>, HashMap<String, Vec<u32>>);
+
+/// 把文本的 token 加入倒排表，返回是否含超长 token
+fn add_postings(postings: &mut HashMap<String, Vec<u32>>, text: &str, line_num: usize) -> bool {
+    let mut seen = HashSet::new();
+    let mut overlong = false;
+    for token in tokenize(&text.to_lowercase()) {
+        if token.len() > MAX_TOKEN_LEN {
+            overlong = true;
+            continue;
+        }
+        if seen.insert(token) {
+            postings.entry(token.to_string()).or_default().push(line_num as u32);
+        }
+    }
+    overlong
+}
+
+fn intersect_sorted(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut out = Vec::with_capacity(a.len().min(b.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                out.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    out
+}
+
+/// 按字母数字串切分文本（调用方负责大小写归一化）
+pub fn tokenize(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|t| !t.is_empty())
+}
+
+/// 查询串需要在哪张倒排表中出现
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexScope {
+    /// 正文
+    Content,
+    /// 思考内容（`thinking:` 字段）
+    Thinking,
+    /// 正文或思考内容（include_thinking 时的普通词）
+    Any,
+}
+
+/// 可下推到索引的查询：clauses 之间为 AND，clause 内各串为 OR
+#[derive(Debug, Default)]
+pub struct IndexQuery {
+    pub clauses: Vec<Vec<(IndexScope, String)>>,
+}
+
+impl IndexQuery {
+    /// 从查询 AST 提取必要条件；无任何可裁剪条件时返回 None（只能线性扫描）
+    ///
+    /// include_thinking 与 SearchParams 一致：为 true 时普通词也可能只命中思考内容
+    pub fn from_query(query: &Query, include_thinking: bool) -> Option<Self> {
+        let term_scope = if include_thinking {
+            IndexScope::Any
+        } else {
+            IndexScope::Content
+        };
+        let clauses = query
+            .root
+            .as_ref()
+            .map(|root| necessary_clauses(root, term_scope))
+            .unwrap_or_default();
+        (!clauses.is_empty()).then_some(Self { clauses })
+    }
+}
+
+/// 按字母数字串切分为各自必须出现的子句
+fn run_clauses(text: &str, scope: IndexScope) -> Vec<Vec<(IndexScope, String)>> {
+    tokenize(&text.to_lowercase())
+        .map(|run| vec![(scope, run.to_string())])
+        .collect()
+}
+
+/// 表达式命中的必要条件（AND of OR）；空 = 无法裁剪
+fn necessary_clauses(expr: &QueryExpr, term_scope: IndexScope) -> Vec<Vec<(IndexScope, String)>> {
+    match expr {
+        // 词内每个字母数字串都必须出现
+        QueryExpr::Term(term) => run_clauses(term, term_scope),
+        // 思考字段的值同样按字母数字串拆分，只查思考倒排表
+        QueryExpr::Field(FieldFilter::Thinking(text)) => run_clauses(text, IndexScope::Thinking),
+        QueryExpr::And(items) => items
+            .iter()
+            .flat_map(|item| necessary_clauses(item, term_scope))
+            .collect(),
+        // 每个分支取一条子句（优先最长的串）合并为一个 OR 子句；任一分支无法裁剪则整组无法裁剪
+        QueryExpr::Or(items) => {
+            let mut merged = Vec::new();
+            for item in items {
+                let best = necessary_clauses(item, term_scope)
+                    .into_iter()
+                    .max_by_key(|clause| clause.iter().map(|(_, run)| run.len()).min().unwrap_or(0));
+                match best {
+                    Some(clause) => merged.extend(clause),
+                    None => return Vec::new(),
+                }
+            }
+            vec![merged]
+        }
+        // 其他字段条件不在索引中，NOT 无法转为必要条件
+        QueryExpr::Field(_) | QueryExpr::Not(_) => Vec::new(),
+    }
+}
+
+/// 返回文件的 (size, mtime 毫秒)
+pub fn file_watermark(path: &Path) -> Option<(u64, u64)> {
+    let meta = fs::metadata(path).ok()?;
+    let mtime_ms = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    Some((meta.len(), mtime_ms))
+}
+
+/// projects 目录下的路径在索引目录中的镜像位置：主根直接镜像，其他根放在 `roots/<name>/` 下
+fn index_mirror(config: &Config, path: &Path) -> Option<PathBuf> {
+    let (i, root) = config
+        .roots
+        .iter()
+        .enumerate()
+        .find(|(_, r)| path.starts_with(&r.projects_dir))?;
+    let rel = path.strip_prefix(&root.projects_dir).ok()?;
+    Some(match i {
+        0 => config.index_dir.join(rel),
+        _ => config.index_dir.join("roots").join(&root.name).join(rel),
+    })
+}
+
+/// session 文件对应的索引文件路径（镜像 projects_dir 下的相对路径）
+pub fn index_path_for(config: &Config, session_path: &Path) -> Option<PathBuf> {
+    let mirror = index_mirror(config, session_path)?;
+    let mut name = mirror.file_name()?.to_os_string();
+    name.push(".idx");
+    Some(mirror.with_file_name(name))
+}
+
+/// 校验 offset 前一个字节是换行符，确认"追加"判断成立（文件被整体替换时大概率失败）
+fn prefix_boundary_ok(path: &Path, offset: u64) -> bool {
+    if offset == 0 {
+        return true;
+    }
+    let Ok(mut file) = File::open(path) else {
+        return false;
+    };
+    let mut byte = [0u8; 1];
+    file.seek(SeekFrom::Start(offset - 1)).is_ok() && file.read_exact(&mut byte).is_ok() && byte[0] == b'\n'
+}
+
+/// 载入可用于搜索的索引；过期或缺失时返回 None
+pub fn load_usable(config: &Config, session_path: &Path) -> Option<FileIndex> {
+    let index = FileIndex::load(&index_path_for(config, session_path)?)?;
+    let (size, mtime_ms) = file_watermark(session_path)?;
+    match index.freshness(size, mtime_ms) {
+        Freshness::Fresh => Some(index),
+        Freshness::Appended if prefix_boundary_ok(session_path, index.offset) => Some(index),
+        _ => None,
+    }
+}
+
+/// 单文件增量更新结果
+enum UpdateOutcome {
+    Unchanged,
+    Appended(usize),
+    Rebuilt(usize),
+    Failed,
+}
+
+fn update_file(config: &Config, session_path: &Path, force: bool) -> UpdateOutcome {
+    let Some(index_path) = index_path_for(config, session_path) else {
+        return UpdateOutcome::Failed;
+    };
+    let Some((size, mtime_ms)) = file_watermark(session_path) else {
+        return UpdateOutcome::Failed;
+    };
+
+    let existing = if force { None } else { FileIndex::load(&index_path) };
+    let (mut index, rebuilt) = match existing {
+        Some(index) => match index.freshness(size, mtime_ms) {
+            Freshness::Fresh => return UpdateOutcome::Unchanged,
+            Freshness::Appended if prefix_boundary_ok(session_path, index.offset) => (index, false),
+            _ => (FileIndex::default(), true),
+        },
+        None => (FileIndex::default(), true),
+    };
+    index.version = INDEX_VERSION;
+
+    let Ok(added) = index.extend_from(session_path, size, mtime_ms) else {
+        return UpdateOutcome::Failed;
+    };
+    if index.save(&index_path).is_err() {
+        return UpdateOutcome::Failed;
+    }
+    if rebuilt {
+        UpdateOutcome::Rebuilt(added)
+    } else {
+        UpdateOutcome::Appended(added)
+    }
+}
+
+/// 构建/增量更新索引；rebuild=true 时先删除这些项目的旧索引
+pub fn build_index(
+    config: &Config,
+    project_dirs: &[(String, PathBuf)],
+    rebuild: bool,
+) -> Result<IndexBuildResponse, ErrorResponse> {
+    let start = Instant::now();
+
+    if rebuild {
+        for (_, project_dir) in project_dirs {
+            let Some(dir) = index_mirror(config, project_dir) else {
+                continue;
+            };
+            if dir.exists() {
+                fs::remove_dir_all(&dir).map_err(|e| ErrorResponse {
+                    error: "io_error".to_string(),
+                    message: format!("无法删除旧索引 {}: {}", dir.display(), e),
+                    available: None,
+                })?;
+            }
+        }
+    }
+
+    let was_new = !config.index_dir.exists();
+    fs::create_dir_all(&config.index_dir).map_err(|e| ErrorResponse {
+        error: "io_error".to_string(),
+        message: format!("无法创建索引目录: {}", e),
+        available: None,
+    })?;
+    if was_new {
+        set_private_permissions(&config.index_dir, 0o700, "索引目录")?;
+    }
+
+    let files = indexable_files(project_dirs);
+    let outcomes: Vec<UpdateOutcome> = files
+        .par_iter()
+        .map(|(_, _, path)| update_file(config, path, rebuild))
+        .collect();
+
+    let mut response = IndexBuildResponse {
+        index_dir: config.index_dir.clone(),
+        files_total: files.len(),
+        files_unchanged: 0,
+        files_appended: 0,
+        files_rebuilt: 0,
+        files_failed: 0,
+        lines_indexed: 0,
+        time_ms: 0,
+    };
+    for outcome in outcomes {
+        match outcome {
+            UpdateOutcome::Unchanged => response.files_unchanged += 1,
+            UpdateOutcome::Appended(n) => {
+                response.files_appended += 1;
+                response.lines_indexed += n;
+            }
+            UpdateOutcome::Rebuilt(n) => {
+                response.files_rebuilt += 1;
+                response.lines_indexed += n;
+            }
+            UpdateOutcome::Failed => response.files_failed += 1,
+        }
+    }
+    response.time_ms = start.elapsed().as_millis() as u64;
+    Ok(response)
+}
+
+/// 可建索引的会话文件：归档（压缩）文件无法按字节偏移随机读取，也不会再追加，直接线性扫描
+fn indexable_files(project_dirs: &[(String, PathBuf)]) -> Vec<(String, String, PathBuf)> {
+    let mut files = collect_jsonl_files(project_dirs, &[], true);
+    files.retain(|(_, _, path)| Compression::of(path) == Compression::None);
+    files
+}
+
+/// 统计索引覆盖情况
+pub fn index_status(config: &Config, project_dirs: &[(String, PathBuf)]) -> IndexStatusResponse {
+    let files = indexable_files(project_dirs);
+    let states: Vec<(Freshness, u64)> = files
+        .par_iter()
+        .map(|(_, _, path)| {
+            let Some(index_path) = index_path_for(config, path) else {
+                return (Freshness::Missing, 0);
+            };
+            let index_bytes = fs::metadata(&index_path).map(|m| m.len()).unwrap_or(0);
+            let Some(index) = FileIndex::load(&index_path) else {
+                return (Freshness::Missing, index_bytes);
+            };
+            let state = match file_watermark(path) {
+                Some((size, mtime_ms)) => index.freshness(size, mtime_ms),
+                None => Freshness::Stale,
+            };
+            (state, index_bytes)
+        })
+        .collect();
+
+    let mut response = IndexStatusResponse {
+        index_dir: config.index_dir.clone(),
+        files_total: files.len(),
+        files_fresh: 0,
+        files_appended: 0,
+        files_stale: 0,
+        files_missing: 0,
+        index_bytes: 0,
+    };
+    for (state, bytes) in states {
+        response.index_bytes += bytes;
+        match state {
+            Freshness::Fresh => response.files_fresh += 1,
+            Freshness::Appended => response.files_appended += 1,
+            Freshness::Stale => response.files_stale += 1,
+            Freshness::Missing => response.files_missing += 1,
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(pattern: &str) -> Query {
+        Query::parse(pattern, false, crate::time::TimeZone::Utc).unwrap()
+    }
+
+    fn user_line(i: usize, text: &str) -> String {
+        format!(
+            r#"{{"uuid":"u-{i}","type":"user","timestamp":"2026-04-26T10:00:00Z","message":{{"role":"user","content":"{text}"}}}}"#
+        )
+    }
+
+    #[test]
+    fn test_candidates_substring_semantics() {
+        let tmp = std::env::temp_dir().join(format!("mcp-index-test-{}", std::process::id()));
+        fs::create_dir_all(&tmp).unwrap();
+        let path = tmp.join("s.jsonl");
+        let lines = [
+            user_line(1, "tokio deadlock in runtime"),
+            user_line(2, "nothing here"),
+            user_line(3, "Deadlocked again: foo.bar"),
+        ];
+        fs::write(&path, format!("{}\n", lines.join("\n"))).unwrap();
+
+        let mut index = FileIndex::default();
+        let (size, mtime) = file_watermark(&path).unwrap();
+        index.extend_from(&path, size, mtime).unwrap();
+        assert_eq!(index.lines, 3);
+        assert_eq!(index.freshness(size, mtime), Freshness::Fresh);
+
+        let q = IndexQuery::from_query(&parse("DEADLOCK"), false).unwrap();
+        assert_eq!(index.candidates(&q), vec![1, 3]);
+
+        let q = IndexQuery::from_query(&parse("o.ba"), false).unwrap();
+        assert_eq!(index.candidates(&q), vec![3]);
+
+        let q = IndexQuery::from_query(&parse("tokio|nothing"), false).unwrap();
+        assert_eq!(index.candidates(&q), vec![1, 2]);
+
+        assert!(IndexQuery::from_query(&parse("!deadlock"), false).is_none());
+
+        fs::remove_dir_all(&tmp).ok();
+    }
+
+    #[test]
+    fn test_thinking_postings() {
+        let tmp = std::env::temp_dir().join(format!("mcp-index-test3-{}", std::process::id()));
+        fs::create_dir_all(&tmp).unwrap();
+        let path = tmp.join("s.jsonl");
+        let lines = [
+            user_line(1, "retry the deploy"),
+            r#"{"uuid":"a-2","type":"assistant","message":{"role":"assistant","content":[{"type":"thinking","thinking":"maybe retry"},{"type":"text","text":"Done."}]}}"#.to_string(),
+            r#"{"uuid":"a-3","type":"assistant","message":{"role":"assistant","content":[{"type":"redacted_thinking","data":"xyz"}]}}"#.to_string(),
+        ];
+        fs::write(&path, format!("{}\n", lines.join("\n"))).unwrap();
+
+        let mut index = FileIndex::default();
+        let (size, mtime) = file_watermark(&path).unwrap();
+        index.extend_from(&path, size, mtime).unwrap();
+
+        let candidates = |pattern: &str, include_thinking| {
+            let q = IndexQuery::from_query(&parse(pattern), include_thinking).unwrap();
+            index.candidates(&q)
+        };
+        // 思考内容不进入正文倒排表
+        assert_eq!(candidates("retry", false), vec![1]);
+        assert_eq!(candidates("retry", true), vec![1, 2]);
+        // [THINKING] 段标记与 search 渲染一致
+        assert_eq!(candidates("thinking", true), vec![2, 3]);
+        assert_eq!(candidates("thinking:retry", false), vec![2]);
+        assert_eq!(candidates("thinking:redacted_thinking", false), vec![3]);
+        assert_eq!(candidates("thinking:retry done", false), vec![2]);
+        assert_eq!(candidates("thinking:retry deploy", false), Vec::<usize>::new());
+
+        fs::remove_dir_all(&tmp).ok();
+    }
+
+    /// 粗略基准：2 万行里查一个罕见词，载入索引 + 读取候选行应明显快于逐行解析
+    #[test]
+    fn test_indexed_scan_beats_linear_scan() {
+        use crate::search::{scan_file, scan_file_indexed};
+
+        let tmp = std::env::temp_dir().join(format!("mcp-index-bench-{}", std::process::id()));
+        fs::create_dir_all(&tmp).unwrap();
+        let path = tmp.join("s.jsonl");
+        let lines: Vec<String> = (1..=20_000)
+            .map(|i| match i {
+                12_345 => user_line(i, "found the needle at last"),
+                _ => user_line(
+                    i,
+                    &format!("step {i} of the deploy pipeline finished with code {}", i % 7),
+                ),
+            })
+            .collect();
+        fs::write(&path, format!("{}\n", lines.join("\n"))).unwrap();
+        let index_path = tmp.join("s.jsonl.idx");
+        let mut index = FileIndex {
+            version: INDEX_VERSION,
+            ..Default::default()
+        };
+        let (size, mtime) = file_watermark(&path).unwrap();
+        index.extend_from(&path, size, mtime).unwrap();
+        index.save(&index_path).unwrap();
+
+        let visit = |line: usize, record: MessageRecord| {
+            let (content, _) = render_content(&record, false);
+            content.contains("needle").then_some(line)
+        };
+
+        let start = Instant::now();
+        let (_, linear) = scan_file(&path, &[], usize::MAX, visit);
+        let linear_time = start.elapsed();
+
+        let start = Instant::now();
+        let index = FileIndex::load(&index_path).unwrap();
+        let query = IndexQuery::from_query(&parse("needle"), false).unwrap();
+        let (scanned, indexed) = scan_file_indexed(&path, &[], &index, &query, usize::MAX, visit);
+        let indexed_time = start.elapsed();
+
+        assert_eq!(linear, vec![12_345]);
+        assert_eq!((scanned, indexed), (1, vec![12_345]));
+        assert!(
+            indexed_time < linear_time,
+            "indexed {:?} vs linear {:?}",
+            indexed_time,
+            linear_time
+        );
+
+        // 旧版本的 JSON 索引和截断的文件视为缺失
+        fs::write(&index_path, br#"{"version":3}"#).unwrap();
+        assert!(FileIndex::load(&index_path).is_none());
+        index.save(&index_path).unwrap();
+        let data = fs::read(&index_path).unwrap();
+        fs::write(&index_path, &data[..data.len() / 2]).unwrap();
+        assert!(FileIndex::load(&index_path).is_none());
+
+        fs::remove_dir_all(&tmp).ok();
+    }
+
+    #[test]
+    fn test_incremental_append_and_partial_line() {
+        let tmp = std::env::temp_dir().join(format!("mcp-index-test2-{}", std::process::id()));
+        fs::create_dir_all(&tmp).unwrap();
+        let path = tmp.join("s.jsonl");
+        fs::write(
+            &path,
+            format!("{}\n{}", user_line(1, "alpha"), &user_line(2, "beta")[..20]),
+        )
+        .unwrap();
+
+        let mut index = FileIndex::default();
+        let (size, mtime) = file_watermark(&path).unwrap();
+        index.extend_from(&path, size, mtime).unwrap();
+        // 半截行不入索引
+        assert_eq!(index.lines, 1);
+
+        fs::write(&path, format!("{}\n{}\n", user_line(1, "alpha"), user_line(2, "beta"))).unwrap();
+        let (size, mtime) = file_watermark(&path).unwrap();
+        assert_eq!(index.freshness(size, mtime), Freshness::Appended);
+        assert!(prefix_boundary_ok(&path, index.offset));
+
+        let added = index.extend_from(&path, size, mtime).unwrap();
+        assert_eq!(added, 1);
+        assert_eq!(index.lines, 2);
+        let q = IndexQuery::from_query(&parse("beta"), false).unwrap();
+        assert_eq!(index.candidates(&q), vec![2]);
+
+        fs::write(&path, "").unwrap();
+        let (size, mtime) = file_watermark(&path).unwrap();
+        assert_eq!(index.freshness(size, mtime), Freshness::Stale);
+
+        fs::remove_dir_all(&tmp).ok();
+    }
+}
@@ -0,0 +1,38 @@
+//! Claude Code 会话历史的搜索 / 读取引擎
+//!
+//! 二进制（CLI 与 MCP 服务器）建立在这里的公开 API 之上，其他工具可直接嵌入而无需调用可执行文件：
+//! - [`model`]：类型化的消息模型（[`model::MessageRecord`]、[`model::ContentBlock`] 等）
+//! - [`reader`]：流式读取会话文件的 [`reader::SessionReader`]
+//! - [`search`]、[`get`]、[`context`]、[`sessions`] 等：与 MCP 工具一一对应的查询入口
+//! - [`redact`]：输出脱敏（密钥、token、密码等）
+//! - [`access`]：项目访问策略（允许 / 禁止列表、仅当前项目）
+
+pub mod access;
+pub mod archive;
+pub mod config;
+pub mod context;
+mod diff;
+pub mod export;
+pub mod file_versions;
+pub mod files;
+pub mod get;
+pub mod index;
+pub mod mcp_rmcp;
+pub mod model;
+pub mod projects;
+pub mod query;
+mod rank;
+pub mod reader;
+pub mod redact;
+pub mod resources;
+pub mod search;
+pub mod sessions;
+pub mod subagents;
+pub mod tail;
+pub mod thread;
+pub mod time;
+pub mod timeline;
+pub mod tool_calls;
+pub mod types;
+pub mod usage;
+pub mod utils;
@@ -1,25 +1,26 @@
-mod config;
-mod context;
-mod get;
-mod mcp_rmcp;
-mod projects;
-mod search;
-mod sessions;
-mod types;
-mod utils;
-
 use clap::{Parser, Subcommand};
 
-use config::Config;
-use context::{context, ContextParams};
-use get::{get, GetParams};
-use mcp_rmcp::run_mcp_server_rmcp;
-use projects::list_projects;
-use search::{search, SearchParams};
-use sessions::list_sessions;
-use types::Range;
-use utils::parse_iso_utc;
-use utils::parse_range;
+use mcp_claude_history::archive::{ArchiveParams, archive, parse_format};
+use mcp_claude_history::config::{Config, Root, config_file_path, validate_config_file};
+use mcp_claude_history::context::{ContextParams, context};
+use mcp_claude_history::export::{ExportFormat, ExportParams, ImageMode, export};
+use mcp_claude_history::file_versions::{FileVersionsParams, file_versions};
+use mcp_claude_history::files::{FileOp, FilesParams, files};
+use mcp_claude_history::get::{GetParams, get};
+use mcp_claude_history::index::{build_index, index_status};
+use mcp_claude_history::mcp_rmcp::{parse_http_addr, run_mcp_server_http, run_mcp_server_rmcp};
+use mcp_claude_history::projects::list_projects;
+use mcp_claude_history::redact::{Redactor, redacted};
+use mcp_claude_history::search::{SearchGroup, SearchParams, SortOrder, get_project_dirs, search};
+use mcp_claude_history::sessions::{SessionSort, SessionsParams, list_sessions};
+use mcp_claude_history::tail::{TailParams, Tailer, load_state, save_state};
+use mcp_claude_history::thread::{ThreadParams, thread};
+use mcp_claude_history::time::{TimeZone, parse_time_expr, parse_time_window};
+use mcp_claude_history::timeline::{BucketSize, TimelineParams, timeline};
+use mcp_claude_history::tool_calls::{ToolCallsParams, tool_calls};
+use mcp_claude_history::types::{ErrorResponse, Range};
+use mcp_claude_history::usage::{UsageFormat, UsageGroup, UsageParams, render_csv, usage};
+use mcp_claude_history::utils::parse_range;
 
 /// 把 domain Result<T, E> 序列化为 Result<String, String>:
 ///   - 成功 → Ok(json)
@@ -45,6 +46,26 @@ struct Cli {
     /// Run as MCP server
     #[arg(long)]
     mcp: bool,
+
+    /// Run as MCP server over streamable HTTP (ADDR or PORT, default 127.0.0.1:8765)
+    #[arg(long, value_name = "ADDR", num_args = 0..=1, default_missing_value = "127.0.0.1:8765")]
+    http: Option<String>,
+
+    /// Redact secrets (API keys, tokens, passwords, high-entropy strings) in output
+    #[arg(long, global = true)]
+    redact: bool,
+
+    /// Additional history root: a Claude config dir containing projects/, as NAME=PATH or PATH (repeatable)
+    #[arg(long, global = true, value_name = "[NAME=]PATH")]
+    root: Vec<String>,
+
+    /// Only allow access to the current project (inferred from the working directory)
+    #[arg(long, global = true)]
+    current_only: bool,
+
+    /// Timezone for date-only times and rendered timestamps: UTC, local, +08:00 or an IANA name
+    #[arg(long, global = true, value_name = "TZ")]
+    timezone: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -67,17 +88,17 @@ enum Commands {
         #[arg(long)]
         sessions: Option<Vec<String>>,
 
-        /// Start time (ISO 8601)
+        /// Start time: RFC 3339, date, or relative such as 2h / yesterday / last monday
         #[arg(long)]
         since: Option<String>,
 
-        /// End time (ISO 8601)
+        /// End time (same formats as --since)
         #[arg(long)]
         until: Option<String>,
 
-        /// Message types (comma separated)
-        #[arg(long, default_value = "assistant,user,summary")]
-        types: String,
+        /// Message types (comma separated, default: assistant,user,summary)
+        #[arg(long)]
+        types: Option<String>,
 
         /// Message subtypes filter (comma separated).
         /// user subtypes: human, tool_result, meta;
@@ -109,13 +130,25 @@ enum Commands {
         #[arg(long)]
         limit: Option<usize>,
 
-        /// Max chars per result
-        #[arg(long, default_value = "4000")]
-        max_content: usize,
+        /// Result order: time_asc, time_desc or relevance (BM25)
+        #[arg(long, default_value = "time_asc")]
+        sort: String,
 
-        /// Max total chars
-        #[arg(long, default_value = "40000")]
-        max_total: usize,
+        /// Group hits: "session" returns one entry per session; offset / limit then page over sessions
+        #[arg(long)]
+        group_by: Option<String>,
+
+        /// Also match thinking blocks and show them as a [THINKING] section (`thinking:` works without it)
+        #[arg(long)]
+        include_thinking: bool,
+
+        /// Max chars per result (default from config)
+        #[arg(long)]
+        max_content: Option<usize>,
+
+        /// Max total chars (default from config)
+        #[arg(long)]
+        max_total: Option<usize>,
     },
 
     /// Get full content by ref
@@ -135,6 +168,10 @@ enum Commands {
         /// Project ID
         #[arg(long)]
         project: Option<String>,
+
+        /// Prepend the message's thinking blocks as a [THINKING] section
+        #[arg(long)]
+        include_thinking: bool,
     },
 
     /// Get context around a message
@@ -159,6 +196,10 @@ enum Commands {
         #[arg(long, default_value = "forward")]
         direction: String,
 
+        /// Message order: file (line order) or parent (walk the parentUuid chain, skipping dead branches)
+        #[arg(long, default_value = "file")]
+        follow: String,
+
         /// Message types to include (comma separated)
         #[arg(long)]
         types: Option<String>,
@@ -175,17 +216,367 @@ enum Commands {
         #[arg(long)]
         case_sensitive: bool,
 
+        /// Show thinking blocks as a [THINKING] section (--pattern then matches them too)
+        #[arg(long)]
+        include_thinking: bool,
+
         /// Project ID
         #[arg(long)]
         project: Option<String>,
 
-        /// Max chars per message
-        #[arg(long, default_value = "4000")]
-        max_content: usize,
+        /// Max chars per message (default from config)
+        #[arg(long)]
+        max_content: Option<usize>,
+
+        /// Max total chars (default from config)
+        #[arg(long)]
+        max_total: Option<usize>,
+    },
+
+    /// Show the conversation chain (root → ref) reconstructed from parentUuid
+    Thread {
+        /// Reference (session_prefix:line)
+        #[arg(long)]
+        r#ref: String,
+
+        /// Message types to include (comma separated, anchor always included)
+        #[arg(long)]
+        types: Option<String>,
+
+        /// Project ID
+        #[arg(long)]
+        project: Option<String>,
+
+        /// Max chars per message (default from config)
+        #[arg(long)]
+        max_content: Option<usize>,
+
+        /// Max total chars (default from config; oldest messages are dropped first)
+        #[arg(long)]
+        max_total: Option<usize>,
+    },
+
+    /// Export a session (or a line range / thread) as a Markdown, HTML or JSON transcript
+    Export {
+        /// Session ID or prefix (exports the whole session)
+        #[arg(long, conflicts_with = "thread")]
+        session: Option<String>,
+
+        /// Reference (session_prefix:line): export the parentUuid chain ending here
+        #[arg(long)]
+        thread: Option<String>,
+
+        /// Project ID
+        #[arg(long)]
+        project: Option<String>,
+
+        /// Output format: markdown, html or json
+        #[arg(long, default_value = "markdown")]
+        format: String,
+
+        /// Images: inline (data URI), files (written next to the transcript) or none
+        #[arg(long, default_value = "inline")]
+        images: String,
+
+        /// Line ranges (e.g., "1-100,!50-60")
+        #[arg(long)]
+        lines: Option<String>,
+
+        /// Message types to include (comma separated)
+        #[arg(long)]
+        types: Option<String>,
+
+        /// Include thinking blocks (omitted by default)
+        #[arg(long)]
+        include_thinking: bool,
+
+        /// Output directory (same rules as `get --output`)
+        #[arg(long, default_value = "exports")]
+        output: String,
+    },
+
+    /// List tool calls (tool_use paired with its tool_result)
+    Tools {
+        /// Project ID (default: current)
+        #[arg(long)]
+        project: Option<Vec<String>>,
+
+        /// Search all projects
+        #[arg(long)]
+        all: bool,
+
+        /// Session IDs
+        #[arg(long)]
+        sessions: Option<Vec<String>>,
+
+        /// Start time: RFC 3339, date, or relative such as 2h / yesterday / last monday
+        #[arg(long)]
+        since: Option<String>,
+
+        /// End time (same formats as --since)
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Tool names (comma separated, exact match)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Only failed (true) or only successful (false) calls
+        #[arg(long)]
+        is_error: Option<bool>,
+
+        /// Input field filter `field=regex` (dotted path, repeatable, all must match)
+        #[arg(long)]
+        input: Vec<String>,
+
+        /// Include subagent sessions
+        #[arg(long)]
+        subagents: bool,
+
+        /// Skip first N calls
+        #[arg(long, default_value = "0")]
+        offset: usize,
+
+        /// Max calls
+        #[arg(long)]
+        limit: Option<usize>,
+
+        /// Max chars per tool result (default from config)
+        #[arg(long)]
+        max_content: Option<usize>,
+
+        /// Max total chars (default from config)
+        #[arg(long)]
+        max_total: Option<usize>,
+    },
+
+    /// Files read / written / edited by tool calls, per file and per touch
+    Files {
+        /// Path glob (absolute: whole path; relative: path suffix, e.g. "src/*.rs")
+        path: Option<String>,
+
+        /// Project ID (default: current)
+        #[arg(long)]
+        project: Option<Vec<String>>,
+
+        /// Search all projects
+        #[arg(long)]
+        all: bool,
+
+        /// Session IDs
+        #[arg(long)]
+        sessions: Option<Vec<String>>,
+
+        /// Operations (comma separated): read, write, edit, bash
+        #[arg(long)]
+        op: Option<String>,
+
+        /// Start time: RFC 3339, date, or relative such as 2h / yesterday / last monday
+        #[arg(long)]
+        since: Option<String>,
+
+        /// End time (same formats as --since)
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Include subagent sessions
+        #[arg(long)]
+        subagents: bool,
+
+        /// Skip first N touches
+        #[arg(long, default_value = "0")]
+        offset: usize,
+
+        /// Max touches
+        #[arg(long, default_value = "100")]
+        limit: usize,
+    },
+
+    /// Reconstruct a file's versions from Write / Edit / MultiEdit history
+    FileVersions {
+        /// File path or glob (must resolve to a single file)
+        path: String,
+
+        /// Project ID (default: current)
+        #[arg(long)]
+        project: Option<Vec<String>>,
+
+        /// Search all projects
+        #[arg(long)]
+        all: bool,
+
+        /// Session IDs
+        #[arg(long)]
+        sessions: Option<Vec<String>>,
+
+        /// Start time: RFC 3339, date, or relative such as 2h / yesterday / last monday
+        #[arg(long)]
+        since: Option<String>,
+
+        /// End time (same formats as --since)
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Include subagent sessions
+        #[arg(long)]
+        subagents: bool,
+
+        /// Only return this version's content
+        #[arg(long)]
+        version: Option<usize>,
+
+        /// Unified diff between two versions, "from-to" (0 = empty file)
+        #[arg(long)]
+        diff: Option<String>,
+
+        /// Max total content chars (default from config)
+        #[arg(long)]
+        max_total: Option<usize>,
+    },
+
+    /// Token usage and estimated cost, grouped by model / session / project / day
+    Usage {
+        /// Project ID (default: current)
+        #[arg(long)]
+        project: Option<Vec<String>>,
+
+        /// Include all projects
+        #[arg(long)]
+        all: bool,
+
+        /// Session IDs
+        #[arg(long)]
+        sessions: Option<Vec<String>>,
+
+        /// Start time: RFC 3339, date, or relative such as 2h / yesterday / last monday
+        #[arg(long)]
+        since: Option<String>,
+
+        /// End time (same formats as --since)
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Group keys (comma separated): model, session, project, day; empty for totals only
+        #[arg(long, default_value = "model")]
+        group_by: String,
+
+        /// Include subagent sessions
+        #[arg(long)]
+        subagents: bool,
+
+        /// Pricing table JSON (default: <config dir>/mcp-claude-history/pricing.json if present)
+        #[arg(long)]
+        pricing: Option<std::path::PathBuf>,
+
+        /// Output format: json or csv
+        #[arg(long, default_value = "json")]
+        format: String,
+    },
+
+    /// Count matching messages per hour / day / week, with the sessions behind each bucket
+    Timeline {
+        /// Filter pattern (same query syntax as search; empty counts every message)
+        #[arg(default_value = "")]
+        pattern: String,
+
+        /// Project ID (default: current)
+        #[arg(long)]
+        project: Option<Vec<String>>,
+
+        /// Include all projects
+        #[arg(long)]
+        all: bool,
+
+        /// Session IDs
+        #[arg(long)]
+        sessions: Option<Vec<String>>,
+
+        /// Start time: RFC 3339, date, or relative such as 2h / yesterday / last monday
+        #[arg(long)]
+        since: Option<String>,
+
+        /// End time (same formats as --since)
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Message types (comma separated, default: assistant,user,summary)
+        #[arg(long)]
+        types: Option<String>,
+
+        /// Message subtypes filter (comma separated)
+        #[arg(long)]
+        subtypes: Option<String>,
+
+        /// Use regex pattern
+        #[arg(long)]
+        regex: bool,
+
+        /// Case sensitive search
+        #[arg(long)]
+        case_sensitive: bool,
+
+        /// Include subagent sessions
+        #[arg(long)]
+        subagents: bool,
+
+        /// Bucket size: hour, day or week (configured timezone; weeks start on Monday)
+        #[arg(long, default_value = "day")]
+        bucket: String,
+
+        /// Also output a weekday x hour heatmap
+        #[arg(long)]
+        heatmap: bool,
+    },
+
+    /// Follow sessions as they are written, printing each new matching message as a JSON line
+    Tail {
+        /// Filter pattern (same query syntax as search; empty for all)
+        #[arg(default_value = "")]
+        pattern: String,
+
+        /// Project ID (default: current)
+        #[arg(long)]
+        project: Option<Vec<String>>,
+
+        /// Session IDs (default: every session in the project, including ones created later)
+        #[arg(long)]
+        sessions: Option<Vec<String>>,
+
+        /// Message types (comma separated, default: assistant,user,summary)
+        #[arg(long)]
+        types: Option<String>,
+
+        /// Message subtypes filter (comma separated)
+        #[arg(long)]
+        subtypes: Option<String>,
+
+        /// Use regex pattern
+        #[arg(long)]
+        regex: bool,
+
+        /// Case sensitive matching
+        #[arg(long)]
+        case_sensitive: bool,
+
+        /// Include subagent sessions
+        #[arg(long)]
+        subagents: bool,
+
+        /// Replay existing messages instead of starting at the end of each file
+        #[arg(long)]
+        from_start: bool,
+
+        /// State file with per-file byte offsets; loaded on start and rewritten after each poll
+        #[arg(long)]
+        state: Option<std::path::PathBuf>,
+
+        /// Poll interval in milliseconds
+        #[arg(long, default_value = "1000")]
+        interval: u64,
 
-        /// Max total chars
-        #[arg(long, default_value = "40000")]
-        max_total: usize,
+        /// Max chars per message (default from config)
+        #[arg(long)]
+        max_content: Option<usize>,
     },
 
     /// List all projects
@@ -196,6 +587,101 @@ enum Commands {
         /// Project ID (default: current)
         #[arg(long)]
         project: Option<String>,
+
+        /// Sort: activity (most recent first), size, messages or tokens
+        #[arg(long, default_value = "activity")]
+        sort: String,
+
+        /// Only sessions that were on this git branch
+        #[arg(long)]
+        branch: Option<String>,
+
+        /// Only sessions that used a model containing this text
+        #[arg(long)]
+        model: Option<String>,
+
+        /// Minimum number of messages
+        #[arg(long, default_value = "0")]
+        min_messages: usize,
+
+        /// Only sessions active after this time (RFC 3339, date, or relative such as 3d)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only sessions active before this time (same formats as --since)
+        #[arg(long)]
+        until: Option<String>,
+
+        /// Max sessions to return
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+
+    /// Manage the persistent search index
+    Index {
+        #[command(subcommand)]
+        action: IndexAction,
+    },
+
+    /// Compress sessions last modified before a cutoff in place (.jsonl.gz / .jsonl.zst); refs stay valid
+    Archive {
+        /// Archive sessions last modified before this time (RFC 3339, date, or relative such as 30d)
+        #[arg(long)]
+        before: String,
+
+        /// Compression format: zst, gz
+        #[arg(long, default_value = "zst")]
+        format: String,
+
+        /// Project IDs (default: all projects)
+        #[arg(long)]
+        project: Option<Vec<String>>,
+
+        /// Only list the sessions that would be archived
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Inspect the configuration file (defaults, roots, access lists, redaction rules, output roots)
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print the effective configuration and where each value comes from
+    Show,
+
+    /// Check a config file for syntax errors, unknown keys and invalid patterns
+    Validate {
+        /// Config file (default: $MCP_CLAUDE_HISTORY_CONFIG or <config dir>/mcp-claude-history/config.toml)
+        file: Option<std::path::PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum IndexAction {
+    /// Build the index, re-indexing only lines appended since the last build
+    Build {
+        /// Project IDs (default: all projects)
+        #[arg(long)]
+        project: Option<Vec<String>>,
+    },
+
+    /// Show index coverage (fresh / appended / stale / missing files)
+    Status {
+        /// Project IDs (default: all projects)
+        #[arg(long)]
+        project: Option<Vec<String>>,
+    },
+
+    /// Drop and rebuild the index from scratch
+    Rebuild {
+        /// Project IDs (default: all projects)
+        #[arg(long)]
+        project: Option<Vec<String>>,
     },
 }
 
@@ -203,21 +689,57 @@ enum Commands {
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
+    // validate 在配置文件有误时也要能运行，不走 Config::load
+    if let Some(Commands::Config {
+        action: ConfigAction::Validate { file },
+    }) = &cli.command
+    {
+        return exit_with(validate(file.clone()));
+    }
+
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => return exit_with(serialize_result::<(), _>(Err(e))),
+    };
+    for spec in &cli.root {
+        config.add_root(Root::parse(spec));
+        config.set_source("roots", "cli:--root");
+    }
+    if cli.current_only {
+        config.access.current_only = true;
+        config.set_source("access.current_only", "cli:--current-only");
+    }
+    if let Some(timezone) = &cli.timezone {
+        match TimeZone::parse(timezone) {
+            Ok(timezone) => config.defaults.timezone = timezone,
+            Err(e) => return exit_with(serialize_result::<(), _>(Err(e))),
+        }
+        config.set_source("defaults.timezone", "cli:--timezone");
+    }
+
     // MCP 服务器模式（用 rmcp SDK 替代手写 JSON-RPC）
     if cli.mcp {
-        return run_mcp_server_rmcp().await;
+        return run_mcp_server_rmcp(config).await;
     }
 
-    let config = Config::from_env();
+    // 共享的 HTTP MCP 服务器（默认只绑定回环地址）
+    if let Some(addr) = cli.http {
+        return run_mcp_server_http(config, parse_http_addr(&addr)?).await;
+    }
 
     // 无子命令时默认 MCP 模式
     let command = match cli.command {
         Some(cmd) => cmd,
         None => {
-            return run_mcp_server_rmcp().await;
+            return run_mcp_server_rmcp(config).await;
         }
     };
 
+    let redactor = match Redactor::optional(&config, cli.redact) {
+        Ok(redactor) => redactor,
+        Err(e) => return exit_with(serialize_result::<(), _>(Err(e))),
+    };
+
     let result: Result<String, String> = match command {
         Commands::Search {
             pattern,
@@ -234,17 +756,28 @@ async fn main() -> anyhow::Result<()> {
             subagents,
             offset,
             limit,
+            sort,
+            group_by,
+            include_thinking,
             max_content,
             max_total,
         } => {
-            let params = SearchParams {
+            let (since, until) = match parse_time_window(since.as_deref(), until.as_deref(), config.defaults.timezone) {
+                Ok(window) => window,
+                Err(e) => return exit_with(serialize_result::<(), _>(Err(e))),
+            };
+            let parsed = SortOrder::parse(&sort)
+                .and_then(|sort| Ok((sort, SearchGroup::parse(group_by.as_deref().unwrap_or_default())?)));
+            let params = |(sort, group_by)| SearchParams {
                 pattern,
                 projects: project.unwrap_or_default(),
                 all_projects: all,
                 sessions: sessions.unwrap_or_default(),
-                since: since.and_then(|s| parse_iso_utc(&s)),
-                until: until.and_then(|s| parse_iso_utc(&s)),
-                types: types.split(',').map(|s| s.trim().to_string()).collect(),
+                since,
+                until,
+                types: types
+                    .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
+                    .unwrap_or_else(|| config.defaults.types.clone()),
                 subtypes: subtypes
                     .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
                     .unwrap_or_default(),
@@ -253,13 +786,17 @@ async fn main() -> anyhow::Result<()> {
                 case_sensitive,
                 offset,
                 limit,
-                max_content,
-                max_content_tool_result: 500,
-                max_total,
+                max_content: max_content.unwrap_or(config.defaults.max_content),
+                max_content_tool_result: config.defaults.max_content_tool_result,
+                max_total: max_total.unwrap_or(config.defaults.max_total),
                 subagents,
+                sort,
+                group_by,
+                include_thinking,
+                redactor,
             };
 
-            serialize_result(search(&config, params))
+            serialize_result(parsed.and_then(|parsed| search(&config, params(parsed))))
         }
 
         Commands::Get {
@@ -267,6 +804,7 @@ async fn main() -> anyhow::Result<()> {
             range,
             output,
             project,
+            include_thinking,
         } => {
             let range = range.and_then(|s| parse_range(&s));
 
@@ -275,6 +813,8 @@ async fn main() -> anyhow::Result<()> {
                 range,
                 output,
                 project,
+                redactor,
+                include_thinking,
             };
 
             serialize_result(get(&config, params))
@@ -286,10 +826,12 @@ async fn main() -> anyhow::Result<()> {
             after,
             until_type,
             direction,
+            follow,
             types,
             pattern,
             regex,
             case_sensitive,
+            include_thinking,
             project,
             max_content,
             max_total,
@@ -300,25 +842,430 @@ async fn main() -> anyhow::Result<()> {
                 after,
                 until_type,
                 direction,
+                follow,
                 project,
                 types: types
                     .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
                     .unwrap_or_default(),
-                max_content,
-                max_total,
+                max_content: max_content.unwrap_or(config.defaults.max_content),
+                max_total: max_total.unwrap_or(config.defaults.max_total),
                 pattern,
                 regex,
                 case_sensitive,
+                include_thinking,
+                redactor,
             };
 
             serialize_result(context(&config, params))
         }
 
+        Commands::Export {
+            session,
+            thread,
+            project,
+            format,
+            images,
+            lines,
+            types,
+            include_thinking,
+            output,
+        } => {
+            let params = |format, images| ExportParams {
+                session,
+                thread,
+                project,
+                format,
+                images,
+                lines: lines.map(|s| Range::parse_ranges(&s)).unwrap_or_default(),
+                types: types
+                    .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
+                    .unwrap_or_default(),
+                output,
+                redactor,
+                include_thinking,
+            };
+
+            let parsed = ExportFormat::parse(&format).and_then(|f| Ok((f, ImageMode::parse(&images)?)));
+            serialize_result(parsed.and_then(|(format, images)| export(&config, params(format, images))))
+        }
+
+        Commands::Thread {
+            r#ref,
+            types,
+            project,
+            max_content,
+            max_total,
+        } => {
+            let params = ThreadParams {
+                r#ref,
+                project,
+                types: types
+                    .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
+                    .unwrap_or_default(),
+                max_content: max_content.unwrap_or(config.defaults.max_content),
+                max_total: max_total.unwrap_or(config.defaults.max_total),
+                redactor,
+            };
+
+            serialize_result(thread(&config, params))
+        }
+
+        Commands::Tools {
+            project,
+            all,
+            sessions,
+            since,
+            until,
+            name,
+            is_error,
+            input,
+            subagents,
+            offset,
+            limit,
+            max_content,
+            max_total,
+        } => {
+            let (since, until) = match parse_time_window(since.as_deref(), until.as_deref(), config.defaults.timezone) {
+                Ok(window) => window,
+                Err(e) => return exit_with(serialize_result::<(), _>(Err(e))),
+            };
+            let params = ToolCallsParams {
+                projects: project.unwrap_or_default(),
+                all_projects: all,
+                sessions: sessions.unwrap_or_default(),
+                since,
+                until,
+                names: name
+                    .map(|n| n.split(',').map(|s| s.trim().to_string()).collect())
+                    .unwrap_or_default(),
+                is_error,
+                input_filters: input,
+                subagents,
+                offset,
+                limit,
+                max_content: max_content.unwrap_or(config.defaults.max_content),
+                max_total: max_total.unwrap_or(config.defaults.max_total),
+                redactor,
+            };
+
+            serialize_result(tool_calls(&config, params))
+        }
+
+        Commands::Files {
+            path,
+            project,
+            all,
+            sessions,
+            op,
+            since,
+            until,
+            subagents,
+            offset,
+            limit,
+        } => {
+            let (since, until) = match parse_time_window(since.as_deref(), until.as_deref(), config.defaults.timezone) {
+                Ok(window) => window,
+                Err(e) => return exit_with(serialize_result::<(), _>(Err(e))),
+            };
+            let ops = op
+                .as_deref()
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|o| !o.is_empty())
+                .map(FileOp::parse)
+                .collect::<Result<Vec<_>, _>>();
+            let result = ops.and_then(|ops| {
+                let params = FilesParams {
+                    projects: project.unwrap_or_default(),
+                    all_projects: all,
+                    sessions: sessions.unwrap_or_default(),
+                    path,
+                    ops,
+                    since,
+                    until,
+                    subagents,
+                    offset,
+                    limit,
+                };
+                files(&config, params)
+            });
+            serialize_result(result)
+        }
+
+        Commands::FileVersions {
+            path,
+            project,
+            all,
+            sessions,
+            since,
+            until,
+            subagents,
+            version,
+            diff,
+            max_total,
+        } => {
+            let (since, until) = match parse_time_window(since.as_deref(), until.as_deref(), config.defaults.timezone) {
+                Ok(window) => window,
+                Err(e) => return exit_with(serialize_result::<(), _>(Err(e))),
+            };
+            let result = match diff.as_deref().map(|s| parse_range(s).ok_or(s)) {
+                Some(Err(s)) => Err(ErrorResponse {
+                    error: "invalid_range".to_string(),
+                    message: format!("无效的版本范围: {}（格式为 from-to）", s),
+                    available: None,
+                }),
+                diff => {
+                    let params = FileVersionsParams {
+                        path,
+                        projects: project.unwrap_or_default(),
+                        all_projects: all,
+                        sessions: sessions.unwrap_or_default(),
+                        since,
+                        until,
+                        subagents,
+                        version,
+                        diff: diff.and_then(Result::ok),
+                        max_total: max_total.unwrap_or(config.defaults.file_versions_max_total),
+                    };
+                    file_versions(&config, params).map(|r| redacted(r, redactor.as_ref()))
+                }
+            };
+            serialize_result(result)
+        }
+
+        Commands::Usage {
+            project,
+            all,
+            sessions,
+            since,
+            until,
+            group_by,
+            subagents,
+            pricing,
+            format,
+        } => {
+            let (since, until) = match parse_time_window(since.as_deref(), until.as_deref(), config.defaults.timezone) {
+                Ok(window) => window,
+                Err(e) => return exit_with(serialize_result::<(), _>(Err(e))),
+            };
+            let parsed = group_by
+                .split(',')
+                .map(str::trim)
+                .filter(|g| !g.is_empty())
+                .map(UsageGroup::parse)
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|groups| Ok((groups, UsageFormat::parse(&format)?)));
+            match parsed {
+                Ok((group_by, format)) => {
+                    let params = UsageParams {
+                        projects: project.unwrap_or_default(),
+                        all_projects: all,
+                        sessions: sessions.unwrap_or_default(),
+                        since,
+                        until,
+                        group_by,
+                        subagents,
+                        pricing,
+                    };
+                    match usage(&config, params) {
+                        Ok(response) if format == UsageFormat::Csv => Ok(render_csv(&response).trim_end().to_string()),
+                        result => serialize_result(result),
+                    }
+                }
+                Err(e) => serialize_result::<(), _>(Err(e)),
+            }
+        }
+
+        Commands::Timeline {
+            pattern,
+            project,
+            all,
+            sessions,
+            since,
+            until,
+            types,
+            subtypes,
+            regex,
+            case_sensitive,
+            subagents,
+            bucket,
+            heatmap,
+        } => {
+            let (since, until) = match parse_time_window(since.as_deref(), until.as_deref(), config.defaults.timezone) {
+                Ok(window) => window,
+                Err(e) => return exit_with(serialize_result::<(), _>(Err(e))),
+            };
+            let result = BucketSize::parse(&bucket).and_then(|bucket| {
+                let params = TimelineParams {
+                    filter: SearchParams {
+                        pattern,
+                        projects: project.unwrap_or_default(),
+                        all_projects: all,
+                        sessions: sessions.unwrap_or_default(),
+                        since,
+                        until,
+                        types: types
+                            .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
+                            .unwrap_or_else(|| config.defaults.types.clone()),
+                        subtypes: subtypes
+                            .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
+                            .unwrap_or_default(),
+                        use_regex: regex,
+                        case_sensitive,
+                        subagents,
+                        ..Default::default()
+                    },
+                    bucket,
+                    heatmap,
+                };
+                timeline(&config, params)
+            });
+            serialize_result(result)
+        }
+
+        Commands::Tail {
+            pattern,
+            project,
+            sessions,
+            types,
+            subtypes,
+            regex,
+            case_sensitive,
+            subagents,
+            from_start,
+            state,
+            interval,
+            max_content,
+        } => {
+            let cursors = match state.as_deref().map(load_state).transpose() {
+                Ok(cursors) => cursors.unwrap_or_default(),
+                Err(e) => return exit_with(serialize_result::<(), _>(Err(e))),
+            };
+            let params = TailParams {
+                projects: project.unwrap_or_default(),
+                sessions: sessions.unwrap_or_default(),
+                pattern,
+                types: types
+                    .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
+                    .unwrap_or_else(|| config.defaults.types.clone()),
+                subtypes: subtypes
+                    .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
+                    .unwrap_or_default(),
+                use_regex: regex,
+                case_sensitive,
+                subagents,
+                from_start,
+                cursors,
+                max_content: max_content.unwrap_or(config.defaults.max_content),
+                redactor,
+            };
+            match Tailer::new(&config, params) {
+                Ok(tailer) => match follow(tailer, state.as_deref(), std::time::Duration::from_millis(interval)) {
+                    Ok(()) => return Ok(()),
+                    Err(e) => serialize_result::<(), _>(Err(e)),
+                },
+                Err(e) => serialize_result::<(), _>(Err(e)),
+            }
+        }
+
         Commands::Projects => serialize_result(list_projects(&config)),
 
-        Commands::Sessions { project } => serialize_result(list_sessions(&config, project.as_deref())),
+        Commands::Sessions {
+            project,
+            sort,
+            branch,
+            model,
+            min_messages,
+            since,
+            until,
+            limit,
+        } => {
+            let (since, until) = match parse_time_window(since.as_deref(), until.as_deref(), config.defaults.timezone) {
+                Ok(window) => window,
+                Err(e) => return exit_with(serialize_result::<(), _>(Err(e))),
+            };
+            let result = SessionSort::parse(&sort).and_then(|sort| {
+                let params = SessionsParams {
+                    project,
+                    sort,
+                    branch,
+                    model,
+                    min_messages,
+                    since,
+                    until,
+                    limit,
+                    redactor,
+                };
+                list_sessions(&config, params)
+            });
+            serialize_result(result)
+        }
+
+        Commands::Index { action } => {
+            let (project, rebuild, status) = match action {
+                IndexAction::Build { project } => (project, false, false),
+                IndexAction::Status { project } => (project, false, true),
+                IndexAction::Rebuild { project } => (project, true, false),
+            };
+            // 未指定项目时覆盖全部项目
+            let projects = project.unwrap_or_default();
+            match get_project_dirs(&config, &projects, projects.is_empty()) {
+                Ok(dirs) if status => serialize_result::<_, ()>(Ok(index_status(&config, &dirs))),
+                Ok(dirs) => serialize_result(build_index(&config, &dirs, rebuild)),
+                Err(e) => serialize_result::<(), _>(Err(e)),
+            }
+        }
+
+        Commands::Archive {
+            before,
+            format,
+            project,
+            dry_run,
+        } => {
+            let params = parse_time_expr(&before, config.defaults.timezone, chrono::Utc::now()).and_then(|before| {
+                Ok(ArchiveParams {
+                    before,
+                    format: parse_format(&format)?,
+                    dry_run,
+                })
+            });
+            // 未指定项目时覆盖全部项目
+            let projects = project.unwrap_or_default();
+            let result = params.and_then(|params| {
+                let dirs = get_project_dirs(&config, &projects, projects.is_empty())?;
+                archive(&config, &dirs, params)
+            });
+            serialize_result(result)
+        }
+
+        Commands::Config { action } => match action {
+            ConfigAction::Show => serialize_result::<_, ()>(Ok(config.show())),
+            ConfigAction::Validate { file } => validate(file),
+        },
     };
 
+    exit_with(result)
+}
+
+/// 校验配置文件；无效时以错误退出
+fn validate(file: Option<std::path::PathBuf>) -> Result<String, String> {
+    let Some(path) = file.or_else(config_file_path) else {
+        return serialize_result::<(), _>(Err(ErrorResponse {
+            error: "invalid_config".to_string(),
+            message: "无法确定配置文件路径，请显式指定".to_string(),
+            available: None,
+        }));
+    };
+    let response = validate_config_file(&path);
+    if response.valid {
+        serialize_result::<_, ()>(Ok(response))
+    } else {
+        serialize_result::<(), _>(Err(response))
+    }
+}
+
+fn exit_with(result: Result<String, String>) -> anyhow::Result<()> {
     match result {
         Ok(output) => {
             println!("{}", output);
@@ -330,3 +1277,35 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 }
+
+/// tail 主循环：每条新消息输出一行 JSON，直到 stdout 关闭（如管道下游退出）
+fn follow(
+    mut tailer: Tailer,
+    state: Option<&std::path::Path>,
+    interval: std::time::Duration,
+) -> Result<(), ErrorResponse> {
+    use std::io::Write;
+
+    let mut stdout = std::io::stdout().lock();
+    let mut saved = None;
+    loop {
+        for result in tailer.poll() {
+            let line = serde_json::to_string(&result).unwrap_or_default();
+            if writeln!(stdout, "{}", line).and_then(|_| stdout.flush()).is_err() {
+                return Ok(());
+            }
+        }
+        if let Some(state) = state {
+            let cursors = tailer.cursors();
+            if saved.as_ref() != Some(&cursors) {
+                save_state(state, &cursors).map_err(|e| ErrorResponse {
+                    error: "io_error".to_string(),
+                    message: format!("无法写入状态文件 {}: {}", state.display(), e),
+                    available: None,
+                })?;
+                saved = Some(cursors);
+            }
+        }
+        std::thread::sleep(interval);
+    }
+}
@@ -1,24 +1,52 @@
 //! rmcp 路径：用 SDK 替代手写 JSON-RPC（mcp.rs）
 //!
-//! 业务函数（search/get/context/projects/sessions）保持同步，通过
+//! 业务函数（search/get/context/export/thread/tools/files/usage/projects/sessions）保持同步，通过
 //! tokio::task::spawn_blocking 包装，避免阻塞 rmcp 异步运行时
+//!
+//! 工具结果同时返回 structuredContent（outputSchema 由响应类型派生）和等价的 JSON 文本；
+//! 业务错误（ErrorResponse）标记 isError
+//!
+//! resources（history:// URI）见 resources.rs，同样经 spawn_blocking 调用
+//!
+//! history_tail 启动后台任务轮询会话文件，新消息通过 notifications/message（logger = history_tail）推送
+//!
+//! 返回消息内容的工具默认脱敏（redact=false 关闭），resources 总是脱敏
 
+use rmcp::transport::streamable_http_server::{
+    StreamableHttpServerConfig, StreamableHttpService, session::local::LocalSessionManager,
+};
 use rmcp::{
-    handler::server::{router::tool::ToolRouter, wrapper::Parameters}, model::*, schemars,
-    tool,
-    tool_handler,
-    tool_router, ErrorData as McpError, ServerHandler, ServiceExt,
+    ErrorData as McpError, Peer, RoleServer, ServerHandler, ServiceExt,
+    handler::server::{router::tool::ToolRouter, tool::schema_for_type, wrapper::Parameters},
+    model::*,
+    schemars,
+    service::RequestContext,
+    tool, tool_handler, tool_router,
 };
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 
 use crate::config::Config;
-use crate::context::{context, ContextParams};
-use crate::get::{get, GetParams};
+use crate::context::{ContextParams, context};
+use crate::export::{ExportFormat, ExportParams, ImageMode, export};
+use crate::file_versions::{FileVersionsParams, file_versions};
+use crate::files::{FileOp, FilesParams, files};
+use crate::get::{GetParams, get};
 use crate::projects::list_projects;
-use crate::search::{search, SearchParams};
-use crate::sessions::list_sessions;
-use crate::types::Range;
-use crate::utils::parse_iso_utc;
+use crate::redact::{Redactor, redacted};
+use crate::resources::{list_resources, read_resource, resource_templates};
+use crate::search::{SearchGroup, SearchParams, SortOrder, search};
+use crate::sessions::{SessionSort, SessionsParams, list_sessions};
+use crate::tail::{TailCursor, TailParams, Tailer};
+use crate::thread::{ThreadParams, thread};
+use crate::time::parse_time_window;
+use crate::timeline::{BucketSize, TimelineParams, timeline};
+use crate::tool_calls::{ToolCallsParams, tool_calls};
+use crate::types::*;
+use crate::usage::{UsageFormat, UsageGroup, UsageParams, render_csv, usage};
 use crate::utils::parse_range;
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
@@ -31,8 +59,10 @@ pub struct SearchToolParams {
     pub all: Option<bool>,
     #[serde(default)]
     pub sessions: Option<String>,
+    /// RFC 3339, date or date-time in the configured timezone, or relative: 2h, 3d, yesterday, last monday
     #[serde(default)]
     pub since: Option<String>,
+    /// Same formats as since
     #[serde(default)]
     pub until: Option<String>,
     #[serde(default)]
@@ -55,6 +85,20 @@ pub struct SearchToolParams {
     pub max_total: Option<usize>,
     #[serde(default)]
     pub subagents: Option<bool>,
+    /// time_asc (default), time_desc or relevance (BM25)
+    #[serde(default)]
+    pub sort: Option<String>,
+    /// "session": one entry per session (hit count, first/last ref, best snippet, topic, time span);
+    /// offset / limit then page over sessions
+    #[serde(default)]
+    pub group_by: Option<String>,
+    /// Also match thinking blocks and include them as a [THINKING] section, default false
+    /// (the `thinking:` query field searches thinking without this)
+    #[serde(default)]
+    pub include_thinking: Option<bool>,
+    /// Redact secrets (API keys, tokens, passwords, private keys, high-entropy strings) in returned content, default true
+    #[serde(default)]
+    pub redact: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
@@ -66,6 +110,12 @@ pub struct GetToolParams {
     pub output: Option<String>,
     #[serde(default)]
     pub project: Option<String>,
+    /// Prepend thinking blocks as a [THINKING] section, default false
+    #[serde(default)]
+    pub include_thinking: Option<bool>,
+    /// Redact secrets (API keys, tokens, passwords, private keys, high-entropy strings) in returned content, default true
+    #[serde(default)]
+    pub redact: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
@@ -79,6 +129,9 @@ pub struct ContextToolParams {
     pub until_type: Option<String>,
     #[serde(default)]
     pub direction: Option<String>,
+    /// file (default, line order) or parent (walk the parentUuid chain)
+    #[serde(default)]
+    pub follow: Option<String>,
     #[serde(default)]
     pub types: Option<String>,
     #[serde(default)]
@@ -87,12 +140,266 @@ pub struct ContextToolParams {
     pub regex: Option<bool>,
     #[serde(default)]
     pub case_sensitive: Option<bool>,
+    /// Show thinking blocks as a [THINKING] section (pattern then matches them too), default false
+    #[serde(default)]
+    pub include_thinking: Option<bool>,
+    #[serde(default)]
+    pub project: Option<String>,
+    #[serde(default)]
+    pub max_content: Option<usize>,
+    #[serde(default)]
+    pub max_total: Option<usize>,
+    /// Redact secrets (API keys, tokens, passwords, private keys, high-entropy strings) in returned content, default true
+    #[serde(default)]
+    pub redact: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ExportToolParams {
+    /// Session ID or prefix (exports the whole session); exclusive with `thread`
+    #[serde(default)]
+    pub session: Option<String>,
+    /// Ref: export the parentUuid chain ending at this message; exclusive with `session`
+    #[serde(default)]
+    pub thread: Option<String>,
+    #[serde(default)]
+    pub project: Option<String>,
+    /// markdown (default), html or json
+    #[serde(default)]
+    pub format: Option<String>,
+    /// inline (default, data URI), files or none
+    #[serde(default)]
+    pub images: Option<String>,
+    #[serde(default)]
+    pub lines: Option<String>,
+    #[serde(default)]
+    pub types: Option<String>,
+    /// Include thinking blocks (redacted ones as placeholders), default false
+    #[serde(default)]
+    pub include_thinking: Option<bool>,
+    /// Output directory (same rules as history_get output), default "exports" under the controlled temp dir
+    #[serde(default)]
+    pub output: Option<String>,
+    /// Redact secrets (API keys, tokens, passwords, private keys, high-entropy strings) in returned content, default true
+    #[serde(default)]
+    pub redact: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ThreadToolParams {
+    pub r#ref: String,
+    #[serde(default)]
+    pub types: Option<String>,
     #[serde(default)]
     pub project: Option<String>,
     #[serde(default)]
     pub max_content: Option<usize>,
     #[serde(default)]
     pub max_total: Option<usize>,
+    /// Redact secrets (API keys, tokens, passwords, private keys, high-entropy strings) in returned content, default true
+    #[serde(default)]
+    pub redact: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ToolsToolParams {
+    #[serde(default)]
+    pub project: Option<String>,
+    #[serde(default)]
+    pub all: Option<bool>,
+    #[serde(default)]
+    pub sessions: Option<String>,
+    /// RFC 3339, date or date-time in the configured timezone, or relative: 2h, 3d, yesterday, last monday
+    #[serde(default)]
+    pub since: Option<String>,
+    /// Same formats as since
+    #[serde(default)]
+    pub until: Option<String>,
+    /// Tool names, comma separated (exact match, e.g. "Bash,Edit")
+    #[serde(default)]
+    pub name: Option<String>,
+    /// true = only failed calls, false = only successful calls
+    #[serde(default)]
+    pub is_error: Option<bool>,
+    /// Input field filters `field=regex` (dotted path like "edits.0.old_string"), all must match
+    #[serde(default)]
+    pub input: Option<Vec<String>>,
+    #[serde(default)]
+    pub subagents: Option<bool>,
+    #[serde(default)]
+    pub offset: Option<usize>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub max_content: Option<usize>,
+    #[serde(default)]
+    pub max_total: Option<usize>,
+    /// Redact secrets (API keys, tokens, passwords, private keys, high-entropy strings) in returned content, default true
+    #[serde(default)]
+    pub redact: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct FilesToolParams {
+    #[serde(default)]
+    pub project: Option<String>,
+    #[serde(default)]
+    pub all: Option<bool>,
+    /// Session IDs or 8-char prefixes, comma separated ("files touched by session X")
+    #[serde(default)]
+    pub sessions: Option<String>,
+    /// Path glob ("sessions that touched P"): absolute globs match the whole path, relative ones a path suffix
+    /// (e.g. "src/*.rs", "**/Cargo.toml")
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Operations, comma separated: read, write, edit, bash
+    #[serde(default)]
+    pub op: Option<String>,
+    /// RFC 3339, date or date-time in the configured timezone, or relative: 2h, 3d, yesterday, last monday
+    #[serde(default)]
+    pub since: Option<String>,
+    /// Same formats as since
+    #[serde(default)]
+    pub until: Option<String>,
+    #[serde(default)]
+    pub subagents: Option<bool>,
+    #[serde(default)]
+    pub offset: Option<usize>,
+    /// Max touches returned (default 100); the per-file summary always covers all matches
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct FileVersionsToolParams {
+    /// File path or glob (same rules as history_files); must resolve to a single file
+    pub path: String,
+    #[serde(default)]
+    pub project: Option<String>,
+    #[serde(default)]
+    pub all: Option<bool>,
+    /// Session IDs or 8-char prefixes, comma separated
+    #[serde(default)]
+    pub sessions: Option<String>,
+    /// RFC 3339, date or date-time in the configured timezone, or relative: 2h, 3d, yesterday, last monday
+    #[serde(default)]
+    pub since: Option<String>,
+    /// Same formats as since
+    #[serde(default)]
+    pub until: Option<String>,
+    #[serde(default)]
+    pub subagents: Option<bool>,
+    /// Return only this version's content
+    #[serde(default)]
+    pub version: Option<usize>,
+    /// Unified diff between two versions, "from-to" (0 = empty file before the first version), e.g. "1-3"
+    #[serde(default)]
+    pub diff: Option<String>,
+    /// Max total content chars across versions (default from config)
+    #[serde(default)]
+    pub max_total: Option<usize>,
+    /// Redact secrets in returned content, default true
+    #[serde(default)]
+    pub redact: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct UsageToolParams {
+    #[serde(default)]
+    pub project: Option<String>,
+    #[serde(default)]
+    pub all: Option<bool>,
+    #[serde(default)]
+    pub sessions: Option<String>,
+    /// RFC 3339, date or date-time in the configured timezone, or relative: 2h, 3d, yesterday, last monday
+    #[serde(default)]
+    pub since: Option<String>,
+    /// Same formats as since
+    #[serde(default)]
+    pub until: Option<String>,
+    /// Group keys, comma separated: model (default), session, project, day; "" for totals only
+    #[serde(default)]
+    pub group_by: Option<String>,
+    #[serde(default)]
+    pub subagents: Option<bool>,
+    /// json (default) or csv
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct TimelineToolParams {
+    /// Filter pattern (same query syntax as history_search); empty counts every message
+    #[serde(default)]
+    pub pattern: Option<String>,
+    #[serde(default)]
+    pub project: Option<String>,
+    #[serde(default)]
+    pub all: Option<bool>,
+    #[serde(default)]
+    pub sessions: Option<String>,
+    /// RFC 3339, date or date-time in the configured timezone, or relative: 2h, 3d, yesterday, last monday
+    #[serde(default)]
+    pub since: Option<String>,
+    /// Same formats as since
+    #[serde(default)]
+    pub until: Option<String>,
+    #[serde(default)]
+    pub types: Option<String>,
+    #[serde(default)]
+    pub subtypes: Option<String>,
+    #[serde(default)]
+    pub regex: Option<bool>,
+    #[serde(default)]
+    pub case_sensitive: Option<bool>,
+    #[serde(default)]
+    pub subagents: Option<bool>,
+    /// Bucket size: hour, day (default) or week (ISO weeks, starting Monday), in the configured timezone
+    #[serde(default)]
+    pub bucket: Option<String>,
+    /// Also return a weekday x hour heatmap (7 rows starting Monday, 24 columns)
+    #[serde(default)]
+    pub heatmap: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct TailToolParams {
+    /// Filter pattern (same query syntax as history_search)
+    #[serde(default)]
+    pub pattern: Option<String>,
+    #[serde(default)]
+    pub project: Option<String>,
+    #[serde(default)]
+    pub sessions: Option<String>,
+    #[serde(default)]
+    pub types: Option<String>,
+    #[serde(default)]
+    pub subtypes: Option<String>,
+    #[serde(default)]
+    pub regex: Option<bool>,
+    #[serde(default)]
+    pub case_sensitive: Option<bool>,
+    #[serde(default)]
+    pub subagents: Option<bool>,
+    /// Replay existing messages instead of starting at the end of each file
+    #[serde(default)]
+    pub from_start: Option<bool>,
+    /// Cursors returned by history_tail_stop (file path → byte offset / line), to resume where it stopped
+    #[serde(default)]
+    pub cursors: Option<BTreeMap<String, TailCursor>>,
+    /// Poll interval in milliseconds (default 1000)
+    #[serde(default)]
+    pub interval_ms: Option<u64>,
+    #[serde(default)]
+    pub max_content: Option<usize>,
+    /// Redact secrets (API keys, tokens, passwords, private keys, high-entropy strings) in returned content, default true
+    #[serde(default)]
+    pub redact: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct TailStopToolParams {
+    pub watch_id: String,
 }
 
 #[derive(Debug, Deserialize, schemars::JsonSchema, Default)]
@@ -102,57 +409,210 @@ pub struct ProjectsToolParams {}
 pub struct SessionsToolParams {
     #[serde(default)]
     pub project: Option<String>,
+    /// activity (default, most recent first), size, messages or tokens
+    #[serde(default)]
+    pub sort: Option<String>,
+    /// Only sessions that were on this git branch
+    #[serde(default)]
+    pub branch: Option<String>,
+    /// Only sessions that used a model containing this text
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Minimum number of messages
+    #[serde(default)]
+    pub min_messages: Option<usize>,
+    /// Only sessions active after this time (RFC 3339, date, or relative such as 3d)
+    #[serde(default)]
+    pub since: Option<String>,
+    /// Only sessions active before this time (same formats as since)
+    #[serde(default)]
+    pub until: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// Redact secrets (API keys, tokens, passwords, private keys, high-entropy strings) in returned content, default true
+    #[serde(default)]
+    pub redact: Option<bool>,
+}
+
+/// 运行中的 tail 任务
+struct TailWatch {
+    handle: tokio::task::JoinHandle<()>,
+    tailer: Arc<Mutex<Tailer>>,
+    sent: Arc<AtomicUsize>,
 }
 
 #[derive(Clone)]
 pub struct McpHistoryService {
     config: Config,
     tool_router: ToolRouter<Self>,
+    tails: Arc<Mutex<HashMap<String, TailWatch>>>,
+    next_tail: Arc<AtomicU64>,
+    /// 客户端通过 logging/setLevel 设置的最低级别
+    log_level: Arc<Mutex<LoggingLevel>>,
+    /// 启动时编译一次的脱敏器；规则无效时保留错误，需要脱敏的调用返回该错误
+    redactor: Arc<Result<Redactor, ErrorResponse>>,
 }
 
 fn comma_split(s: &str) -> Vec<String> {
     s.split(',').map(|x| x.trim().to_string()).collect()
 }
 
-fn pretty_or<E: serde::Serialize>(r: Result<impl serde::Serialize, E>) -> String {
-    match r {
-        Ok(v) => serde_json::to_string_pretty(&v).unwrap_or_default(),
-        Err(e) => serde_json::to_string_pretty(&e).unwrap_or_default(),
+/// outputSchema：MCP 要求根类型为 object，untagged enum（根为 anyOf）补上 "type": "object"
+fn output_schema<T: schemars::JsonSchema + 'static>() -> Arc<JsonObject> {
+    let mut schema = (*schema_for_type::<T>()).clone();
+    schema.entry("type").or_insert_with(|| "object".into());
+    Arc::new(schema)
+}
+
+/// 业务结果转为工具结果：JSON 同时放入 structuredContent 和文本内容，ErrorResponse 标记 isError
+fn structured<T: serde::Serialize>(result: Result<T, ErrorResponse>) -> Result<CallToolResult, McpError> {
+    // 文本直接由原类型序列化，保留字段顺序
+    let (text, value, is_error) = match &result {
+        Ok(v) => (serde_json::to_string_pretty(v), serde_json::to_value(v), false),
+        Err(e) => (serde_json::to_string_pretty(e), serde_json::to_value(e), true),
+    };
+    let value = value.map_err(|e| McpError::internal_error(format!("serialize error: {}", e), None))?;
+    let mut result = if is_error {
+        CallToolResult::structured_error(value)
+    } else {
+        CallToolResult::structured(value)
+    };
+    result.content = vec![Content::text(text.unwrap_or_default())];
+    Ok(result)
+}
+
+fn json_data(e: &ErrorResponse) -> serde_json::Value {
+    serde_json::to_value(e).unwrap_or_default()
+}
+
+fn level_rank(level: LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Debug => 0,
+        LoggingLevel::Info => 1,
+        LoggingLevel::Notice => 2,
+        LoggingLevel::Warning => 3,
+        LoggingLevel::Error => 4,
+        LoggingLevel::Critical => 5,
+        LoggingLevel::Alert => 6,
+        LoggingLevel::Emergency => 7,
     }
 }
 
-fn ok_text(text: String) -> Result<CallToolResult, McpError> {
-    Ok(CallToolResult::success(vec![Content::text(text)]))
+/// tail 后台任务：按间隔轮询，把新消息逐条推送给客户端；推送失败（连接已断开）即退出并注销 watch。
+/// 客户端把日志级别调到 info 以上时暂停轮询，游标停在原处，恢复后补发暂停期间的消息
+async fn run_tail(
+    watch_id: String,
+    tailer: Arc<Mutex<Tailer>>,
+    sent: Arc<AtomicUsize>,
+    interval: std::time::Duration,
+    peer: Peer<RoleServer>,
+    log_level: Arc<Mutex<LoggingLevel>>,
+    tails: Arc<Mutex<HashMap<String, TailWatch>>>,
+) {
+    'watch: loop {
+        tokio::time::sleep(interval).await;
+        let muted =
+            level_rank(*log_level.lock().unwrap_or_else(PoisonError::into_inner)) > level_rank(LoggingLevel::Info);
+        if muted {
+            continue;
+        }
+        let polled = tailer.clone();
+        let Ok(results) =
+            tokio::task::spawn_blocking(move || polled.lock().unwrap_or_else(PoisonError::into_inner).poll()).await
+        else {
+            break;
+        };
+        for result in results {
+            let data = serde_json::json!({ "watch_id": watch_id, "message": result });
+            let notification =
+                LoggingMessageNotificationParam::new(LoggingLevel::Info, data).with_logger("history_tail");
+            if peer.notify_logging_message(notification).await.is_err() {
+                break 'watch;
+            }
+            sent.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    tails.lock().unwrap_or_else(PoisonError::into_inner).remove(&watch_id);
+}
+
+impl Default for McpHistoryService {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl McpHistoryService {
+    /// 配置来自环境变量与配置文件；配置文件无效时拒绝访问全部项目（见 Config::from_env）
     pub fn new() -> Self {
+        Self::with_config(Config::from_env())
+    }
+
+    pub fn with_config(config: Config) -> Self {
+        let redactor = Arc::new(Redactor::load(&config));
+        Self::with_redactor(config, redactor)
+    }
+
+    /// 复用已编译的脱敏器（HTTP 模式每个会话一个实例，共享同一份规则）
+    fn with_redactor(config: Config, redactor: Arc<Result<Redactor, ErrorResponse>>) -> Self {
         Self {
-            config: Config::from_env(),
+            config,
             tool_router: Self::tool_router(),
+            tails: Arc::new(Mutex::new(HashMap::new())),
+            next_tail: Arc::new(AtomicU64::new(1)),
+            log_level: Arc::new(Mutex::new(LoggingLevel::Info)),
+            redactor,
+        }
+    }
+
+    /// 按调用的 redact 开关取用启动时编译的脱敏器
+    fn redactor(&self, enabled: bool) -> Result<Option<Redactor>, ErrorResponse> {
+        match self.redactor.as_ref() {
+            _ if !enabled => Ok(None),
+            Ok(redactor) => Ok(Some(redactor.clone())),
+            Err(e) => Err(e.clone()),
         }
     }
 }
 
 #[tool_router]
 impl McpHistoryService {
-    #[tool(description = "Search through Claude Code conversation history")]
+    #[tool(
+        description = "Search through Claude Code conversation history",
+        output_schema = output_schema::<SearchResponse>()
+    )]
     async fn history_search(&self, Parameters(p): Parameters<SearchToolParams>) -> Result<CallToolResult, McpError> {
         let cfg = self.config.clone();
 
         let projects: Vec<String> = p.project.as_deref().map(comma_split).unwrap_or_default();
         let sessions: Vec<String> = p.sessions.as_deref().map(comma_split).unwrap_or_default();
-        let types: Vec<String> = comma_split(p.types.as_deref().unwrap_or("assistant,user,summary"));
+        let types: Vec<String> = p
+            .types
+            .as_deref()
+            .map(comma_split)
+            .unwrap_or_else(|| self.config.defaults.types.clone());
         let subtypes: Vec<String> = p.subtypes.as_deref().map(comma_split).unwrap_or_default();
         let lines: Vec<Range> = p.lines.as_deref().map(Range::parse_ranges).unwrap_or_default();
+        let sort = match SortOrder::parse(p.sort.as_deref().unwrap_or("time_asc")) {
+            Ok(sort) => sort,
+            Err(e) => return structured(Err::<(), _>(e)),
+        };
+        let group_by = match SearchGroup::parse(p.group_by.as_deref().unwrap_or_default()) {
+            Ok(group_by) => group_by,
+            Err(e) => return structured(Err::<(), _>(e)),
+        };
 
-        let params = SearchParams {
+        let (since, until) =
+            match parse_time_window(p.since.as_deref(), p.until.as_deref(), self.config.defaults.timezone) {
+                Ok(window) => window,
+                Err(e) => return structured(Err::<(), _>(e)),
+            };
+        let mut params = SearchParams {
             pattern: p.pattern.unwrap_or_default(),
             projects,
             all_projects: p.all.unwrap_or(false),
             sessions,
-            since: p.since.as_deref().and_then(parse_iso_utc),
-            until: p.until.as_deref().and_then(parse_iso_utc),
+            since,
+            until,
             types,
             subtypes,
             lines,
@@ -160,92 +620,683 @@ impl McpHistoryService {
             case_sensitive: p.case_sensitive.unwrap_or(false),
             offset: p.offset.unwrap_or(0),
             limit: p.limit,
-            max_content: p.max_content.unwrap_or(4000),
-            max_content_tool_result: 500,
-            max_total: p.max_total.unwrap_or(40000),
+            max_content: p.max_content.unwrap_or(self.config.defaults.max_content),
+            max_content_tool_result: self.config.defaults.max_content_tool_result,
+            max_total: p.max_total.unwrap_or(self.config.defaults.max_total),
             subagents: p.subagents.unwrap_or(false),
+            sort,
+            group_by,
+            include_thinking: p.include_thinking.unwrap_or(false),
+            redactor: None,
         };
-        let result = tokio::task::spawn_blocking(move || search(&cfg, params))
-            .await
-            .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
-        ok_text(pretty_or(result))
+        let redactor = self.redactor(p.redact.unwrap_or(true));
+        let result = tokio::task::spawn_blocking(move || {
+            params.redactor = redactor?;
+            search(&cfg, params)
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
+        structured(result)
     }
 
-    #[tool(description = "Get full content of a message by ref")]
+    #[tool(
+        description = "Get full content of a message by ref",
+        output_schema = output_schema::<GetResponse>()
+    )]
     async fn history_get(&self, Parameters(p): Parameters<GetToolParams>) -> Result<CallToolResult, McpError> {
         let cfg = self.config.clone();
-        let params = GetParams {
+        let mut params = GetParams {
             r#ref: p.r#ref,
             range: p.range.as_deref().and_then(parse_range),
             output: p.output,
             project: p.project,
+            redactor: None,
+            include_thinking: p.include_thinking.unwrap_or(false),
         };
-        let result = tokio::task::spawn_blocking(move || get(&cfg, params))
-            .await
-            .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
-        ok_text(pretty_or(result))
+        let redactor = self.redactor(p.redact.unwrap_or(true));
+        let result = tokio::task::spawn_blocking(move || {
+            params.redactor = redactor?;
+            get(&cfg, params)
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
+        structured(result)
     }
 
-    #[tool(description = "Get surrounding messages for context")]
+    #[tool(
+        description = "Get surrounding messages for context. Task calls and their results carry subagent_ref (the \
+                       spawned subagent's first message); inside a subagent session, parent_ref points back to the \
+                       Task call",
+        output_schema = output_schema::<ContextResponse>()
+    )]
     async fn history_context(&self, Parameters(p): Parameters<ContextToolParams>) -> Result<CallToolResult, McpError> {
         let cfg = self.config.clone();
         let types: Vec<String> = p.types.as_deref().map(comma_split).unwrap_or_default();
-        let params = ContextParams {
+        let mut params = ContextParams {
             r#ref: p.r#ref,
             before: p.before,
             after: p.after,
             until_type: p.until_type,
             direction: p.direction.unwrap_or_else(|| "forward".to_string()),
+            follow: p.follow.unwrap_or_else(|| "file".to_string()),
             project: p.project,
             types,
-            max_content: p.max_content.unwrap_or(4000),
-            max_total: p.max_total.unwrap_or(40000),
+            max_content: p.max_content.unwrap_or(self.config.defaults.max_content),
+            max_total: p.max_total.unwrap_or(self.config.defaults.max_total),
             pattern: p.pattern,
             regex: p.regex.unwrap_or(false),
             case_sensitive: p.case_sensitive.unwrap_or(false),
+            include_thinking: p.include_thinking.unwrap_or(false),
+            redactor: None,
+        };
+        let redactor = self.redactor(p.redact.unwrap_or(true));
+        let result = tokio::task::spawn_blocking(move || {
+            params.redactor = redactor?;
+            context(&cfg, params)
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
+        structured(result)
+    }
+
+    #[tool(
+        description = "Export a session, line range or thread as a Markdown, HTML or JSON transcript file",
+        output_schema = output_schema::<ExportResponse>()
+    )]
+    async fn history_export(&self, Parameters(p): Parameters<ExportToolParams>) -> Result<CallToolResult, McpError> {
+        let cfg = self.config.clone();
+        let format = match ExportFormat::parse(p.format.as_deref().unwrap_or("markdown")) {
+            Ok(format) => format,
+            Err(e) => return structured(Err::<(), _>(e)),
+        };
+        let images = match ImageMode::parse(p.images.as_deref().unwrap_or("inline")) {
+            Ok(images) => images,
+            Err(e) => return structured(Err::<(), _>(e)),
+        };
+        let mut params = ExportParams {
+            session: p.session,
+            thread: p.thread,
+            project: p.project,
+            format,
+            images,
+            lines: p.lines.as_deref().map(Range::parse_ranges).unwrap_or_default(),
+            types: p.types.as_deref().map(comma_split).unwrap_or_default(),
+            output: p.output.unwrap_or_else(|| "exports".to_string()),
+            redactor: None,
+            include_thinking: p.include_thinking.unwrap_or(false),
+        };
+        let redactor = self.redactor(p.redact.unwrap_or(true));
+        let result = tokio::task::spawn_blocking(move || {
+            params.redactor = redactor?;
+            export(&cfg, params)
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
+        structured(result)
+    }
+
+    #[tool(
+        description = "Get the conversation chain ending at a ref, reconstructed from parentUuid (marks active path vs abandoned branches)",
+        output_schema = output_schema::<ThreadResponse>()
+    )]
+    async fn history_thread(&self, Parameters(p): Parameters<ThreadToolParams>) -> Result<CallToolResult, McpError> {
+        let cfg = self.config.clone();
+        let mut params = ThreadParams {
+            r#ref: p.r#ref,
+            project: p.project,
+            types: p.types.as_deref().map(comma_split).unwrap_or_default(),
+            max_content: p.max_content.unwrap_or(self.config.defaults.max_content),
+            max_total: p.max_total.unwrap_or(self.config.defaults.max_total),
+            redactor: None,
         };
-        let result = tokio::task::spawn_blocking(move || context(&cfg, params))
+        let redactor = self.redactor(p.redact.unwrap_or(true));
+        let result = tokio::task::spawn_blocking(move || {
+            params.redactor = redactor?;
+            thread(&cfg, params)
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
+        structured(result)
+    }
+
+    #[tool(
+        description = "List tool calls with full input, result text, is_error and latency (tool_use paired with its tool_result)",
+        output_schema = output_schema::<ToolCallsResponse>()
+    )]
+    async fn history_tools(&self, Parameters(p): Parameters<ToolsToolParams>) -> Result<CallToolResult, McpError> {
+        let cfg = self.config.clone();
+        let (since, until) =
+            match parse_time_window(p.since.as_deref(), p.until.as_deref(), self.config.defaults.timezone) {
+                Ok(window) => window,
+                Err(e) => return structured(Err::<(), _>(e)),
+            };
+        let mut params = ToolCallsParams {
+            projects: p.project.as_deref().map(comma_split).unwrap_or_default(),
+            all_projects: p.all.unwrap_or(false),
+            sessions: p.sessions.as_deref().map(comma_split).unwrap_or_default(),
+            since,
+            until,
+            names: p.name.as_deref().map(comma_split).unwrap_or_default(),
+            is_error: p.is_error,
+            input_filters: p.input.unwrap_or_default(),
+            subagents: p.subagents.unwrap_or(false),
+            offset: p.offset.unwrap_or(0),
+            limit: p.limit,
+            max_content: p.max_content.unwrap_or(self.config.defaults.max_content),
+            max_total: p.max_total.unwrap_or(self.config.defaults.max_total),
+            redactor: None,
+        };
+        let redactor = self.redactor(p.redact.unwrap_or(true));
+        let result = tokio::task::spawn_blocking(move || {
+            params.redactor = redactor?;
+            tool_calls(&cfg, params)
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
+        structured(result)
+    }
+
+    #[tool(
+        description = "Files read, written or edited by tool calls (Read, Write, Edit, MultiEdit, NotebookEdit, paths in \
+                       Bash commands): per-file summary plus each touch with ref, operation and timestamp. Filter by \
+                       session to see what it touched, or by path glob to find the sessions that touched a file",
+        output_schema = output_schema::<FilesResponse>()
+    )]
+    async fn history_files(&self, Parameters(p): Parameters<FilesToolParams>) -> Result<CallToolResult, McpError> {
+        let cfg = self.config.clone();
+        let ops = match p
+            .op
+            .as_deref()
+            .map(comma_split)
+            .unwrap_or_default()
+            .iter()
+            .filter(|o| !o.is_empty())
+            .map(|o| FileOp::parse(o))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(ops) => ops,
+            Err(e) => return structured(Err::<(), _>(e)),
+        };
+        let (since, until) =
+            match parse_time_window(p.since.as_deref(), p.until.as_deref(), self.config.defaults.timezone) {
+                Ok(window) => window,
+                Err(e) => return structured(Err::<(), _>(e)),
+            };
+        let params = FilesParams {
+            projects: p.project.as_deref().map(comma_split).unwrap_or_default(),
+            all_projects: p.all.unwrap_or(false),
+            sessions: p.sessions.as_deref().map(comma_split).unwrap_or_default(),
+            path: p.path,
+            ops,
+            since,
+            until,
+            subagents: p.subagents.unwrap_or(false),
+            offset: p.offset.unwrap_or(0),
+            limit: p.limit.unwrap_or(100),
+        };
+        let result = tokio::task::spawn_blocking(move || files(&cfg, params))
             .await
             .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
-        ok_text(pretty_or(result))
+        structured(result)
     }
 
-    #[tool(description = "List all projects with conversation history")]
+    #[tool(
+        description = "Reconstruct a file's contents by replaying Write/Edit/MultiEdit tool calls in order: every \
+                       intermediate version with ref and status (failed, interrupted or conflicting edits are listed \
+                       but not applied), one version's content, or a unified diff between two versions",
+        output_schema = output_schema::<FileVersionsResponse>()
+    )]
+    async fn history_file_versions(
+        &self,
+        Parameters(p): Parameters<FileVersionsToolParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let cfg = self.config.clone();
+        let diff = match p.diff.as_deref() {
+            Some(s) => match parse_range(s) {
+                Some(range) => Some(range),
+                None => {
+                    return structured(Err::<(), _>(ErrorResponse {
+                        error: "invalid_range".to_string(),
+                        message: format!("无效的版本范围: {}（格式为 from-to）", s),
+                        available: None,
+                    }));
+                }
+            },
+            None => None,
+        };
+        let (since, until) =
+            match parse_time_window(p.since.as_deref(), p.until.as_deref(), self.config.defaults.timezone) {
+                Ok(window) => window,
+                Err(e) => return structured(Err::<(), _>(e)),
+            };
+        let params = FileVersionsParams {
+            path: p.path,
+            projects: p.project.as_deref().map(comma_split).unwrap_or_default(),
+            all_projects: p.all.unwrap_or(false),
+            sessions: p.sessions.as_deref().map(comma_split).unwrap_or_default(),
+            since,
+            until,
+            subagents: p.subagents.unwrap_or(false),
+            version: p.version,
+            diff,
+            max_total: p.max_total.unwrap_or(self.config.defaults.file_versions_max_total),
+        };
+        let redactor = self.redactor(p.redact.unwrap_or(true));
+        let result = tokio::task::spawn_blocking(move || {
+            let redactor = redactor?;
+            file_versions(&cfg, params).map(|r| redacted(r, redactor.as_ref()))
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
+        structured(result)
+    }
+
+    #[tool(
+        description = "Token usage (input/output/cache) grouped by model, session, project or day, with estimated cost from a local pricing table",
+        output_schema = output_schema::<UsageResponse>()
+    )]
+    async fn history_usage(&self, Parameters(p): Parameters<UsageToolParams>) -> Result<CallToolResult, McpError> {
+        let cfg = self.config.clone();
+        let group_by = match p
+            .group_by
+            .as_deref()
+            .map(comma_split)
+            .unwrap_or_else(|| vec!["model".to_string()])
+            .iter()
+            .filter(|g| !g.is_empty())
+            .map(|g| UsageGroup::parse(g))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(groups) => groups,
+            Err(e) => return structured(Err::<(), _>(e)),
+        };
+        let format = match UsageFormat::parse(p.format.as_deref().unwrap_or("json")) {
+            Ok(format) => format,
+            Err(e) => return structured(Err::<(), _>(e)),
+        };
+        let (since, until) =
+            match parse_time_window(p.since.as_deref(), p.until.as_deref(), self.config.defaults.timezone) {
+                Ok(window) => window,
+                Err(e) => return structured(Err::<(), _>(e)),
+            };
+        let params = UsageParams {
+            projects: p.project.as_deref().map(comma_split).unwrap_or_default(),
+            all_projects: p.all.unwrap_or(false),
+            sessions: p.sessions.as_deref().map(comma_split).unwrap_or_default(),
+            since,
+            until,
+            group_by,
+            subagents: p.subagents.unwrap_or(false),
+            pricing: None,
+        };
+        let result = tokio::task::spawn_blocking(move || usage(&cfg, params))
+            .await
+            .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
+        match result {
+            // csv：structuredContent 仍为 JSON，文本内容换成 CSV
+            Ok(response) if format == UsageFormat::Csv => {
+                let csv = render_csv(&response);
+                let mut result = structured(Ok(response))?;
+                result.content = vec![Content::text(csv)];
+                Ok(result)
+            }
+            result => structured(result),
+        }
+    }
+
+    #[tool(
+        description = "Count matching messages per hour, day or week (same filters as history_search), with the sessions behind each bucket and an optional weekday x hour heatmap",
+        output_schema = output_schema::<TimelineResponse>()
+    )]
+    async fn history_timeline(
+        &self,
+        Parameters(p): Parameters<TimelineToolParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let cfg = self.config.clone();
+        let bucket = match BucketSize::parse(p.bucket.as_deref().unwrap_or("day")) {
+            Ok(bucket) => bucket,
+            Err(e) => return structured(Err::<(), _>(e)),
+        };
+        let (since, until) =
+            match parse_time_window(p.since.as_deref(), p.until.as_deref(), self.config.defaults.timezone) {
+                Ok(window) => window,
+                Err(e) => return structured(Err::<(), _>(e)),
+            };
+        let params = TimelineParams {
+            filter: SearchParams {
+                pattern: p.pattern.unwrap_or_default(),
+                projects: p.project.as_deref().map(comma_split).unwrap_or_default(),
+                all_projects: p.all.unwrap_or(false),
+                sessions: p.sessions.as_deref().map(comma_split).unwrap_or_default(),
+                since,
+                until,
+                types: p
+                    .types
+                    .as_deref()
+                    .map(comma_split)
+                    .unwrap_or_else(|| self.config.defaults.types.clone()),
+                subtypes: p.subtypes.as_deref().map(comma_split).unwrap_or_default(),
+                use_regex: p.regex.unwrap_or(false),
+                case_sensitive: p.case_sensitive.unwrap_or(false),
+                subagents: p.subagents.unwrap_or(false),
+                ..Default::default()
+            },
+            bucket,
+            heatmap: p.heatmap.unwrap_or(false),
+        };
+        let result = tokio::task::spawn_blocking(move || timeline(&cfg, params))
+            .await
+            .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
+        structured(result)
+    }
+
+    #[tool(
+        description = "Follow sessions as they are written: each new matching message is pushed as a notifications/message log entry (logger \"history_tail\") until history_tail_stop",
+        output_schema = output_schema::<TailStartResponse>()
+    )]
+    async fn history_tail(
+        &self,
+        Parameters(p): Parameters<TailToolParams>,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let cfg = self.config.clone();
+        let mut params = TailParams {
+            projects: p.project.as_deref().map(comma_split).unwrap_or_default(),
+            sessions: p.sessions.as_deref().map(comma_split).unwrap_or_default(),
+            pattern: p.pattern.unwrap_or_default(),
+            types: p
+                .types
+                .as_deref()
+                .map(comma_split)
+                .unwrap_or_else(|| self.config.defaults.types.clone()),
+            subtypes: p.subtypes.as_deref().map(comma_split).unwrap_or_default(),
+            use_regex: p.regex.unwrap_or(false),
+            case_sensitive: p.case_sensitive.unwrap_or(false),
+            subagents: p.subagents.unwrap_or(false),
+            from_start: p.from_start.unwrap_or(false),
+            cursors: p.cursors.unwrap_or_default(),
+            max_content: p.max_content.unwrap_or(self.config.defaults.max_content),
+            redactor: None,
+        };
+        let redactor = self.redactor(p.redact.unwrap_or(true));
+        let result = tokio::task::spawn_blocking(move || {
+            params.redactor = redactor?;
+            Tailer::new(&cfg, params)
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
+        let tailer = match result {
+            Ok(tailer) => tailer,
+            Err(e) => return structured(Err::<(), _>(e)),
+        };
+
+        let watch_id = format!("tail-{}", self.next_tail.fetch_add(1, Ordering::Relaxed));
+        let interval_ms = p.interval_ms.unwrap_or(1000).max(100);
+        let files = tailer.file_count();
+        let tailer = Arc::new(Mutex::new(tailer));
+        let sent = Arc::new(AtomicUsize::new(0));
+        // 持锁启动并登记，任务退出时的注销一定发生在登记之后
+        let mut tails = self.tails.lock().unwrap_or_else(PoisonError::into_inner);
+        let handle = tokio::spawn(run_tail(
+            watch_id.clone(),
+            tailer.clone(),
+            sent.clone(),
+            std::time::Duration::from_millis(interval_ms),
+            peer,
+            self.log_level.clone(),
+            self.tails.clone(),
+        ));
+        tails.insert(watch_id.clone(), TailWatch { handle, tailer, sent });
+        drop(tails);
+
+        structured(Ok(TailStartResponse {
+            watch_id,
+            files,
+            interval_ms,
+        }))
+    }
+
+    #[tool(
+        description = "Stop a history_tail watch; returns cursors that history_tail accepts to resume later",
+        output_schema = output_schema::<TailStopResponse>()
+    )]
+    async fn history_tail_stop(
+        &self,
+        Parameters(p): Parameters<TailStopToolParams>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut tails = self.tails.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(watch) = tails.remove(&p.watch_id) else {
+            let available: Vec<&String> = tails.keys().collect();
+            return structured(Err::<(), _>(ErrorResponse {
+                error: "tail_not_found".to_string(),
+                message: format!("tail 不存在: {}", p.watch_id),
+                available: Some(serde_json::json!(available)),
+            }));
+        };
+        drop(tails);
+
+        watch.handle.abort();
+        let cursors = watch.tailer.lock().unwrap_or_else(PoisonError::into_inner).cursors();
+        structured(Ok(TailStopResponse {
+            watch_id: p.watch_id,
+            messages_sent: watch.sent.load(Ordering::Relaxed),
+            cursors,
+        }))
+    }
+
+    #[tool(
+        description = "List all projects with conversation history",
+        output_schema = output_schema::<ProjectsResponse>()
+    )]
     async fn history_projects(&self, _: Parameters<ProjectsToolParams>) -> Result<CallToolResult, McpError> {
         let cfg = self.config.clone();
         let result = tokio::task::spawn_blocking(move || list_projects(&cfg))
             .await
             .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
-        ok_text(pretty_or(result))
+        structured(result)
     }
 
-    #[tool(description = "List sessions in a project")]
+    #[tool(
+        description = "List sessions in a project with summary metadata (message / tool-call counts, models, tokens, \
+                       branches, compact summaries, subagents). Filter by branch, model, min_messages and activity \
+                       window; sort by activity, size, messages or tokens",
+        output_schema = output_schema::<SessionsResponse>()
+    )]
     async fn history_sessions(
         &self,
         Parameters(p): Parameters<SessionsToolParams>,
     ) -> Result<CallToolResult, McpError> {
         let cfg = self.config.clone();
-        let project = p.project.clone();
-        let result = tokio::task::spawn_blocking(move || list_sessions(&cfg, project.as_deref()))
-            .await
-            .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
-        ok_text(pretty_or(result))
+        let sort = match SessionSort::parse(p.sort.as_deref().unwrap_or("activity")) {
+            Ok(sort) => sort,
+            Err(e) => return structured(Err::<(), _>(e)),
+        };
+        let (since, until) =
+            match parse_time_window(p.since.as_deref(), p.until.as_deref(), self.config.defaults.timezone) {
+                Ok(window) => window,
+                Err(e) => return structured(Err::<(), _>(e)),
+            };
+        let mut params = SessionsParams {
+            project: p.project,
+            sort,
+            branch: p.branch,
+            model: p.model,
+            min_messages: p.min_messages.unwrap_or(0),
+            since,
+            until,
+            limit: p.limit,
+            redactor: None,
+        };
+        let redactor = self.redactor(p.redact.unwrap_or(true));
+        let result = tokio::task::spawn_blocking(move || {
+            params.redactor = redactor?;
+            list_sessions(&cfg, params)
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
+        structured(result)
     }
 }
 
-#[tool_handler]
+#[tool_handler(router = self.tool_router)]
 impl ServerHandler for McpHistoryService {
     fn get_info(&self) -> ServerInfo {
-        ServerInfo::new(ServerCapabilities::builder().enable_tools().build())
-            .with_protocol_version(ProtocolVersion::LATEST)
-            .with_server_info(Implementation::new("mcp-claude-history", env!("CARGO_PKG_VERSION")))
-            .with_instructions("MCP server for searching Claude Code conversation history")
+        ServerInfo::new(
+            ServerCapabilities::builder()
+                .enable_tools()
+                .enable_resources()
+                .enable_logging()
+                .build(),
+        )
+        .with_protocol_version(ProtocolVersion::LATEST)
+        .with_server_info(Implementation::new("mcp-claude-history", env!("CARGO_PKG_VERSION")))
+        .with_instructions("MCP server for searching Claude Code conversation history")
+    }
+
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        let cfg = self.config.clone();
+        let resources = tokio::task::spawn_blocking(move || list_resources(&cfg))
+            .await
+            .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
+        Ok(ListResourcesResult::with_all_items(resources))
+    }
+
+    async fn list_resource_templates(
+        &self,
+        _request: Option<PaginatedRequestParams>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourceTemplatesResult, McpError> {
+        Ok(ListResourceTemplatesResult::with_all_items(resource_templates()))
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParams,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        let cfg = self.config.clone();
+        let redactor = self.redactor(true);
+        let result = tokio::task::spawn_blocking(move || {
+            let redactor = redactor?;
+            read_resource(&cfg, &request.uri, redactor.as_ref())
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("join error: {}", e), None))?;
+        match result {
+            Ok(contents) => Ok(ReadResourceResult::new(contents)),
+            // URI 格式错误属于参数错误，其余（项目/会话/ref 不存在等）按 resource 不存在上报
+            Err(e) if e.error == "invalid_uri" => Err(McpError::invalid_params(e.message.clone(), Some(json_data(&e)))),
+            Err(e) if e.error == "invalid_redact_pattern" => {
+                Err(McpError::internal_error(e.message.clone(), Some(json_data(&e))))
+            }
+            Err(e) => Err(McpError::resource_not_found(e.message.clone(), Some(json_data(&e)))),
+        }
+    }
+
+    async fn set_level(
+        &self,
+        request: SetLevelRequestParams,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        *self.log_level.lock().unwrap_or_else(PoisonError::into_inner) = request.level;
+        Ok(())
     }
 }
 
 /// rmcp 路径启动入口（替代 mcp.rs 的 run_mcp_server）
-pub async fn run_mcp_server_rmcp() -> anyhow::Result<()> {
-    let service = McpHistoryService::new().serve(rmcp::transport::stdio()).await?;
+pub async fn run_mcp_server_rmcp(config: Config) -> anyhow::Result<()> {
+    let service = McpHistoryService::with_config(config)
+        .serve(rmcp::transport::stdio())
+        .await?;
     service.waiting().await?;
     Ok(())
 }
+
+/// HTTP 模式的 MCP 端点路径
+const HTTP_PATH: &str = "/mcp";
+
+/// 解析 --http 地址：只给端口时绑定 127.0.0.1
+pub fn parse_http_addr(s: &str) -> anyhow::Result<SocketAddr> {
+    if let Ok(port) = s.parse::<u16>() {
+        return Ok(SocketAddr::from(([127, 0, 0, 1], port)));
+    }
+    s.parse()
+        .map_err(|e| anyhow::anyhow!("无效的监听地址 {}: {}（示例：127.0.0.1:8765 或 8765）", s, e))
+}
+
+/// streamable HTTP 入口：一个常驻进程供多个客户端共享（每个 MCP 会话一个 service 实例）
+pub async fn run_mcp_server_http(config: Config, addr: SocketAddr) -> anyhow::Result<()> {
+    let token = config.http_token.clone();
+    // 非回环地址对外可见，必须设置 token
+    if !addr.ip().is_loopback() && token.is_none() {
+        anyhow::bail!(
+            "监听非回环地址 {} 时必须设置 MCP_CLAUDE_HISTORY_TOKEN 或配置文件的 http_token",
+            addr.ip()
+        );
+    }
+
+    let mut http_config = StreamableHttpServerConfig::default();
+    let cancel = http_config.cancellation_token.clone();
+    if !addr.ip().is_loopback() {
+        http_config.allowed_hosts.push(addr.ip().to_string());
+    }
+    let redactor = Arc::new(Redactor::load(&config));
+    let service = StreamableHttpService::new(
+        move || Ok(McpHistoryService::with_redactor(config.clone(), redactor.clone())),
+        Arc::new(LocalSessionManager::default()),
+        http_config,
+    );
+
+    let mut router = axum::Router::new().nest_service(HTTP_PATH, service);
+    if let Some(token) = token {
+        router = router.layer(axum::middleware::from_fn_with_state(Arc::new(token), require_bearer));
+    }
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    eprintln!(
+        "mcp-claude-history listening on http://{}{}",
+        listener.local_addr()?,
+        HTTP_PATH
+    );
+    axum::serve(listener, router)
+        .with_graceful_shutdown(async move {
+            let _ = tokio::signal::ctrl_c().await;
+            cancel.cancel();
+        })
+        .await?;
+    Ok(())
+}
+
+/// 校验 `Authorization: Bearer <token>`，失败返回 401
+async fn require_bearer(
+    axum::extract::State(token): axum::extract::State<Arc<String>>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    use axum::http::{StatusCode, header};
+    use axum::response::IntoResponse;
+
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match provided {
+        Some(provided) if constant_time_eq(provided.as_bytes(), token.as_bytes()) => next.run(request).await,
+        _ => (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "unauthorized",
+        )
+            .into_response(),
+    }
+}
+
+/// 常量时间比较，避免按响应耗时逐字节猜测 token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,381 @@
+//! 会话 JSONL 的类型化消息模型
+//!
+//! 每行一条 [`MessageRecord`]；`message.content` 统一解析为 [`ContentBlock`] 列表
+//! （字符串内容视为单个 Text 块），未知或字段缺失的块宽松处理，不会让整行解析失败
+
+use serde::de::{self, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+/// JSONL 中的消息记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageRecord {
+    /// summary 等非对话记录没有 uuid / timestamp
+    #[serde(default)]
+    pub uuid: String,
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    #[serde(default)]
+    pub timestamp: String,
+    #[serde(default)]
+    pub message: Option<Message>,
+    /// 上下文压缩产生的摘要消息（raw type 为 user，但逻辑类型应为 summary）
+    #[serde(default)]
+    pub is_compact_summary: bool,
+    /// CLI 命令产生的 meta 消息
+    #[serde(default)]
+    pub is_meta: bool,
+    /// 父消息 uuid（编辑/回退会让多条消息共享同一父节点，形成分支）
+    #[serde(default)]
+    pub parent_uuid: Option<String>,
+    /// compact 边界处 parentUuid 为空，逻辑上的父消息记录在这里
+    #[serde(default)]
+    pub logical_parent_uuid: Option<String>,
+    /// subagent 等旁路对话
+    #[serde(default)]
+    pub is_sidechain: bool,
+    /// summary 记录指向的分支末端 uuid
+    #[serde(default)]
+    pub leaf_uuid: Option<String>,
+    /// summary 记录的标题文本
+    #[serde(default)]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub git_branch: Option<String>,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Task 结果记录的 `toolUseResult.agentId`：对应 `subagents/agent-<id>.jsonl`（toolUseResult 其余内容不解析）
+    #[serde(
+        default,
+        rename = "toolUseResult",
+        deserialize_with = "result_agent_id",
+        skip_serializing
+    )]
+    pub result_agent_id: Option<String>,
+}
+
+/// API 消息体
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Message {
+    /// API 消息 ID；流式写入时同一 ID 会出现在多行
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub role: Option<Role>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default, deserialize_with = "content_blocks")]
+    pub content: Vec<ContentBlock>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+/// 消息角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Assistant,
+    System,
+    #[serde(other)]
+    Other,
+}
+
+/// token 用量（缺失或非数字的字段按 0 计）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(default, deserialize_with = "count")]
+    pub input_tokens: u64,
+    #[serde(default, deserialize_with = "count")]
+    pub output_tokens: u64,
+    #[serde(default, deserialize_with = "count")]
+    pub cache_creation_input_tokens: u64,
+    #[serde(default, deserialize_with = "count")]
+    pub cache_read_input_tokens: u64,
+}
+
+/// 消息内容块
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        /// 字符串结果视为单个 Text 块；可能嵌套图片（如 Read 截图）
+        content: Vec<ContentBlock>,
+        is_error: bool,
+    },
+    Thinking {
+        thinking: String,
+    },
+    /// 加密的思考内容，原文不可见，只保留位置
+    RedactedThinking,
+    Image {
+        source: ImageSource,
+    },
+    /// 其他类型（document 等），内容不参与搜索
+    Other,
+}
+
+/// 图片数据（base64）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImageSource {
+    pub media_type: String,
+    pub data: String,
+}
+
+impl<'de> Deserialize<'de> for ContentBlock {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::from_value(Value::deserialize(deserializer)?))
+    }
+}
+
+impl ContentBlock {
+    /// 宽松解析：字段缺失时取默认值，无法识别的块为 Other
+    fn from_value(mut value: Value) -> Self {
+        let block_type = value
+            .get("type")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        match block_type.as_str() {
+            "text" => Self::Text {
+                text: take_string(&mut value, "text"),
+            },
+            "thinking" => Self::Thinking {
+                thinking: take_string(&mut value, "thinking"),
+            },
+            "redacted_thinking" => Self::RedactedThinking,
+            "tool_use" => Self::ToolUse {
+                id: take_string(&mut value, "id"),
+                name: take_string(&mut value, "name"),
+                input: value.get_mut("input").map(Value::take).unwrap_or(Value::Null),
+            },
+            "tool_result" => Self::ToolResult {
+                tool_use_id: take_string(&mut value, "tool_use_id"),
+                content: blocks_from_value(value.get_mut("content").map(Value::take).unwrap_or(Value::Null)),
+                is_error: value.get("is_error").and_then(Value::as_bool).unwrap_or(false),
+            },
+            "image" => match value.get_mut("source") {
+                Some(source) => Self::Image {
+                    source: ImageSource {
+                        media_type: source
+                            .get("media_type")
+                            .and_then(Value::as_str)
+                            .unwrap_or("image/png")
+                            .to_string(),
+                        data: take_string(source, "data"),
+                    },
+                },
+                None => Self::Other,
+            },
+            _ => Self::Other,
+        }
+    }
+}
+
+impl MessageRecord {
+    /// 解析一行 JSONL；无法解析（空行、截断的行等）返回 None
+    pub fn parse(line: &str) -> Option<Self> {
+        serde_json::from_str(line).ok()
+    }
+
+    /// 消息内容块（无 message 时为空）
+    pub fn blocks(&self) -> &[ContentBlock] {
+        self.message.as_ref().map(|m| m.content.as_slice()).unwrap_or_default()
+    }
+
+    /// 消息分类：(effective_type, subtype)
+    ///
+    /// effective_type：修正后的类型（isCompactSummary 的 user → summary）
+    /// subtype：细粒度分类
+    ///   user   → human / tool_result / meta
+    ///   assistant → text / tool_use / thinking / empty
+    ///   summary → summary
+    ///   system → system
+    pub fn classify(&self) -> (&'static str, &'static str) {
+        let blocks = self.blocks();
+        let has = |f: fn(&ContentBlock) -> bool| blocks.iter().any(f);
+        match self.msg_type.as_str() {
+            "user" => {
+                if self.is_compact_summary {
+                    ("summary", "summary")
+                } else if self.is_meta {
+                    ("user", "meta")
+                } else if has(|b| matches!(b, ContentBlock::ToolResult { .. })) {
+                    ("user", "tool_result")
+                } else {
+                    ("user", "human")
+                }
+            }
+            // 优先级 text > tool_use > thinking > empty
+            "assistant" => {
+                if has(|b| matches!(b, ContentBlock::Text { text } if !text.is_empty())) {
+                    ("assistant", "text")
+                } else if has(|b| matches!(b, ContentBlock::ToolUse { .. })) {
+                    ("assistant", "tool_use")
+                } else if has(|b| matches!(b, ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking)) {
+                    ("assistant", "thinking")
+                } else {
+                    ("assistant", "empty")
+                }
+            }
+            "system" => ("system", "system"),
+            // progress, file-history-snapshot 等内部类型，搜索时会被 types 过滤掉
+            _ => ("other", "other"),
+        }
+    }
+}
+
+/// content 可能是字符串或块数组
+fn content_blocks<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<ContentBlock>, D::Error> {
+    Ok(blocks_from_value(Value::deserialize(deserializer)?))
+}
+
+fn blocks_from_value(value: Value) -> Vec<ContentBlock> {
+    match value {
+        Value::String(text) => vec![ContentBlock::Text { text }],
+        Value::Array(items) => items.into_iter().map(ContentBlock::from_value).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn take_string(value: &mut Value, key: &str) -> String {
+    match value.get_mut(key).map(Value::take) {
+        Some(Value::String(s)) => s,
+        _ => String::new(),
+    }
+}
+
+/// 只取 toolUseResult 对象中的 agentId；其他字段与非对象的值（错误字符串等）直接跳过，不构造 Value
+fn result_agent_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    struct AgentId;
+
+    impl<'de> Visitor<'de> for AgentId {
+        type Value = Option<String>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("toolUseResult")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut agent_id = None;
+            while let Some(key) = map.next_key::<String>()? {
+                if key == "agentId" {
+                    agent_id = map.next_value::<Value>()?.as_str().map(str::to_string);
+                } else {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+            Ok(agent_id)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            while seq.next_element::<IgnoredAny>()?.is_some() {}
+            Ok(None)
+        }
+
+        fn visit_str<E: de::Error>(self, _: &str) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_bool<E: de::Error>(self, _: bool) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_i64<E: de::Error>(self, _: i64) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_u64<E: de::Error>(self, _: u64) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_f64<E: de::Error>(self, _: f64) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+    }
+
+    deserializer.deserialize_any(AgentId)
+}
+
+fn count<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    Ok(Value::deserialize(deserializer)?.as_u64().unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_content_and_classify() {
+        let record = MessageRecord::parse(
+            r#"{"type":"assistant","uuid":"a","message":{"id":"msg_1","role":"assistant","model":"m",
+                "usage":{"input_tokens":3,"output_tokens":null},
+                "content":[{"type":"thinking","thinking":"hmm"},{"type":"tool_use","id":"t1","name":"Read"},
+                           {"type":"server_tool_use"},{"type":"image"}]}}"#,
+        )
+        .unwrap();
+        let message = record.message.as_ref().unwrap();
+        assert_eq!(message.role, Some(Role::Assistant));
+        assert_eq!(message.usage.unwrap().input_tokens, 3);
+        assert_eq!(message.usage.unwrap().output_tokens, 0);
+        assert_eq!(record.blocks().len(), 4);
+        assert!(
+            matches!(&record.blocks()[1], ContentBlock::ToolUse { name, input, .. } if name == "Read" && input.is_null())
+        );
+        assert_eq!(record.blocks()[2], ContentBlock::Other);
+        assert_eq!(record.blocks()[3], ContentBlock::Other);
+        assert_eq!(record.classify(), ("assistant", "tool_use"));
+
+        let record = MessageRecord::parse(
+            r#"{"type":"user","message":{"role":"user","content":[
+                {"type":"tool_result","tool_use_id":"t1","content":"ok","is_error":true}]}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            record.blocks()[0],
+            ContentBlock::ToolResult {
+                tool_use_id: "t1".to_string(),
+                content: vec![ContentBlock::Text { text: "ok".to_string() }],
+                is_error: true,
+            }
+        );
+        assert_eq!(record.classify(), ("user", "tool_result"));
+
+        let record = MessageRecord::parse(r#"{"type":"assistant","message":{"content":""}}"#).unwrap();
+        assert_eq!(record.classify(), ("assistant", "empty"));
+        let record = MessageRecord::parse(
+            r#"{"type":"assistant","message":{"content":[{"type":"redacted_thinking","data":"xyz"}]}}"#,
+        )
+        .unwrap();
+        assert_eq!(record.blocks()[0], ContentBlock::RedactedThinking);
+        assert_eq!(record.classify(), ("assistant", "thinking"));
+        let record =
+            MessageRecord::parse(r#"{"type":"user","isCompactSummary":true,"message":{"content":"s"}}"#).unwrap();
+        assert_eq!(record.classify(), ("summary", "summary"));
+        assert!(MessageRecord::parse("{\"type\":").is_none());
+
+        let record = MessageRecord::parse(
+            r#"{"type":"user","toolUseResult":{"content":[{"type":"text","text":"x"}],"agentId":"a1b2","totalTokens":9}}"#,
+        )
+        .unwrap();
+        assert_eq!(record.result_agent_id.as_deref(), Some("a1b2"));
+        // 工具出错时 toolUseResult 是字符串，不影响整行解析
+        let record = MessageRecord::parse(r#"{"type":"user","toolUseResult":"Error: denied"}"#).unwrap();
+        assert_eq!(record.result_agent_id, None);
+    }
+}
@@ -3,9 +3,9 @@ use crate::types::*;
 use crate::utils::*;
 use std::fs;
 
-/// 列出所有项目
+/// 列出所有项目（访问策略拒绝的项目不出现）
 pub fn list_projects(config: &Config) -> Result<ProjectsResponse, ErrorResponse> {
-    let entries = fs::read_dir(&config.projects_dir).map_err(|e| ErrorResponse {
+    let dirs = config.list_project_dirs().map_err(|e| ErrorResponse {
         error: "io_error".to_string(),
         message: format!("无法读取项目目录: {}", e),
         available: None,
@@ -13,14 +13,9 @@ pub fn list_projects(config: &Config) -> Result<ProjectsResponse, ErrorResponse>
 
     let mut projects = Vec::new();
 
-    for entry in entries.flatten() {
-        if !entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
-            continue;
-        }
-
-        let id = entry.file_name().to_string_lossy().to_string();
-        let path = project_id_to_display_path(&id);
-        let dir = entry.path();
+    for (id, dir) in dirs {
+        let path = project_id_to_display_path(unqualified_project_id(&id));
+        let root = config.root_label(&dir);
 
         // 统计会话数量和最后活动时间
         let mut session_count = 0;
@@ -30,35 +25,35 @@ pub fn list_projects(config: &Config) -> Result<ProjectsResponse, ErrorResponse>
         if let Ok(files) = fs::read_dir(&dir) {
             for file in files.flatten() {
                 let file_path = file.path();
-                if file_path.extension().map(|e| e == "jsonl").unwrap_or(false) {
-                    if let Some(_session_id) = session_id_from_filename(&file.file_name().to_string_lossy()) {
-                        session_count += 1;
+                if let Some(session_id) = session_id_from_filename(&file.file_name().to_string_lossy())
+                    && !shadowed_by_plain(&file_path, &session_id)
+                {
+                    session_count += 1;
 
-                        if let Ok(meta) = file.metadata() {
-                            if let Ok(mtime) = meta.modified() {
-                                if mtime > last_mtime {
-                                    last_mtime = mtime;
-                                }
-                            }
-                        }
+                    if let Ok(meta) = file.metadata()
+                        && let Ok(mtime) = meta.modified()
+                        && mtime > last_mtime
+                    {
+                        last_mtime = mtime;
                     }
                 }
             }
         }
 
         // 转换时间
-        if last_mtime != std::time::SystemTime::UNIX_EPOCH {
-            if let Ok(duration) = last_mtime.duration_since(std::time::SystemTime::UNIX_EPOCH) {
-                let dt = chrono::DateTime::from_timestamp(duration.as_secs() as i64, 0);
-                if let Some(dt) = dt {
-                    last_activity = dt.format("%Y-%m-%dT%H:%M:%SZ").to_string();
-                }
+        if last_mtime != std::time::SystemTime::UNIX_EPOCH
+            && let Ok(duration) = last_mtime.duration_since(std::time::SystemTime::UNIX_EPOCH)
+        {
+            let dt = chrono::DateTime::from_timestamp(duration.as_secs() as i64, 0);
+            if let Some(dt) = dt {
+                last_activity = dt.format("%Y-%m-%dT%H:%M:%SZ").to_string();
             }
         }
 
         projects.push(ProjectInfo {
             id,
             path,
+            root,
             session_count,
             last_activity,
         });
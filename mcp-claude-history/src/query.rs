@@ -0,0 +1,602 @@
+//! 搜索查询语言：search 与 context 共用
+//!
+//! 语法（优先级从高到低）：
+//! - `word` / `"quoted phrase"`：内容子串匹配（大小写由 case_sensitive 决定）
+//! - `field:value` / `field:"value with spaces"`：按记录元数据过滤，见 [`FieldFilter`]
+//! - `( … )`：分组
+//! - `!x` / `NOT x`：取反
+//! - `a|b` / `a OR b`：或，绑定比 AND 紧（`a b|c` = a AND (b OR c)，与旧语法一致）
+//! - 空格 / `AND`：与
+//!
+//! 未知字段名（如 `std::io`、`error:`）按普通词处理；字面括号、`|` 需要放进引号
+
+use crate::model::{ContentBlock, MessageRecord};
+use crate::time::{TimeZone, parse_time_expr};
+use crate::types::ErrorResponse;
+use crate::utils::{extract_thinking, parse_time};
+use chrono::{DateTime, Utc};
+
+/// 字段过滤条件（值一律不区分大小写）
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldFilter {
+    /// `tool:Bash`：消息中有同名 tool_use
+    Tool(String),
+    /// `model:opus`：message.model 包含该串
+    Model(String),
+    /// `branch:main`：gitBranch 相等
+    Branch(String),
+    /// `cwd:/srv/app`：cwd 以该路径开头
+    Cwd(String),
+    /// `role:user`：effective_type 或 subtype 相等（如 role:human、role:tool_result）
+    Role(String),
+    /// `file:src/main.rs`：tool_use 输入中的 file_path / path / notebook_path 包含该串
+    File(String),
+    /// `thinking:retry`：思考内容（含 `[REDACTED_THINKING]` 占位符）包含该串，不要求 include_thinking
+    Thinking(String),
+    /// `before:2026-01-01`：时间早于该时刻
+    Before(chrono::DateTime<chrono::Utc>),
+    /// `after:2026-01-01`：时间不早于该时刻
+    After(chrono::DateTime<chrono::Utc>),
+}
+
+const FIELD_NAMES: [&str; 9] = [
+    "tool", "model", "branch", "cwd", "role", "file", "thinking", "before", "after",
+];
+
+/// 查询 AST
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryExpr {
+    /// 内容子串（已按 case_sensitive 归一化）
+    Term(String),
+    Field(FieldFilter),
+    And(Vec<QueryExpr>),
+    Or(Vec<QueryExpr>),
+    Not(Box<QueryExpr>),
+}
+
+/// 解析后的查询；root 为 None 表示空查询（匹配全部）
+#[derive(Debug, Clone)]
+pub struct Query {
+    pub root: Option<QueryExpr>,
+    pub case_sensitive: bool,
+}
+
+/// 字段过滤所需的记录元数据
+#[derive(Debug, Default)]
+pub struct MessageFields {
+    pub effective_type: String,
+    pub subtype: String,
+    pub tools: Vec<String>,
+    pub files: Vec<String>,
+    pub model: Option<String>,
+    pub branch: Option<String>,
+    pub cwd: Option<String>,
+    /// 小写的思考内容（extract_thinking）
+    pub thinking: String,
+    pub timestamp: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl MessageFields {
+    pub fn from_record(record: &MessageRecord, effective_type: &str, subtype: &str) -> Self {
+        let mut tools = Vec::new();
+        let mut files = Vec::new();
+        for block in record.blocks() {
+            let ContentBlock::ToolUse { name, input, .. } = block else {
+                continue;
+            };
+            if !name.is_empty() {
+                tools.push(name.to_lowercase());
+            }
+            for key in ["file_path", "path", "notebook_path"] {
+                if let Some(path) = input.get(key).and_then(|p| p.as_str()) {
+                    files.push(path.to_lowercase());
+                }
+            }
+        }
+
+        Self {
+            effective_type: effective_type.to_string(),
+            subtype: subtype.to_string(),
+            tools,
+            files,
+            model: record
+                .message
+                .as_ref()
+                .and_then(|m| m.model.as_deref())
+                .map(str::to_lowercase),
+            branch: record.git_branch.as_deref().map(str::to_lowercase),
+            cwd: record.cwd.as_deref().map(str::to_lowercase),
+            thinking: extract_thinking(record).to_lowercase(),
+            timestamp: parse_time(&record.timestamp),
+        }
+    }
+}
+
+impl FieldFilter {
+    fn matches(&self, fields: &MessageFields) -> bool {
+        match self {
+            Self::Tool(name) => fields.tools.iter().any(|t| t == name),
+            Self::Model(model) => fields.model.as_deref().is_some_and(|m| m.contains(model.as_str())),
+            Self::Branch(branch) => fields.branch.as_deref() == Some(branch.as_str()),
+            Self::Cwd(cwd) => fields.cwd.as_deref().is_some_and(|c| c.starts_with(cwd.as_str())),
+            Self::Role(role) => fields.effective_type == *role || fields.subtype == *role,
+            Self::File(file) => fields.files.iter().any(|f| f.contains(file.as_str())),
+            Self::Thinking(text) => fields.thinking.contains(text.as_str()),
+            Self::Before(t) => fields.timestamp.is_some_and(|ts| ts < *t),
+            Self::After(t) => fields.timestamp.is_some_and(|ts| ts >= *t),
+        }
+    }
+}
+
+fn query_error(pos: usize, message: &str) -> ErrorResponse {
+    ErrorResponse {
+        error: "invalid_query".to_string(),
+        message: format!("查询语法错误（位置 {}）: {}", pos, message),
+        available: None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    Or,
+    And,
+    Not,
+    Term(String),
+    Field(String, String),
+}
+
+/// 词法分析，返回 (token, 字符位置)
+fn tokenize_query(input: &str) -> Result<Vec<(Token, usize)>, ErrorResponse> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    // 读取引号内的短语，i 指向开引号
+    let read_phrase = |i: &mut usize| -> Result<String, ErrorResponse> {
+        let start = *i;
+        *i += 1;
+        let mut phrase = String::new();
+        while *i < chars.len() {
+            match chars[*i] {
+                '"' => {
+                    *i += 1;
+                    return Ok(phrase);
+                }
+                '\\' if *i + 1 < chars.len() => {
+                    phrase.push(chars[*i + 1]);
+                    *i += 2;
+                }
+                c => {
+                    phrase.push(c);
+                    *i += 1;
+                }
+            }
+        }
+        Err(query_error(start, "引号未闭合"))
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push((Token::LParen, start));
+                i += 1;
+            }
+            ')' => {
+                tokens.push((Token::RParen, start));
+                i += 1;
+            }
+            '|' => {
+                tokens.push((Token::Or, start));
+                i += 1;
+            }
+            '!' => {
+                tokens.push((Token::Not, start));
+                i += 1;
+            }
+            '"' => {
+                let phrase = read_phrase(&mut i)?;
+                tokens.push((Token::Term(phrase), start));
+            }
+            _ => {
+                let mut word = String::new();
+                while i < chars.len() && !chars[i].is_whitespace() && !matches!(chars[i], '(' | ')' | '|' | '"') {
+                    if chars[i] == ':' && FIELD_NAMES.contains(&word.as_str()) {
+                        break;
+                    }
+                    word.push(chars[i]);
+                    i += 1;
+                }
+
+                if i < chars.len() && chars[i] == ':' {
+                    // 已知字段：读取值（可为引号短语）
+                    i += 1;
+                    let value = if i < chars.len() && chars[i] == '"' {
+                        read_phrase(&mut i)?
+                    } else {
+                        let mut value = String::new();
+                        while i < chars.len() && !chars[i].is_whitespace() && !matches!(chars[i], '(' | ')' | '|') {
+                            value.push(chars[i]);
+                            i += 1;
+                        }
+                        value
+                    };
+                    if value.is_empty() {
+                        return Err(query_error(start, &format!("字段 {} 缺少值", word)));
+                    }
+                    tokens.push((Token::Field(word, value), start));
+                    continue;
+                }
+
+                let token = match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Term(word),
+                };
+                tokens.push((token, start));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    end: usize,
+    case_sensitive: bool,
+    /// before: / after: 的时区与相对时间基准
+    timezone: TimeZone,
+    now: DateTime<Utc>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    /// 当前 token 的字符位置（末尾时为输入长度）
+    fn at(&self) -> usize {
+        self.tokens.get(self.pos).map(|(_, p)| *p).unwrap_or(self.end)
+    }
+
+    /// and := or (AND? or)*
+    fn parse_and(&mut self) -> Result<QueryExpr, ErrorResponse> {
+        let mut items = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::RParen) => break,
+                Some(Token::And) => {
+                    if items.is_empty() {
+                        return Err(query_error(self.at(), "AND 前缺少查询词"));
+                    }
+                    self.pos += 1;
+                    if matches!(self.peek(), None | Some(Token::RParen)) {
+                        return Err(query_error(self.at(), "AND 后缺少查询词"));
+                    }
+                }
+                _ => items.push(self.parse_or()?),
+            }
+        }
+        match items.len() {
+            0 => Err(query_error(self.at(), "空的查询表达式")),
+            1 => Ok(items.remove(0)),
+            _ => Ok(QueryExpr::And(items)),
+        }
+    }
+
+    /// or := unary ((| | OR) unary)*
+    fn parse_or(&mut self) -> Result<QueryExpr, ErrorResponse> {
+        let mut items = vec![self.parse_unary()?];
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            items.push(self.parse_unary()?);
+        }
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            QueryExpr::Or(items)
+        })
+    }
+
+    /// unary := (! | NOT) unary | ( and ) | term | field
+    fn parse_unary(&mut self) -> Result<QueryExpr, ErrorResponse> {
+        let at = self.at();
+        let Some((token, _)) = self.tokens.get(self.pos).cloned() else {
+            return Err(query_error(at, "表达式不完整，缺少查询词"));
+        };
+        self.pos += 1;
+        match token {
+            Token::Not => Ok(QueryExpr::Not(Box::new(self.parse_unary()?))),
+            Token::LParen => {
+                let inner = self.parse_and()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(query_error(at, "缺少右括号"));
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            Token::Term(term) => {
+                if term.is_empty() {
+                    return Err(query_error(at, "空的引号短语"));
+                }
+                Ok(QueryExpr::Term(if self.case_sensitive {
+                    term
+                } else {
+                    term.to_lowercase()
+                }))
+            }
+            Token::Field(name, value) => parse_field(&name, &value, at, self.timezone, self.now).map(QueryExpr::Field),
+            Token::RParen => Err(query_error(at, "多余的右括号")),
+            Token::Or => Err(query_error(at, "| / OR 前缺少查询词")),
+            Token::And => Err(query_error(at, "AND 前缺少查询词")),
+        }
+    }
+}
+
+/// before: / after: 与 since / until 接受相同的时间表达式（见 [`crate::time`]），按配置时区解释
+fn parse_field(
+    name: &str,
+    value: &str,
+    at: usize,
+    timezone: TimeZone,
+    now: DateTime<Utc>,
+) -> Result<FieldFilter, ErrorResponse> {
+    let lower = value.to_lowercase();
+    let time =
+        || parse_time_expr(value, timezone, now).map_err(|e| query_error(at, &format!("{}: {}", name, e.message)));
+    Ok(match name {
+        "tool" => FieldFilter::Tool(lower),
+        "model" => FieldFilter::Model(lower),
+        "branch" => FieldFilter::Branch(lower),
+        "cwd" => FieldFilter::Cwd(lower),
+        "role" => FieldFilter::Role(lower),
+        "file" => FieldFilter::File(lower),
+        "thinking" => FieldFilter::Thinking(lower),
+        "before" => FieldFilter::Before(time()?),
+        "after" => FieldFilter::After(time()?),
+        _ => return Err(query_error(at, &format!("未知字段: {}", name))),
+    })
+}
+
+/// 大小写归一化后的待匹配文本，保留到原文字符位置的映射
+struct Haystack<'a> {
+    text: std::borrow::Cow<'a, str>,
+    /// case-insensitive 时：小写文本字符索引 → 原文字符索引
+    map: Option<Vec<usize>>,
+}
+
+impl<'a> Haystack<'a> {
+    fn new(content: &'a str, case_sensitive: bool) -> Self {
+        if case_sensitive {
+            return Self {
+                text: content.into(),
+                map: None,
+            };
+        }
+        // 预分配 capacity（按字节上界），避免热路径多次 grow
+        let mut lower = String::with_capacity(content.len());
+        let mut map = Vec::with_capacity(content.len());
+        for (orig_idx, ch) in content.chars().enumerate() {
+            for lc in ch.to_lowercase() {
+                lower.push(lc);
+                map.push(orig_idx);
+            }
+        }
+        Self {
+            text: lower.into(),
+            map: Some(map),
+        }
+    }
+
+    /// 子串在原文中的字符位置
+    fn find(&self, term: &str) -> Option<usize> {
+        let byte_pos = self.text.find(term)?;
+        let char_pos = self.text[..byte_pos].chars().count();
+        match &self.map {
+            Some(map) => map.get(char_pos).copied(),
+            None => Some(char_pos),
+        }
+    }
+}
+
+impl Query {
+    /// 解析查询串；空串返回空查询。timezone 用于解释 before: / after: 中不带偏移的时间
+    pub fn parse(input: &str, case_sensitive: bool, timezone: TimeZone) -> Result<Self, ErrorResponse> {
+        Self::parse_at(input, case_sensitive, timezone, Utc::now())
+    }
+
+    /// 同 parse；now 为相对时间（`after:2h`）的基准时刻
+    pub fn parse_at(
+        input: &str,
+        case_sensitive: bool,
+        timezone: TimeZone,
+        now: DateTime<Utc>,
+    ) -> Result<Self, ErrorResponse> {
+        let tokens = tokenize_query(input)?;
+        if tokens.is_empty() {
+            return Ok(Self {
+                root: None,
+                case_sensitive,
+            });
+        }
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: input.chars().count(),
+            case_sensitive,
+            timezone,
+            now,
+        };
+        let root = parser.parse_and()?;
+        if parser.peek() == Some(&Token::RParen) {
+            return Err(query_error(parser.at(), "多余的右括号"));
+        }
+        Ok(Self {
+            root: Some(root),
+            case_sensitive,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// 是否包含字段过滤（调用方据此决定是否提取 MessageFields）
+    pub fn has_fields(&self) -> bool {
+        fn walk(expr: &QueryExpr) -> bool {
+            match expr {
+                QueryExpr::Term(_) => false,
+                QueryExpr::Field(_) => true,
+                QueryExpr::And(items) | QueryExpr::Or(items) => items.iter().any(walk),
+                QueryExpr::Not(inner) => walk(inner),
+            }
+        }
+        self.root.as_ref().is_some_and(walk)
+    }
+
+    /// 不在 NOT 之下的内容词（去重、按出现顺序），用于相关性打分和定位命中
+    pub fn positive_terms(&self) -> Vec<String> {
+        fn walk(expr: &QueryExpr, out: &mut Vec<String>) {
+            match expr {
+                QueryExpr::Term(t) => {
+                    if !out.contains(t) {
+                        out.push(t.clone());
+                    }
+                }
+                QueryExpr::And(items) | QueryExpr::Or(items) => items.iter().for_each(|e| walk(e, out)),
+                QueryExpr::Field(_) | QueryExpr::Not(_) => {}
+            }
+        }
+        let mut terms = Vec::new();
+        if let Some(root) = &self.root {
+            walk(root, &mut terms);
+        }
+        terms
+    }
+
+    /// 匹配消息内容与元数据，返回 (是否命中, 第一个正向词在原文中的字符位置)
+    ///
+    /// fields 为 None 时所有字段条件视为不满足
+    pub fn matches(&self, content: &str, fields: Option<&MessageFields>) -> (bool, Option<usize>) {
+        let Some(root) = &self.root else {
+            return (true, None);
+        };
+        let haystack = Haystack::new(content, self.case_sensitive);
+        if !eval(root, &haystack, fields) {
+            return (false, None);
+        }
+        let pos = self.positive_terms().iter().find_map(|t| haystack.find(t));
+        (true, pos)
+    }
+}
+
+fn eval(expr: &QueryExpr, haystack: &Haystack, fields: Option<&MessageFields>) -> bool {
+    match expr {
+        QueryExpr::Term(t) => haystack.text.contains(t.as_str()),
+        QueryExpr::Field(f) => fields.is_some_and(|fields| f.matches(fields)),
+        QueryExpr::And(items) => items.iter().all(|e| eval(e, haystack, fields)),
+        QueryExpr::Or(items) => items.iter().any(|e| eval(e, haystack, fields)),
+        QueryExpr::Not(inner) => !eval(inner, haystack, fields),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(tool: &str, role: &str) -> MessageFields {
+        MessageFields {
+            effective_type: role.to_string(),
+            tools: vec![tool.to_lowercase()],
+            files: vec!["/srv/app/src/main.rs".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_legacy_syntax_precedence() {
+        // a b|c = a AND (b OR c)
+        let q = Query::parse("Tokio dead|live !panic", false, TimeZone::Utc).unwrap();
+        assert_eq!(
+            q.root,
+            Some(QueryExpr::And(vec![
+                QueryExpr::Term("tokio".to_string()),
+                QueryExpr::Or(vec![
+                    QueryExpr::Term("dead".to_string()),
+                    QueryExpr::Term("live".to_string())
+                ]),
+                QueryExpr::Not(Box::new(QueryExpr::Term("panic".to_string()))),
+            ]))
+        );
+        assert_eq!(q.matches("the TOKIO runtime is dead", None), (true, Some(4)));
+        assert!(!q.matches("tokio dead, panic", None).0);
+    }
+
+    #[test]
+    fn test_fields_phrases_and_groups() {
+        let q = Query::parse(
+            r#"tool:Bash (file:main.rs OR "exit code") NOT role:user std::io"#,
+            false,
+            TimeZone::Utc,
+        )
+        .unwrap();
+        assert!(q.has_fields());
+        assert_eq!(q.positive_terms(), vec!["exit code".to_string(), "std::io".to_string()]);
+        assert!(q.matches("use std::io", Some(&fields("Bash", "assistant"))).0);
+        assert!(!q.matches("use std::io", Some(&fields("Read", "assistant"))).0);
+        assert!(!q.matches("use std::io", Some(&fields("Bash", "user"))).0);
+        assert!(!q.matches("use std::io", None).0);
+
+        let q = Query::parse(r#"thinking:"Retry the" deploy"#, false, TimeZone::Utc).unwrap();
+        let thinking = MessageFields {
+            thinking: "maybe retry the upload".to_string(),
+            ..Default::default()
+        };
+        assert!(q.matches("deploy done", Some(&thinking)).0);
+        // 思考内容不参与普通词匹配
+        assert!(!q.matches("retry the deploy", Some(&MessageFields::default())).0);
+    }
+
+    #[test]
+    fn test_time_fields_use_timezone() {
+        let utc = |s: &str| parse_time(s).unwrap();
+        let now = utc("2026-05-06T03:30:00Z");
+        let root = |q: &str, tz: &str| {
+            Query::parse_at(q, false, TimeZone::parse(tz).unwrap(), now)
+                .unwrap()
+                .root
+                .unwrap()
+        };
+        assert_eq!(
+            root("after:2026-05-01", "+08:00"),
+            QueryExpr::Field(FieldFilter::After(utc("2026-04-30T16:00:00Z")))
+        );
+        assert_eq!(
+            root("after:2h", "UTC"),
+            QueryExpr::Field(FieldFilter::After(utc("2026-05-06T01:30:00Z")))
+        );
+        assert_eq!(
+            root(r#"before:"last monday""#, "UTC"),
+            QueryExpr::Field(FieldFilter::Before(utc("2026-05-04T00:00:00Z")))
+        );
+    }
+
+    #[test]
+    fn test_errors_report_position() {
+        let err = |q: &str| Query::parse(q, false, TimeZone::Utc).unwrap_err();
+        assert_eq!(err("a (b").error, "invalid_query");
+        assert!(err("a (b").message.contains("位置 2"));
+        assert!(err("a)").message.contains("多余的右括号"));
+        assert!(err("\"open").message.contains("引号未闭合"));
+        assert!(err("before:yesterdy").message.contains("无法解析时间"));
+        assert!(err("a |").message.contains("缺少查询词"));
+        assert!(err("tool:").message.contains("缺少值"));
+        assert!(Query::parse("   ", false, TimeZone::Utc).unwrap().is_empty());
+    }
+}
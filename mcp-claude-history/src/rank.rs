@@ -0,0 +1,109 @@
+//! BM25 相关性打分
+//!
+//! 文档 = 一条命中消息（extract_and_replace_images 产出的文本），语料 = 本次搜索的全部命中。
+//! 词频按子串出现次数统计，与查询词的子串匹配语义保持一致；文档长度按 token 数计
+
+use crate::index::tokenize;
+use crate::query::Query;
+use regex::Regex;
+
+/// BM25 词频饱和参数
+const K1: f64 = 1.2;
+/// BM25 文档长度归一化参数
+const B: f64 = 0.75;
+
+/// 打分用的查询词
+pub enum RankQuery<'a> {
+    /// 普通模式：查询中所有不在 NOT 之下的内容词（字段条件不参与打分）
+    Terms { terms: Vec<String>, case_sensitive: bool },
+    /// 正则模式：整个正则视为一个词，词频为匹配次数
+    Regex(&'a Regex),
+}
+
+impl<'a> RankQuery<'a> {
+    pub fn from_query(query: &Query) -> Self {
+        Self::Terms {
+            terms: query.positive_terms(),
+            case_sensitive: query.case_sensitive,
+        }
+    }
+
+    pub fn from_regex(regex: &'a Regex) -> Self {
+        Self::Regex(regex)
+    }
+
+    /// 返回 (每个词的词频, 文档长度)
+    fn term_frequencies(&self, content: &str) -> (Vec<usize>, usize) {
+        match self {
+            Self::Terms { terms, case_sensitive } => {
+                let haystack = if *case_sensitive {
+                    content.to_string()
+                } else {
+                    content.to_lowercase()
+                };
+                let tfs = terms.iter().map(|t| haystack.matches(t.as_str()).count()).collect();
+                let doc_len = tokenize(&haystack).count();
+                (tfs, doc_len)
+            }
+            Self::Regex(regex) => (vec![regex.find_iter(content).count()], tokenize(content).count()),
+        }
+    }
+}
+
+/// 对一组文档计算 BM25 分数，返回值与输入一一对应
+pub fn bm25_scores(contents: &[&str], query: &RankQuery) -> Vec<f64> {
+    let docs: Vec<(Vec<usize>, usize)> = contents.iter().map(|c| query.term_frequencies(c)).collect();
+    if docs.is_empty() {
+        return Vec::new();
+    }
+
+    let n = docs.len() as f64;
+    let avg_len = (docs.iter().map(|(_, len)| *len).sum::<usize>() as f64 / n).max(1.0);
+    let term_count = docs[0].0.len();
+
+    let idf: Vec<f64> = (0..term_count)
+        .map(|i| {
+            let df = docs.iter().filter(|(tfs, _)| tfs[i] > 0).count() as f64;
+            ((n - df + 0.5) / (df + 0.5) + 1.0).ln()
+        })
+        .collect();
+
+    docs.iter()
+        .map(|(tfs, len)| {
+            let norm = K1 * (1.0 - B + B * (*len as f64) / avg_len);
+            tfs.iter()
+                .zip(&idf)
+                .map(|(&tf, idf)| {
+                    let tf = tf as f64;
+                    idf * tf * (K1 + 1.0) / (tf + norm)
+                })
+                .sum()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bm25_prefers_focused_documents() {
+        let docs = [
+            "tokio mentioned once in a long message about many other unrelated topics and things",
+            "tokio deadlock: the tokio runtime deadlocked because of a blocking call inside tokio",
+            "deadlock",
+        ];
+        let query = Query::parse("tokio|deadlock", false, crate::time::TimeZone::Utc).unwrap();
+        let query = RankQuery::from_query(&query);
+        let scores = bm25_scores(&docs, &query);
+        assert!(scores[1] > scores[0]);
+        assert!(scores[1] > scores[2]);
+    }
+
+    #[test]
+    fn test_bm25_zero_without_terms() {
+        let query = Query::parse("", false, crate::time::TimeZone::Utc).unwrap();
+        let query = RankQuery::from_query(&query);
+        assert_eq!(bm25_scores(&["anything"], &query), vec![0.0]);
+    }
+}
@@ -0,0 +1,194 @@
+//! 流式读取会话 JSONL
+//!
+//! [`SessionReader`] 逐行读取并解析为 [`MessageRecord`]，产出 `(行号, 记录)`，行号从 1 开始，
+//! 与 ref 中的行号一致。无法解析的行（空行、写到一半的行、非 UTF-8）跳过但仍计入行号
+//!
+//! 归档的会话文件（`.jsonl.gz`、`.jsonl.zst`）按扩展名透明解压，行号与压缩前相同
+
+use crate::model::MessageRecord;
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::path::Path;
+
+/// 会话文件的流式记录迭代器
+///
+/// ```no_run
+/// use mcp_claude_history::reader::SessionReader;
+///
+/// let mut reader = SessionReader::open("session.jsonl".as_ref())?;
+/// for (line, record) in reader.by_ref() {
+///     let (effective_type, subtype) = record.classify();
+///     println!("{} {} {}", line, effective_type, subtype);
+/// }
+/// println!("{} lines", reader.lines_read());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct SessionReader<R, F = fn(usize, &str) -> bool> {
+    reader: R,
+    buf: String,
+    first_line: usize,
+    line: usize,
+    malformed: usize,
+    filter: F,
+}
+
+fn keep_all(_: usize, _: &str) -> bool {
+    true
+}
+
+/// 会话文件的压缩格式（由扩展名决定）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => Self::Gzip,
+            Some("zst") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+
+    /// 按该格式解压读取
+    pub fn reader(self, file: File) -> std::io::Result<Box<dyn BufRead + Send>> {
+        Ok(match self {
+            Self::None => Box::new(BufReader::new(file)),
+            Self::Gzip => Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(file))),
+            Self::Zstd => Box::new(BufReader::new(zstd::stream::read::Decoder::new(file)?)),
+        })
+    }
+
+    /// 会话文件的完整扩展名
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::None => ".jsonl",
+            Self::Gzip => ".jsonl.gz",
+            Self::Zstd => ".jsonl.zst",
+        }
+    }
+}
+
+/// 打开会话文件，按扩展名解压
+pub fn open_session_file(path: &Path) -> std::io::Result<Box<dyn BufRead + Send>> {
+    Compression::of(path).reader(File::open(path)?)
+}
+
+impl SessionReader<Box<dyn BufRead + Send>> {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        Ok(Self::new(open_session_file(path)?))
+    }
+}
+
+impl<R: BufRead> SessionReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: String::new(),
+            first_line: 0,
+            line: 0,
+            malformed: 0,
+            filter: keep_all,
+        }
+    }
+}
+
+impl<R: BufRead, F> SessionReader<R, F> {
+    /// reader 已定位到第 `lines_before + 1` 行的开头时，从该行号继续编号
+    pub fn numbered_from(mut self, lines_before: usize) -> Self {
+        self.first_line = lines_before;
+        self.line = lines_before;
+        self
+    }
+
+    /// 解析前按 (行号, 原始行) 过滤，用于行号范围、子串预筛等廉价条件
+    pub fn filter_lines<G: FnMut(usize, &str) -> bool>(self, filter: G) -> SessionReader<R, G> {
+        SessionReader {
+            reader: self.reader,
+            buf: self.buf,
+            first_line: self.first_line,
+            line: self.line,
+            malformed: self.malformed,
+            filter,
+        }
+    }
+
+    /// 已读取的行数（含被过滤和无法解析的行）
+    pub fn lines_read(&self) -> usize {
+        self.line - self.first_line
+    }
+
+    /// 通过过滤但无法解析的行数
+    pub fn malformed(&self) -> usize {
+        self.malformed
+    }
+}
+
+impl<R: BufRead, F: FnMut(usize, &str) -> bool> Iterator for SessionReader<R, F> {
+    type Item = (usize, MessageRecord);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.buf.clear();
+            match self.reader.read_line(&mut self.buf) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                // 非 UTF-8 行已被消费，跳过即可；其他 IO 错误无法继续读取
+                Err(e) if e.kind() == ErrorKind::InvalidData => {
+                    self.line += 1;
+                    continue;
+                }
+                Err(_) => return None,
+            }
+            self.line += 1;
+
+            let line = self.buf.trim_end_matches(['\n', '\r']);
+            if !(self.filter)(self.line, line) {
+                continue;
+            }
+            match MessageRecord::parse(line) {
+                Some(record) => return Some((self.line, record)),
+                None => self.malformed += 1,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reader_numbering_and_filters() {
+        let data = concat!(
+            r#"{"type":"user","message":{"content":"one"}}"#,
+            "\n\n",
+            "{\"type\":\n",
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"four"}]}}"#,
+            "\r\n",
+            r#"{"type":"user","message":{"content":"five"}}"#,
+        );
+
+        let mut reader = SessionReader::new(data.as_bytes());
+        let lines: Vec<usize> = reader.by_ref().map(|(line, _)| line).collect();
+        assert_eq!(lines, [1, 4, 5]);
+        assert_eq!(reader.lines_read(), 5);
+        assert_eq!(reader.malformed(), 2);
+
+        let mut reader = SessionReader::new(data.as_bytes()).filter_lines(|line, raw| line > 1 && raw.contains("f"));
+        let (line, record) = reader.next().unwrap();
+        assert_eq!((line, record.classify()), (4, ("assistant", "text")));
+        assert_eq!(reader.next().unwrap().0, 5);
+        assert!(reader.next().is_none());
+        assert_eq!(reader.malformed(), 0);
+
+        let reader = SessionReader::new(
+            &data.as_bytes()[data.find("{\"type\":\"user\",\"message\":{\"content\":\"five").unwrap()..],
+        )
+        .numbered_from(4);
+        assert_eq!(reader.map(|(line, _)| line).collect::<Vec<_>>(), [5]);
+    }
+}
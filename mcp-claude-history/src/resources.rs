@@ -0,0 +1,251 @@
+//! MCP resources：把项目、会话和单条消息以 history:// URI 暴露，客户端可直接附加到上下文
+//!
+//! - `history://projects`：项目列表（JSON）
+//! - `history://project/{id}/sessions`：项目的会话列表（JSON）
+//! - `history://session/{prefix}[?project=id]`：整个会话的 Markdown 记录
+//! - `history://message/{ref}[?project=id]`：单条消息文本，图片作为 blob 内容返回
+//!
+//! resources 没有调用参数，是否脱敏由服务端决定（MCP 模式总是脱敏）
+
+use rmcp::model::{AnnotateAble, RawResource, RawResourceTemplate, Resource, ResourceContents, ResourceTemplate};
+
+use crate::config::Config;
+use crate::export::session_markdown;
+use crate::get::read_record;
+use crate::model::ContentBlock;
+use crate::projects::list_projects;
+use crate::redact::Redactor;
+use crate::sessions::{SessionsParams, list_sessions};
+use crate::types::*;
+use crate::utils::*;
+
+const SCHEME: &str = "history://";
+
+/// 解析后的 resource URI
+#[derive(Debug, PartialEq, Eq)]
+pub enum HistoryUri {
+    Projects,
+    Sessions { project: String },
+    Session { prefix: String, project: Option<String> },
+    Message { r#ref: String, project: Option<String> },
+}
+
+impl HistoryUri {
+    pub fn parse(uri: &str) -> Result<Self, ErrorResponse> {
+        let invalid = || ErrorResponse {
+            error: "invalid_uri".to_string(),
+            message: format!(
+                "无效的 resource URI: {}，可选 history://projects、history://project/{{id}}/sessions、\
+                 history://session/{{prefix}}、history://message/{{ref}}",
+                uri
+            ),
+            available: None,
+        };
+
+        let rest = uri.strip_prefix(SCHEME).ok_or_else(invalid)?;
+        let (path, query) = match rest.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (rest, None),
+        };
+        let project = match query {
+            None => None,
+            Some(query) => Some(
+                query
+                    .strip_prefix("project=")
+                    .filter(|p| !p.is_empty() && !p.contains('&'))
+                    .ok_or_else(invalid)?
+                    .to_string(),
+            ),
+        };
+
+        let segments: Vec<&str> = path.split('/').collect();
+        let parsed = match segments.as_slice() {
+            ["projects"] => Self::Projects,
+            ["project", id, "sessions"] if !id.is_empty() => Self::Sessions {
+                project: id.to_string(),
+            },
+            ["session", prefix] if !prefix.is_empty() => Self::Session {
+                prefix: prefix.to_string(),
+                project,
+            },
+            ["message", r#ref] if ParsedRef::parse(r#ref).is_some() => Self::Message {
+                r#ref: r#ref.to_string(),
+                project,
+            },
+            _ => return Err(invalid()),
+        };
+        // 只有 session / message 接受 ?project=
+        if query.is_some() && matches!(parsed, Self::Projects | Self::Sessions { .. }) {
+            return Err(invalid());
+        }
+        Ok(parsed)
+    }
+}
+
+/// 列出具体 resource：项目列表、每个项目的会话列表，以及当前项目的各个会话
+pub fn list_resources(config: &Config) -> Vec<Resource> {
+    let mut resources = vec![
+        RawResource::new(format!("{}projects", SCHEME), "projects")
+            .with_description("All projects with conversation history")
+            .with_mime_type("application/json")
+            .no_annotation(),
+    ];
+
+    if let Ok(projects) = list_projects(config) {
+        for project in &projects.projects {
+            resources.push(
+                RawResource::new(format!("{}project/{}/sessions", SCHEME, project.id), project.id.clone())
+                    .with_title(format!("Sessions of {}", project.path))
+                    .with_mime_type("application/json")
+                    .no_annotation(),
+            );
+        }
+    }
+
+    if let Some(project_id) = config.current_project_id()
+        && let Ok(sessions) = list_sessions(
+            config,
+            SessionsParams {
+                project: Some(project_id.clone()),
+                ..Default::default()
+            },
+        )
+    {
+        for session in &sessions.sessions {
+            let mut resource = RawResource::new(
+                format!("{}session/{}?project={}", SCHEME, session.ref_prefix, project_id),
+                session.id.clone(),
+            )
+            .with_mime_type("text/markdown")
+            .with_description(format!("{} – {}", session.start_time, session.end_time));
+            if let Some(topic) = &session.topic {
+                resource = resource.with_title(topic.clone());
+            }
+            resources.push(resource.no_annotation());
+        }
+    }
+
+    resources
+}
+
+/// resource 模板（RFC 6570）
+pub fn resource_templates() -> Vec<ResourceTemplate> {
+    vec![
+        RawResourceTemplate::new(format!("{}project/{{project}}/sessions", SCHEME), "project_sessions")
+            .with_description("Sessions of a project (JSON)")
+            .with_mime_type("application/json")
+            .no_annotation(),
+        RawResourceTemplate::new(format!("{}session/{{prefix}}{{?project}}", SCHEME), "session")
+            .with_description("Whole session as a Markdown transcript (prefix = first 8 chars of the session ID)")
+            .with_mime_type("text/markdown")
+            .no_annotation(),
+        RawResourceTemplate::new(format!("{}message/{{ref}}{{?project}}", SCHEME), "message")
+            .with_description("Single message text by ref (prefix8:line); images are returned as blob contents")
+            .no_annotation(),
+    ]
+}
+
+/// 读取 resource 内容
+pub fn read_resource(
+    config: &Config,
+    uri: &str,
+    redactor: Option<&Redactor>,
+) -> Result<Vec<ResourceContents>, ErrorResponse> {
+    let json = |value: serde_json::Value| {
+        ResourceContents::text(serde_json::to_string_pretty(&value).unwrap_or_default(), uri)
+            .with_mime_type("application/json")
+    };
+
+    match HistoryUri::parse(uri)? {
+        HistoryUri::Projects => {
+            let projects = list_projects(config)?;
+            Ok(vec![json(serde_json::to_value(projects).unwrap_or_default())])
+        }
+        HistoryUri::Sessions { project } => {
+            let sessions = list_sessions(
+                config,
+                SessionsParams {
+                    project: Some(project),
+                    redactor: redactor.cloned(),
+                    ..Default::default()
+                },
+            )?;
+            Ok(vec![json(serde_json::to_value(sessions).unwrap_or_default())])
+        }
+        HistoryUri::Session { prefix, project } => {
+            let markdown = session_markdown(config, &prefix, project.as_deref(), redactor)?;
+            Ok(vec![
+                ResourceContents::text(markdown, uri).with_mime_type("text/markdown"),
+            ])
+        }
+        HistoryUri::Message { r#ref, project } => {
+            let (_, record) = read_record(config, &r#ref, project.as_deref())?;
+            let mut text = replace_images_with_placeholders(&record);
+            if let Some(redactor) = redactor {
+                redactor.redact(&mut text);
+            }
+            let mut contents = vec![ResourceContents::text(text, uri).with_mime_type("text/plain")];
+            contents.extend(
+                image_blobs(&record)
+                    .into_iter()
+                    .map(|(media_type, data)| ResourceContents::blob(data, uri).with_mime_type(media_type)),
+            );
+            Ok(contents)
+        }
+    }
+}
+
+/// 消息中的图片：(media_type, base64 数据)
+fn image_blobs(record: &MessageRecord) -> Vec<(String, String)> {
+    record
+        .blocks()
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::Image { source } if !source.data.is_empty() => {
+                Some((source.media_type.clone(), source.data.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uri() {
+        assert_eq!(HistoryUri::parse("history://projects").unwrap(), HistoryUri::Projects);
+        assert_eq!(
+            HistoryUri::parse("history://project/-home-me-app/sessions").unwrap(),
+            HistoryUri::Sessions {
+                project: "-home-me-app".to_string()
+            }
+        );
+        assert_eq!(
+            HistoryUri::parse("history://session/aaaabbbb?project=-p").unwrap(),
+            HistoryUri::Session {
+                prefix: "aaaabbbb".to_string(),
+                project: Some("-p".to_string())
+            }
+        );
+        assert_eq!(
+            HistoryUri::parse("history://message/aaaabbbb:12").unwrap(),
+            HistoryUri::Message {
+                r#ref: "aaaabbbb:12".to_string(),
+                project: None
+            }
+        );
+
+        for bad in [
+            "file:///etc/passwd",
+            "history://message/aaaabbbb",
+            "history://projects?project=x",
+            "history://session/aaaabbbb?project=",
+            "history://session/",
+            "history://project/x/sessions/extra",
+        ] {
+            assert_eq!(HistoryUri::parse(bad).unwrap_err().error, "invalid_uri", "{}", bad);
+        }
+    }
+}
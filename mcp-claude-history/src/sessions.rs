@@ -1,13 +1,68 @@
 use crate::config::Config;
+use crate::model::{ContentBlock, MessageRecord, Usage};
+use crate::reader::SessionReader;
+use crate::redact::Redactor;
+use crate::subagents::{TaskCalls, subagents_dir};
 use crate::types::*;
 use crate::utils::*;
-use std::fs::{self, File};
-use std::io::{BufRead, BufReader};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 会话排序方式（均为降序）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SessionSort {
+    /// 按最后活动时间（默认）
+    #[default]
+    Activity,
+    /// 按文件大小
+    Size,
+    /// 按消息数
+    Messages,
+    /// 按 token 总量
+    Tokens,
+}
+
+impl SessionSort {
+    pub fn parse(s: &str) -> Result<Self, ErrorResponse> {
+        match s {
+            "activity" => Ok(Self::Activity),
+            "size" => Ok(Self::Size),
+            "messages" => Ok(Self::Messages),
+            "tokens" => Ok(Self::Tokens),
+            _ => Err(ErrorResponse {
+                error: "invalid_sort".to_string(),
+                message: format!("无效的 sort: {}，可选 activity / size / messages / tokens", s),
+                available: None,
+            }),
+        }
+    }
+}
+
+/// 会话列表参数
+#[derive(Default)]
+pub struct SessionsParams {
+    pub project: Option<String>,
+    pub sort: SessionSort,
+    /// 只保留出现过该分支的会话（不区分大小写）
+    pub branch: Option<String>,
+    /// 只保留用过该模型的会话（子串匹配，不区分大小写）
+    pub model: Option<String>,
+    /// 最少消息数（不含 progress 等内部记录）
+    pub min_messages: usize,
+    /// 活动时间窗口：会话的 [start_time, end_time] 与之有交集即保留
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    pub until: Option<chrono::DateTime<chrono::Utc>>,
+    pub limit: Option<usize>,
+    /// 脱敏主题、compact 摘要与 subagent 描述（先于截断进行）
+    pub redactor: Option<Redactor>,
+}
 
 /// 列出项目的会话
-pub fn list_sessions(config: &Config, project_id: Option<&str>) -> Result<SessionsResponse, ErrorResponse> {
+pub fn list_sessions(config: &Config, params: SessionsParams) -> Result<SessionsResponse, ErrorResponse> {
     // 确定项目
-    let project_id = match project_id {
+    let project_id = match params.project.as_deref() {
         Some(id) => id.to_string(),
         None => config.current_project_id().ok_or_else(|| {
             let available = config.available_projects_json();
@@ -38,108 +93,304 @@ pub fn list_sessions(config: &Config, project_id: Option<&str>) -> Result<Sessio
         available: None,
     })?;
 
-    let mut sessions = Vec::new();
-
-    for entry in entries.flatten() {
-        let path = entry.path();
-        if !path.extension().map(|e| e == "jsonl").unwrap_or(false) {
-            continue;
-        }
+    let files: Vec<(String, u64, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let session_id = session_id_from_filename(&entry.file_name().to_string_lossy())?;
+            if shadowed_by_plain(&entry.path(), &session_id) {
+                return None;
+            }
+            let size_bytes = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            Some((session_id, size_bytes, entry.path()))
+        })
+        .collect();
 
-        let filename = entry.file_name().to_string_lossy().to_string();
-        let Some(session_id) = session_id_from_filename(&filename) else {
-            continue;
-        };
+    // 统计需要完整解析每个文件，按文件并行
+    let mut sessions: Vec<SessionInfo> = files
+        .par_iter()
+        .map(|(session_id, size_bytes, path)| {
+            let (mut info, calls) = get_session_stats(path);
+            info.id = session_id.clone();
+            info.ref_prefix = ref_prefix(session_id);
+            info.size_bytes = *size_bytes;
+            info.subagents = calls.link(session_id, &subagents_dir(path, session_id));
+            info.subagent_count = info.subagents.len();
+            info
+        })
+        .filter(|info| session_matches(info, &params))
+        .collect();
 
-        // 获取文件信息
-        let meta = entry.metadata().ok();
-        let size_bytes = meta.as_ref().map(|m| m.len()).unwrap_or(0);
-
-        // 统计行数并获取时间范围和主题
-        let (line_count, start_time, end_time, topic) = get_session_stats(&path);
-
-        sessions.push(SessionInfo {
-            id: session_id.clone(),
-            ref_prefix: ref_prefix(&session_id),
-            line_count,
-            start_time,
-            end_time,
-            size_bytes,
-            topic,
-        });
+    match params.sort {
+        SessionSort::Activity => sessions.sort_by(|a, b| b.end_time.cmp(&a.end_time)),
+        SessionSort::Size => sessions.sort_by_key(|s| std::cmp::Reverse(s.size_bytes)),
+        SessionSort::Messages => sessions.sort_by_key(|s| std::cmp::Reverse(s.message_count)),
+        SessionSort::Tokens => sessions.sort_by_key(|s| std::cmp::Reverse(s.total_tokens)),
     }
 
-    // 按结束时间排序（最新的在前）
-    sessions.sort_by(|a, b| b.end_time.cmp(&a.end_time));
+    let total = sessions.len();
+    sessions.truncate(params.limit.unwrap_or(usize::MAX));
+    let redactions: usize = sessions
+        .iter_mut()
+        .map(|info| finish_previews(info, params.redactor.as_ref()))
+        .sum();
 
     Ok(SessionsResponse {
         project: project_id,
+        total,
         sessions,
+        redactions: params.redactor.as_ref().map(|_| redactions),
     })
 }
 
-/// 获取会话统计信息
-fn get_session_stats(path: &std::path::Path) -> (usize, String, String, Option<String>) {
-    let file = match File::open(path) {
-        Ok(f) => f,
-        Err(_) => return (0, String::new(), String::new(), None),
+/// 检查会话是否满足过滤条件
+fn session_matches(info: &SessionInfo, params: &SessionsParams) -> bool {
+    if info.message_count < params.min_messages {
+        return false;
+    }
+    if let Some(branch) = &params.branch
+        && !info.git_branches.iter().any(|b| b.eq_ignore_ascii_case(branch))
+    {
+        return false;
+    }
+    if let Some(model) = &params.model {
+        let model = model.to_lowercase();
+        if !info.models.iter().any(|m| m.to_lowercase().contains(&model)) {
+            return false;
+        }
+    }
+    // 时间无法解析的会话不按时间过滤
+    if let Some(since) = params.since
+        && parse_time(&info.end_time).is_some_and(|end| end < since)
+    {
+        return false;
+    }
+    if let Some(until) = params.until
+        && parse_time(&info.start_time).is_some_and(|start| start > until)
+    {
+        return false;
+    }
+    true
+}
+
+/// 会话主题的最大展示长度（字符）
+const TOPIC_PREVIEW_CHARS: usize = 100;
+/// compact 摘要的最大展示长度（字符）
+const SUMMARY_PREVIEW_CHARS: usize = 1000;
+
+/// 主题与 compact 摘要截断为预览（subagent 描述不截断）；需要脱敏时先于截断进行，返回替换次数
+fn finish_previews(info: &mut SessionInfo, redactor: Option<&Redactor>) -> usize {
+    let mut count = 0;
+    if let Some(redactor) = redactor {
+        for text in [info.topic.as_mut(), info.latest_summary.as_mut()]
+            .into_iter()
+            .flatten()
+            .chain(info.subagents.iter_mut().filter_map(|s| s.description.as_mut()))
+        {
+            count += redactor.redact(text);
+        }
+    }
+    info.topic = info.topic.as_deref().map(|t| preview(t, TOPIC_PREVIEW_CHARS));
+    info.latest_summary = info
+        .latest_summary
+        .as_deref()
+        .map(|s| preview(s, SUMMARY_PREVIEW_CHARS));
+    count
+}
+
+/// 截断到 max_chars 个字符，截断时追加 `...`
+pub(crate) fn preview(text: &str, max_chars: usize) -> String {
+    let (text, truncated) = truncate_content(text, max_chars);
+    if truncated { format!("{}...", text) } else { text }
+}
+
+/// 获取会话统计信息，同时收集 Task 调用（id / ref_prefix / size_bytes / subagents 由调用方填充；
+/// topic 与 latest_summary 为全文，由 finish_previews 截断）
+fn get_session_stats(path: &Path) -> (SessionInfo, TaskCalls) {
+    let mut info = SessionInfo::default();
+    let mut calls = TaskCalls::default();
+    let Ok(mut reader) = SessionReader::open(path) else {
+        return (info, calls);
     };
 
-    let reader = BufReader::new(file);
-    let mut line_count = 0;
-    let mut start_time = String::new();
-    let mut end_time = String::new();
-    let mut topic: Option<String> = None;
+    // 流式写入时同一 message.id 会重复出现，以最后一行的 usage 为准
+    let mut usage_by_id: HashMap<String, Usage> = HashMap::new();
+    let mut usage_without_id = Vec::new();
+    let mut latest_summary = None;
 
-    for line in reader.lines() {
-        let line = match line {
-            Ok(l) => l,
-            Err(_) => continue,
-        };
-        line_count += 1;
+    for (line, record) in reader.by_ref() {
+        calls.observe(line, &record);
+        // summary 等记录没有时间戳，不参与活动时间
+        if !record.timestamp.is_empty() {
+            if info.start_time.is_empty() {
+                info.start_time = record.timestamp.clone();
+            }
+            info.end_time = record.timestamp.clone();
+        }
 
-        // 只解析时间字段，不解析整个消息
-        if let Ok(record) = serde_json::from_str::<MessageRecord>(&line) {
-            if start_time.is_empty() {
-                start_time = record.timestamp.clone();
+        let (effective_type, subtype) = record.classify();
+        if effective_type != "other" {
+            *info.message_counts.entry(subtype.to_string()).or_default() += 1;
+            info.message_count += 1;
+        }
+        for block in record.blocks() {
+            if let ContentBlock::ToolUse { name, .. } = block {
+                *info.tool_calls.entry(name.clone()).or_default() += 1;
             }
-            end_time = record.timestamp.clone();
-
-            // 提取首条 user 消息作为 topic（跳过 summary 和 meta）
-            if topic.is_none() && record.msg_type == "user" && !record.is_compact_summary && !record.is_meta {
-                if let Some(text) = extract_topic_text(&record) {
-                    if !text.is_empty() {
-                        let preview: String = text.chars().take(100).collect();
-                        topic = Some(if text.chars().count() > 100 {
-                            format!("{}...", preview)
-                        } else {
-                            preview
-                        });
+        }
+        if let Some(message) = &record.message {
+            observe(&mut info.models, message.model.as_deref());
+            if let Some(usage) = message.usage {
+                match &message.id {
+                    Some(id) => {
+                        usage_by_id.insert(id.clone(), usage);
                     }
+                    None => usage_without_id.push(usage),
                 }
             }
         }
+        observe(&mut info.git_branches, record.git_branch.as_deref());
+        observe(&mut info.cwds, record.cwd.as_deref());
+
+        if record.is_compact_summary {
+            info.compact_summary_count += 1;
+            latest_summary = Some(record);
+            continue;
+        }
+
+        if info.topic.is_none() {
+            info.topic = topic_text(&record);
+        }
     }
 
-    (line_count, start_time, end_time, topic)
+    info.line_count = reader.lines_read();
+    info.total_tokens = usage_by_id
+        .values()
+        .chain(&usage_without_id)
+        .map(|u| u.input_tokens + u.output_tokens + u.cache_creation_input_tokens + u.cache_read_input_tokens)
+        .sum();
+    info.latest_summary = latest_summary.map(|record| replace_images_with_placeholders(&record));
+    (info, calls)
 }
 
-/// 从消息记录中提取文本内容（用于生成会话主题）
-fn extract_topic_text(record: &MessageRecord) -> Option<String> {
-    let message = record.message.as_ref()?;
-    let content = message.get("content")?;
-
-    if let Some(s) = content.as_str() {
-        return Some(s.to_string());
+/// 按首次出现顺序记录不重复的值
+fn observe(values: &mut Vec<String>, value: Option<&str>) {
+    if let Some(value) = value
+        && !value.is_empty()
+        && !values.iter().any(|v| v == value)
+    {
+        values.push(value.to_string());
     }
+}
 
-    if let Some(arr) = content.as_array() {
-        for item in arr {
-            if let Some(text) = item.get("text").and_then(|t| t.as_str()) {
-                return Some(text.to_string());
-            }
-        }
+/// 会话主题：首条 user 消息的全文（跳过 summary 和 meta），展示前用 topic_preview 截断
+pub(crate) fn session_topic(path: &Path) -> Option<String> {
+    SessionReader::open(path)
+        .ok()?
+        .filter(|(_, record)| !record.is_compact_summary)
+        .find_map(|(_, record)| topic_text(&record))
+}
+
+/// 主题预览：前 100 个字符
+pub(crate) fn topic_preview(topic: &str) -> String {
+    preview(topic, TOPIC_PREVIEW_CHARS)
+}
+
+fn topic_text(record: &MessageRecord) -> Option<String> {
+    if record.msg_type != "user" || record.is_meta {
+        return None;
     }
+    extract_topic_text(record).filter(|t| !t.is_empty()).map(str::to_string)
+}
+
+/// 从消息记录中提取文本内容（用于生成会话主题）
+fn extract_topic_text(record: &MessageRecord) -> Option<&str> {
+    record.blocks().iter().find_map(|block| match block {
+        ContentBlock::Text { text } => Some(text.as_str()),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_stats_and_filters() {
+        let home = std::env::temp_dir().join(format!("mcp-sessions-test-{}", std::process::id()));
+        let project = home.join("projects").join("-p");
+        let subagents = project.join("aaaa1111-0000").join("subagents");
+        std::fs::create_dir_all(&subagents).unwrap();
+        std::fs::write(subagents.join("agent-x.jsonl"), "").unwrap();
+        let lines = [
+            r#"{"type":"user","timestamp":"2026-05-01T00:00:00Z","gitBranch":"feature/x","cwd":"/w","message":{"role":"user","content":"fix the bug"}}"#,
+            r#"{"type":"assistant","timestamp":"2026-05-01T00:00:01Z","gitBranch":"feature/x","message":{"id":"m1","model":"claude-x","usage":{"input_tokens":1,"output_tokens":1},"content":[{"type":"tool_use","id":"t1","name":"Bash","input":{}}]}}"#,
+            r#"{"type":"assistant","timestamp":"2026-05-01T00:00:01Z","message":{"id":"m1","model":"claude-x","usage":{"input_tokens":1,"output_tokens":5},"content":[{"type":"text","text":"done"}]}}"#,
+            r#"{"type":"user","timestamp":"2026-05-01T00:00:02Z","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"t1","content":"ok"}]}}"#,
+            r#"{"type":"progress","timestamp":"2026-05-01T00:00:03Z"}"#,
+            r#"{"type":"user","isCompactSummary":true,"timestamp":"2026-05-01T00:00:04Z","gitBranch":"main","message":{"role":"user","content":"summary text"}}"#,
+        ];
+        std::fs::write(project.join("aaaa1111-0000.jsonl"), lines.join("\n")).unwrap();
+        std::fs::write(
+            project.join("bbbb2222-0000.jsonl"),
+            r#"{"type":"user","timestamp":"2026-04-01T00:00:00Z","message":{"role":"user","content":"old"}}"#,
+        )
+        .unwrap();
+
+        let config = Config::for_test(&home);
+        let list = |params: SessionsParams| {
+            list_sessions(
+                &config,
+                SessionsParams {
+                    project: Some("-p".to_string()),
+                    ..params
+                },
+            )
+            .unwrap()
+        };
+        let ids = |response: SessionsResponse| response.sessions.into_iter().map(|s| s.id).collect::<Vec<_>>();
+
+        let response = list(SessionsParams::default());
+        assert_eq!(response.total, 2);
+        let info = &response.sessions[0];
+        assert_eq!(info.id, "aaaa1111-0000");
+        assert_eq!(info.line_count, 6);
+        assert_eq!(info.message_count, 5);
+        assert_eq!(info.message_counts["tool_use"], 1);
+        assert_eq!(info.message_counts["summary"], 1);
+        assert_eq!(info.tool_calls["Bash"], 1);
+        assert_eq!(info.models, ["claude-x"]);
+        assert_eq!(info.total_tokens, 6);
+        assert_eq!(info.git_branches, ["feature/x", "main"]);
+        assert_eq!(info.cwds, ["/w"]);
+        assert_eq!(info.compact_summary_count, 1);
+        assert_eq!(info.subagent_count, 1);
+        assert_eq!(info.subagents[0].r#ref, "x:1");
+        assert!(info.subagents[0].parent_ref.is_none());
+        assert_eq!(info.latest_summary.as_deref(), Some("summary text"));
+        assert_eq!(info.topic.as_deref(), Some("fix the bug"));
+
+        let filtered = list(SessionsParams {
+            branch: Some("Feature/X".to_string()),
+            model: Some("CLAUDE".to_string()),
+            min_messages: 2,
+            since: parse_time("2026-04-15"),
+            ..Default::default()
+        });
+        assert_eq!(ids(filtered), ["aaaa1111-0000"]);
+        let before = list(SessionsParams {
+            until: parse_time("2026-04-15"),
+            min_messages: 2,
+            ..Default::default()
+        });
+        assert!(before.sessions.is_empty());
+        let by_size = list(SessionsParams {
+            sort: SessionSort::Size,
+            limit: Some(1),
+            ..Default::default()
+        });
+        assert_eq!(by_size.total, 2);
+        assert_eq!(ids(by_size), ["aaaa1111-0000"]);
 
-    None
+        std::fs::remove_dir_all(&home).ok();
+    }
 }
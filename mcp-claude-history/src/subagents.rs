@@ -0,0 +1,326 @@
+//! subagent 关联：`<project>/<session>/subagents/agent-<id>.jsonl` ↔ 父会话中启动它的 Task 调用
+//!
+//! 父会话由所在目录名确定。Task 调用按两种方式匹配：
+//! 1. Task 结果记录的 `toolUseResult.agentId` 与文件名中的 id 相同
+//! 2. 否则按子会话首条 user 消息与 Task 输入的 `prompt` 逐字比较，取尚未关联的第一个调用（子会话按开始时间依次匹配）
+//!
+//! 都匹配不上的子会话仍会列出，只是没有 parent_ref
+
+use crate::model::{ContentBlock, MessageRecord};
+use crate::reader::SessionReader;
+use crate::types::SubagentInfo;
+use crate::utils::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 启动 subagent 的工具名（新版本 Claude Code 改名为 Agent）
+const SPAWN_TOOLS: [&str; 2] = ["Task", "Agent"];
+
+/// 父会话中的一次 Task 调用
+struct TaskCall {
+    line: usize,
+    tool_use_id: String,
+    description: Option<String>,
+    subagent_type: Option<String>,
+    prompt: String,
+    /// 结果记录的行号与 toolUseResult.agentId
+    result_line: Option<usize>,
+    agent_id: Option<String>,
+}
+
+/// 逐条观察父会话记录，收集 Task 调用及其结果
+#[derive(Default)]
+pub struct TaskCalls {
+    calls: Vec<TaskCall>,
+    /// tool_use_id → calls 下标
+    by_id: HashMap<String, usize>,
+}
+
+/// subagent 会话文件的开头：首条记录的行号、时间戳，以及首条 user 消息的文本（即 Task 的 prompt）
+struct SubagentHead {
+    line: usize,
+    timestamp: String,
+    prompt: Option<String>,
+}
+
+impl TaskCalls {
+    pub fn observe(&mut self, line: usize, record: &MessageRecord) {
+        for block in record.blocks() {
+            match block {
+                ContentBlock::ToolUse { id, name, input } if SPAWN_TOOLS.contains(&name.as_str()) => {
+                    let field = |key: &str| input.get(key).and_then(|v| v.as_str()).map(str::to_string);
+                    self.by_id.insert(id.clone(), self.calls.len());
+                    self.calls.push(TaskCall {
+                        line,
+                        tool_use_id: id.clone(),
+                        description: field("description"),
+                        subagent_type: field("subagent_type"),
+                        prompt: field("prompt").unwrap_or_default(),
+                        result_line: None,
+                        agent_id: None,
+                    });
+                }
+                ContentBlock::ToolResult { tool_use_id, .. } => {
+                    if let Some(&i) = self.by_id.get(tool_use_id) {
+                        let call = &mut self.calls[i];
+                        call.result_line = Some(line);
+                        if record.result_agent_id.is_some() {
+                            call.agent_id = record.result_agent_id.clone();
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// 关联 dir（`<session>/subagents`）下的全部子会话；按 Task 调用顺序排列，无法关联的按文件名排在最后
+    pub fn link(&self, parent_session: &str, dir: &Path) -> Vec<SubagentInfo> {
+        let files = subagent_files(dir);
+        if files.is_empty() {
+            return Vec::new();
+        }
+        let heads: Vec<Option<SubagentHead>> = files.iter().map(|(_, path)| subagent_head(path)).collect();
+
+        let mut claimed = vec![false; self.calls.len()];
+        let mut matched: Vec<Option<usize>> = files
+            .iter()
+            .map(|(session, _)| {
+                let agent_id = session.strip_prefix("agent-")?;
+                let i = self
+                    .calls
+                    .iter()
+                    .position(|c| c.agent_id.as_deref() == Some(agent_id))?;
+                claimed[i] = true;
+                Some(i)
+            })
+            .collect();
+
+        // 按 prompt 匹配剩余的子会话：先开始的子会话先认领
+        let mut pending: Vec<usize> = (0..files.len()).filter(|&f| matched[f].is_none()).collect();
+        pending.sort_by_key(|&f| heads[f].as_ref().map(|h| h.timestamp.clone()));
+        for f in pending {
+            let Some(prompt) = heads[f].as_ref().and_then(|h| h.prompt.as_deref()) else {
+                continue;
+            };
+            if let Some(i) = (0..self.calls.len()).find(|&i| !claimed[i] && self.calls[i].prompt == prompt) {
+                claimed[i] = true;
+                matched[f] = Some(i);
+            }
+        }
+
+        let parent_prefix = ref_prefix(parent_session);
+        let mut links: Vec<(Option<usize>, SubagentInfo)> = files
+            .into_iter()
+            .zip(heads)
+            .zip(matched)
+            .map(|(((session, _), head), call)| {
+                let line = head.map_or(1, |h| h.line);
+                let call_info = call.map(|i| &self.calls[i]);
+                let info = SubagentInfo {
+                    r#ref: format!("{}:{}", ref_prefix(&session), line),
+                    session,
+                    parent_ref: call_info.map(|c| format!("{}:{}", parent_prefix, c.line)),
+                    tool_use_id: call_info.map(|c| c.tool_use_id.clone()),
+                    description: call_info.and_then(|c| c.description.clone()),
+                    subagent_type: call_info.and_then(|c| c.subagent_type.clone()),
+                };
+                (call, info)
+            })
+            .collect();
+        links.sort_by_key(|(call, _)| call.unwrap_or(usize::MAX));
+        links.into_iter().map(|(_, info)| info).collect()
+    }
+
+    /// 父会话中 Task 调用及其结果所在行 → 子会话首条消息的 ref
+    pub fn jump_refs(&self, links: &[SubagentInfo]) -> HashMap<usize, String> {
+        let mut refs = HashMap::new();
+        for link in links {
+            let Some(&i) = link.tool_use_id.as_ref().and_then(|id| self.by_id.get(id)) else {
+                continue;
+            };
+            let call = &self.calls[i];
+            for line in std::iter::once(call.line).chain(call.result_line) {
+                refs.insert(line, link.r#ref.clone());
+            }
+        }
+        refs
+    }
+}
+
+/// 父会话的 subagent 目录：`<project>/<session>/subagents`
+pub fn subagents_dir(parent_path: &Path, parent_session: &str) -> PathBuf {
+    parent_path.with_file_name(parent_session).join("subagents")
+}
+
+/// 目录下的子会话文件（含归档），按文件名排序
+pub fn subagent_files(dir: &Path) -> Vec<(String, PathBuf)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<(String, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let session = session_file_stem(&name)?;
+            let path = entry.path();
+            (session.starts_with("agent-") && !shadowed_by_plain(&path, session)).then(|| (session.to_string(), path))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// 读取父会话并关联其全部子会话；没有 subagent 目录时不读取父会话
+pub fn link_session(parent_path: &Path, parent_session: &str) -> Vec<SubagentInfo> {
+    let dir = subagents_dir(parent_path, parent_session);
+    if subagent_files(&dir).is_empty() {
+        return Vec::new();
+    }
+    let mut calls = TaskCalls::default();
+    if let Ok(reader) = SessionReader::open(parent_path) {
+        for (line, record) in reader {
+            calls.observe(line, &record);
+        }
+    }
+    calls.link(parent_session, &dir)
+}
+
+/// 子会话所属的父会话：(父会话 ID, 父会话文件)；父会话文件不存在时返回 None
+pub fn parent_session_file(subagent_path: &Path) -> Option<(String, PathBuf)> {
+    let dir = subagent_path.parent()?;
+    if dir.file_name()? != "subagents" {
+        return None;
+    }
+    let session_dir = dir.parent()?;
+    let session = session_dir.file_name()?.to_string_lossy().to_string();
+    let path = [".jsonl", ".jsonl.gz", ".jsonl.zst"]
+        .iter()
+        .map(|ext| session_dir.with_file_name(format!("{}{}", session, ext)))
+        .find(|p| p.exists())?;
+    Some((session, path))
+}
+
+/// 单个子会话的关联信息（从子会话一侧查找父会话）
+pub fn subagent_link(subagent_path: &Path, session: &str) -> Option<SubagentInfo> {
+    let (parent_session, parent_path) = parent_session_file(subagent_path)?;
+    link_session(&parent_path, &parent_session)
+        .into_iter()
+        .find(|link| link.session == session)
+}
+
+fn subagent_head(path: &Path) -> Option<SubagentHead> {
+    let mut reader = SessionReader::open(path).ok()?;
+    let (line, first) = reader.next()?;
+    let mut head = SubagentHead {
+        line,
+        timestamp: first.timestamp.clone(),
+        prompt: None,
+    };
+    // 首条 user 消息通常就是第一行；之前只允许有少量非对话记录
+    for (_, record) in std::iter::once((line, first)).chain(reader.take(4)) {
+        if record.msg_type == "user" && !record.is_meta {
+            head.prompt = Some(
+                record
+                    .blocks()
+                    .iter()
+                    .filter_map(|b| match b {
+                        ContentBlock::Text { text } => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            );
+            break;
+        }
+    }
+    Some(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_subagents() {
+        let home = std::env::temp_dir().join(format!("mcp-subagents-test-{}", std::process::id()));
+        let project = home.join("-p");
+        let dir = project.join("aaaa1111-0000").join("subagents");
+        fs::create_dir_all(&dir).unwrap();
+        let task = |id: &str, prompt: &str| {
+            format!(
+                r#"{{"type":"assistant","message":{{"content":[{{"type":"tool_use","id":"{}","name":"Task","input":{{"description":"d-{}","prompt":"{}","subagent_type":"general-purpose"}}}}]}}}}"#,
+                id, id, prompt
+            )
+        };
+        let result = |id: &str, agent: Option<&str>| {
+            let extra = agent.map_or(String::new(), |a| format!(r#","toolUseResult":{{"agentId":"{}"}}"#, a));
+            format!(
+                r#"{{"type":"user"{},"message":{{"content":[{{"type":"tool_result","tool_use_id":"{}","content":"ok"}}]}}}}"#,
+                extra, id
+            )
+        };
+        let parent = [
+            r#"{"type":"user","message":{"content":"go"}}"#.to_string(),
+            task("t1", "find the bug"),
+            task("t2", "write tests"),
+            result("t2", Some("bbb")),
+            result("t1", None),
+        ];
+        let parent_path = project.join("aaaa1111-0000.jsonl");
+        fs::write(&parent_path, parent.join("\n")).unwrap();
+        // 子会话首行为空行：ref 指向第一条记录所在的行
+        let child = |ts: &str, prompt: &str| {
+            format!(
+                "\n{{\"type\":\"user\",\"isSidechain\":true,\"timestamp\":\"{}\",\"message\":{{\"content\":\"{}\"}}}}",
+                ts, prompt
+            )
+        };
+        fs::write(
+            dir.join("agent-aaa.jsonl"),
+            child("2026-05-01T00:00:00Z", "find the bug"),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("agent-bbb.jsonl"),
+            child("2026-05-01T00:00:01Z", "write tests"),
+        )
+        .unwrap();
+        fs::write(dir.join("agent-ccc.jsonl"), child("2026-05-01T00:00:02Z", "unrelated")).unwrap();
+
+        let links = link_session(&parent_path, "aaaa1111-0000");
+        let summary: Vec<(&str, &str, Option<&str>)> = links
+            .iter()
+            .map(|l| (l.session.as_str(), l.r#ref.as_str(), l.parent_ref.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                // prompt 匹配
+                ("agent-aaa", "aaa:2", Some("aaaa1111:2")),
+                // agentId 匹配
+                ("agent-bbb", "bbb:2", Some("aaaa1111:3")),
+                ("agent-ccc", "ccc:2", None),
+            ]
+        );
+        assert_eq!(links[1].description.as_deref(), Some("d-t2"));
+        assert_eq!(links[1].subagent_type.as_deref(), Some("general-purpose"));
+
+        let mut calls = TaskCalls::default();
+        for (i, line) in parent.iter().enumerate() {
+            calls.observe(i + 1, &MessageRecord::parse(line).unwrap());
+        }
+        let jumps = calls.jump_refs(&links);
+        assert_eq!(
+            (jumps[&2].as_str(), jumps[&5].as_str(), jumps[&4].as_str()),
+            ("aaa:2", "aaa:2", "bbb:2")
+        );
+
+        let link = subagent_link(&dir.join("agent-bbb.jsonl"), "agent-bbb").unwrap();
+        assert_eq!(link.parent_ref.as_deref(), Some("aaaa1111:3"));
+        assert!(parent_session_file(&parent_path).is_none());
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+}
@@ -0,0 +1,404 @@
+//! tail：跟踪会话 JSONL 的追加内容
+//!
+//! 每次轮询重新枚举项目目录（新会话文件出现即纳入跟踪），按文件记录读到的字节位置和行号，
+//! 只读取完整行（写到一半的行留到下次），每次轮询每个文件最多读取 MAX_POLL_BYTES 字节。文件变短或游标前一个字节不是换行时视为被截断/改写，
+//! 从头重新读取。游标可序列化保存，重启后从上次的字节位置继续
+
+use crate::config::{Config, Defaults};
+use crate::query::Query;
+use crate::reader::Compression;
+use crate::redact::{Redact, Redactor};
+use crate::search::{LineMatcher, SearchParams, collect_jsonl_files, compile_pattern, get_project_dirs};
+use crate::time::TimeZone;
+use crate::types::*;
+use crate::utils::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// 单次轮询每个文件最多读取的字节数，超出部分留到下次轮询
+const MAX_POLL_BYTES: u64 = 16 * 1024 * 1024;
+
+/// 单个文件的读取位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct TailCursor {
+    /// 已读取的字节数（总是落在行首）
+    pub offset: u64,
+    /// 已读取的行数（下一条消息的行号为 line + 1）
+    pub line: usize,
+}
+
+/// tail 参数
+pub struct TailParams {
+    pub projects: Vec<String>,
+    pub sessions: Vec<String>,
+    pub pattern: String,
+    pub types: Vec<String>,
+    pub subtypes: Vec<String>,
+    pub use_regex: bool,
+    pub case_sensitive: bool,
+    pub subagents: bool,
+    /// 启动时已存在的文件从头读取（默认从末尾开始，只输出之后追加的消息）
+    pub from_start: bool,
+    /// 上次保存的游标（文件路径 → 位置），优先于 from_start
+    pub cursors: BTreeMap<String, TailCursor>,
+    pub max_content: usize,
+    /// 脱敏器；在截断之前作用于每条消息
+    pub redactor: Option<Redactor>,
+}
+
+impl Default for TailParams {
+    fn default() -> Self {
+        let defaults = Defaults::default();
+        Self {
+            projects: Vec::new(),
+            sessions: Vec::new(),
+            pattern: String::new(),
+            types: defaults.types,
+            subtypes: Vec::new(),
+            use_regex: false,
+            case_sensitive: false,
+            subagents: false,
+            from_start: false,
+            cursors: BTreeMap::new(),
+            max_content: defaults.max_content,
+            redactor: None,
+        }
+    }
+}
+
+/// 跟踪一组项目目录的会话文件
+pub struct Tailer {
+    project_dirs: Vec<(String, PathBuf)>,
+    sessions: Vec<String>,
+    subagents: bool,
+    filter: SearchParams,
+    regex: Option<Regex>,
+    query: Option<Query>,
+    redactor: Option<Redactor>,
+    timezone: TimeZone,
+    cursors: BTreeMap<PathBuf, TailCursor>,
+}
+
+impl Tailer {
+    pub fn new(config: &Config, params: TailParams) -> Result<Self, ErrorResponse> {
+        let project_dirs = get_project_dirs(config, &params.projects, false)?;
+        let filter = SearchParams {
+            pattern: params.pattern,
+            types: params.types,
+            subtypes: params.subtypes,
+            use_regex: params.use_regex,
+            case_sensitive: params.case_sensitive,
+            max_content: params.max_content,
+            ..Default::default()
+        };
+        let (regex, query) = compile_pattern(&filter, config.defaults.timezone)?;
+
+        let mut tailer = Self {
+            project_dirs,
+            sessions: params.sessions,
+            subagents: params.subagents,
+            filter,
+            regex,
+            query,
+            redactor: params.redactor,
+            timezone: config.defaults.timezone,
+            cursors: BTreeMap::new(),
+        };
+
+        // 启动时已存在的文件：有保存的游标则续读，否则按 from_start 决定起点
+        for (_, _, path) in tailer.files() {
+            let cursor = match params.cursors.get(path.to_string_lossy().as_ref()) {
+                Some(cursor) => *cursor,
+                None if params.from_start => TailCursor::default(),
+                None => end_cursor(&path).unwrap_or_default(),
+            };
+            tailer.cursors.insert(path, cursor);
+        }
+        Ok(tailer)
+    }
+
+    /// 当前跟踪的文件数
+    pub fn file_count(&self) -> usize {
+        self.cursors.len()
+    }
+
+    /// 当前游标（可序列化保存，用于重启后续读）
+    pub fn cursors(&self) -> BTreeMap<String, TailCursor> {
+        self.cursors
+            .iter()
+            .map(|(path, cursor)| (path.to_string_lossy().into_owned(), *cursor))
+            .collect()
+    }
+
+    /// 归档（压缩）的会话不会再写入，不跟踪
+    fn files(&self) -> Vec<(String, String, PathBuf)> {
+        let mut files = collect_jsonl_files(&self.project_dirs, &self.sessions, self.subagents);
+        files.retain(|(_, _, path)| Compression::of(path) == Compression::None);
+        files
+    }
+
+    /// 读取所有文件自上次以来追加的完整行，返回通过过滤的消息（按时间排序）
+    pub fn poll(&mut self) -> Vec<SearchResult> {
+        let files = self.files();
+        // 被删除的文件不再跟踪
+        self.cursors.retain(|path, _| files.iter().any(|(_, _, p)| p == path));
+
+        let matcher = LineMatcher {
+            params: &self.filter,
+            regex: self.regex.as_ref(),
+            query: self.query.as_ref(),
+        };
+        let mut results = Vec::new();
+        for (project_id, session_id, path) in files {
+            // 轮询期间新出现的文件从头读取
+            let cursor = self.cursors.entry(path.clone()).or_default();
+            let Ok(lines) = read_appended(&path, cursor, MAX_POLL_BYTES) else {
+                continue;
+            };
+            for (line_num, line) in lines {
+                if let Some(record) = MessageRecord::parse(&line)
+                    && let Some(mut result) = matcher.match_record(&project_id, &session_id, line_num, record)
+                {
+                    if let Some(redactor) = &self.redactor {
+                        result.redact(redactor);
+                    }
+                    let (content, truncated) = truncate_content(&result.content, self.filter.max_content);
+                    result.content = content;
+                    result.truncated = truncated;
+                    result.local_timestamp = self.timezone.localize(&result.timestamp);
+                    results.push(result);
+                }
+            }
+        }
+
+        results.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        results
+    }
+}
+
+/// 文件末尾（最后一个完整行之后）的游标
+fn end_cursor(path: &Path) -> std::io::Result<TailCursor> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut cursor = TailCursor::default();
+    let mut buf = [0u8; 64 * 1024];
+    let mut pos = 0u64;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            return Ok(cursor);
+        }
+        for (i, &b) in buf[..n].iter().enumerate() {
+            if b == b'\n' {
+                cursor.line += 1;
+                cursor.offset = pos + i as u64 + 1;
+            }
+        }
+        pos += n as u64;
+    }
+}
+
+/// 从游标处逐行读取追加的完整行并推进游标，最多读取 max_bytes 字节；检测到截断/改写时从头读取。
+/// 单行超过 max_bytes 时跳过该行（只计行号，不缓存内容）
+fn read_appended(path: &Path, cursor: &mut TailCursor, max_bytes: u64) -> std::io::Result<Vec<(usize, String)>> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+
+    if size < cursor.offset || !at_line_start(&mut file, cursor.offset)? {
+        *cursor = TailCursor::default();
+    }
+    if size == cursor.offset {
+        return Ok(Vec::new());
+    }
+
+    file.seek(SeekFrom::Start(cursor.offset))?;
+    let mut reader = BufReader::new(file.take(size - cursor.offset));
+    let mut lines = Vec::new();
+    let mut buf = Vec::new();
+    let mut read = 0u64;
+    while read < max_bytes {
+        buf.clear();
+        let n = (&mut reader).take(max_bytes - read).read_until(b'\n', &mut buf)? as u64;
+        if buf.last() != Some(&b'\n') {
+            // 本次读到的第一行就超过上限：跳过到行尾，否则游标永远无法前进
+            if read == 0
+                && n == max_bytes
+                && let Some(skipped) = skip_line(&mut reader)?
+            {
+                cursor.line += 1;
+                cursor.offset += n + skipped;
+            }
+            break;
+        }
+        read += n;
+        cursor.line += 1;
+        if !buf.iter().all(u8::is_ascii_whitespace) {
+            lines.push((cursor.line, String::from_utf8_lossy(&buf).trim_end().to_string()));
+        }
+    }
+    cursor.offset += read;
+    Ok(lines)
+}
+
+/// 丢弃到下一个换行（含）为止的内容，返回丢弃的字节数；没有换行（行未写完）时返回 None
+fn skip_line(reader: &mut impl BufRead) -> std::io::Result<Option<u64>> {
+    let mut skipped = 0u64;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        if let Some(i) = buf.iter().position(|&b| b == b'\n') {
+            reader.consume(i + 1);
+            return Ok(Some(skipped + i as u64 + 1));
+        }
+        let len = buf.len();
+        skipped += len as u64;
+        reader.consume(len);
+    }
+}
+
+/// offset 处是否为行首（文件开头，或前一个字节是换行）
+fn at_line_start(file: &mut File, offset: u64) -> std::io::Result<bool> {
+    if offset == 0 {
+        return Ok(true);
+    }
+    file.seek(SeekFrom::Start(offset - 1))?;
+    let mut byte = [0u8; 1];
+    file.read_exact(&mut byte)?;
+    Ok(byte[0] == b'\n')
+}
+
+/// 读取游标状态文件；不存在时返回空
+pub fn load_state(path: &Path) -> Result<BTreeMap<String, TailCursor>, ErrorResponse> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => {
+            return Err(ErrorResponse {
+                error: "io_error".to_string(),
+                message: format!("无法读取状态文件 {}: {}", path.display(), e),
+                available: None,
+            });
+        }
+    };
+    serde_json::from_slice(&data).map_err(|e| ErrorResponse {
+        error: "invalid_state".to_string(),
+        message: format!("状态文件格式错误 {}: {}", path.display(), e),
+        available: None,
+    })
+}
+
+/// 原子写入游标状态文件（先写临时文件再 rename）
+pub fn save_state(path: &Path, cursors: &BTreeMap<String, TailCursor>) -> std::io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(cursors)?)?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn line(uuid: &str, text: &str) -> String {
+        format!(
+            r#"{{"uuid":"{uuid}","type":"user","timestamp":"2026-05-01T00:00:00Z","message":{{"role":"user","content":"{text}"}}}}"#
+        )
+    }
+
+    #[test]
+    fn test_poll_appends_rotation_and_new_files() {
+        let home = std::env::temp_dir().join(format!("mcp-tail-test-{}", std::process::id()));
+        let project = home.join("projects").join("-p");
+        std::fs::create_dir_all(&project).unwrap();
+        let session = project.join("aaaa1111-0000.jsonl");
+        std::fs::write(&session, format!("{}\n", line("u1", "old"))).unwrap();
+
+        let config = Config::for_test(&home);
+        let params = TailParams {
+            projects: vec!["-p".to_string()],
+            pattern: "!skip".to_string(),
+            ..Default::default()
+        };
+        let mut tailer = Tailer::new(&config, params).unwrap();
+        // 默认从末尾开始：已有内容不输出
+        assert!(tailer.poll().is_empty());
+
+        // 追加一整行 + 半行：只输出完整行，半行等补全后再输出
+        let mut f = std::fs::OpenOptions::new().append(true).open(&session).unwrap();
+        write!(
+            f,
+            "{}\n{}\n{}",
+            line("u2", "new"),
+            line("u3", "skip"),
+            &line("u4", "tail")[..20]
+        )
+        .unwrap();
+        let results = tailer.poll();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].r#ref, "aaaa1111:2");
+        writeln!(f, "{}", &line("u4", "tail")[20..]).unwrap();
+        let results = tailer.poll();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].r#ref, "aaaa1111:4");
+
+        // 游标可恢复：新的 tailer 从保存的位置继续
+        let cursors = tailer.cursors();
+        writeln!(f, "{}", line("u5", "after restart")).unwrap();
+        let params = TailParams {
+            projects: vec!["-p".to_string()],
+            cursors,
+            ..Default::default()
+        };
+        let mut resumed = Tailer::new(&config, params).unwrap();
+        let results = resumed.poll();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].content, "after restart");
+
+        // 文件被改写（变短）：从头重新读取
+        std::fs::write(&session, format!("{}\n", line("r1", "rewritten"))).unwrap();
+        let results = resumed.poll();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].r#ref, "aaaa1111:1");
+
+        // 新会话文件出现：从头读取
+        std::fs::write(
+            project.join("bbbb2222-0000.jsonl"),
+            format!("{}\n", line("n1", "fresh")),
+        )
+        .unwrap();
+        let results = resumed.poll();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].session, "bbbb2222-0000");
+        assert_eq!(resumed.file_count(), 2);
+
+        std::fs::remove_dir_all(&home).ok();
+    }
+
+    #[test]
+    fn test_read_appended_caps_bytes_per_poll() {
+        let path = std::env::temp_dir().join(format!("mcp-tail-cap-{}.jsonl", std::process::id()));
+        std::fs::write(&path, format!("aaaa\nbbbb\n{}\ncccc\ndd", "x".repeat(30))).unwrap();
+
+        // 每次最多 12 字节：只读到上限内的完整行，其余留到下次
+        let mut cursor = TailCursor::default();
+        let lines = read_appended(&path, &mut cursor, 12).unwrap();
+        assert_eq!(lines, vec![(1, "aaaa".to_string()), (2, "bbbb".to_string())]);
+        assert_eq!(cursor, TailCursor { offset: 10, line: 2 });
+
+        // 超过上限的单行被跳过，行号照常推进
+        assert!(read_appended(&path, &mut cursor, 12).unwrap().is_empty());
+        assert_eq!(cursor, TailCursor { offset: 41, line: 3 });
+
+        // 末尾的半行不读
+        let lines = read_appended(&path, &mut cursor, 12).unwrap();
+        assert_eq!(lines, vec![(4, "cccc".to_string())]);
+        assert_eq!(cursor, TailCursor { offset: 46, line: 4 });
+
+        std::fs::remove_file(&path).ok();
+    }
+}
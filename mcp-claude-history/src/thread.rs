@@ -0,0 +1,308 @@
+//! 会话消息树重建
+//!
+//! Claude Code 每条消息通过 parentUuid 指向上一条；编辑/回退后会从旧节点分出新分支，
+//! 旧分支仍留在 jsonl 中。这里按 parentUuid（compact 边界处回退到 logicalParentUuid）
+//! 重建 DAG，以文件中最后一条非 sidechain 消息为活动叶子，其祖先链为活动路径，其余为废弃分支
+
+use crate::config::{Config, Defaults};
+use crate::get::find_session_file;
+use crate::reader::SessionReader;
+use crate::redact::Redactor;
+use crate::types::*;
+use crate::utils::*;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// 读取 session 文件的全部可解析记录（行号从 1 开始）
+pub fn load_records(path: &Path) -> Result<Vec<(usize, MessageRecord)>, ErrorResponse> {
+    let reader = SessionReader::open(path).map_err(|e| ErrorResponse {
+        error: "io_error".to_string(),
+        message: format!("无法打开文件: {}", e),
+        available: None,
+    })?;
+    Ok(reader.collect())
+}
+
+/// 会话消息树，节点下标与构建时传入的记录顺序一致
+pub struct SessionThread {
+    parents: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    active: Vec<bool>,
+    active_leaf: Option<usize>,
+}
+
+impl SessionThread {
+    pub fn build(records: &[&MessageRecord]) -> Self {
+        let mut by_uuid: HashMap<&str, usize> = HashMap::new();
+        for (idx, record) in records.iter().enumerate() {
+            if !record.uuid.is_empty() {
+                by_uuid.entry(record.uuid.as_str()).or_insert(idx);
+            }
+        }
+
+        let mut parents = vec![None; records.len()];
+        let mut children = vec![Vec::new(); records.len()];
+        for (idx, record) in records.iter().enumerate() {
+            let parent_uuid = record.parent_uuid.as_deref().or(record.logical_parent_uuid.as_deref());
+            if let Some(&parent) = parent_uuid.and_then(|p| by_uuid.get(p))
+                && parent != idx
+            {
+                parents[idx] = Some(parent);
+                children[parent].push(idx);
+            }
+        }
+
+        // 活动叶子：文件中最后一条主线消息；全是 sidechain（subagent 文件）时取最后一条
+        let linked = |idx: &usize| !records[*idx].uuid.is_empty();
+        let active_leaf = (0..records.len())
+            .rev()
+            .filter(linked)
+            .find(|&idx| !records[idx].is_sidechain)
+            .or_else(|| (0..records.len()).rev().find(linked));
+
+        let mut thread = Self {
+            parents,
+            children,
+            active: vec![false; records.len()],
+            active_leaf,
+        };
+        if let Some(leaf) = active_leaf {
+            for idx in thread.chain_to(leaf) {
+                thread.active[idx] = true;
+            }
+        }
+        thread
+    }
+
+    pub fn active_leaf(&self) -> Option<usize> {
+        self.active_leaf
+    }
+
+    /// 节点是否位于活动路径上
+    pub fn is_active(&self, idx: usize) -> bool {
+        self.active[idx]
+    }
+
+    /// 没有子节点的分支末端（文件顺序）
+    pub fn leaves(&self) -> Vec<usize> {
+        (0..self.children.len())
+            .filter(|&idx| self.children[idx].is_empty() && (self.parents[idx].is_some() || self.active[idx]))
+            .collect()
+    }
+
+    /// 同一父节点下的其他分支
+    pub fn siblings(&self, idx: usize) -> Vec<usize> {
+        match self.parents[idx] {
+            Some(parent) => self.children[parent].iter().copied().filter(|&c| c != idx).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// 从根到 idx 的祖先链（含 idx）
+    pub fn chain_to(&self, idx: usize) -> Vec<usize> {
+        let mut chain = vec![idx];
+        let mut seen = HashSet::from([idx]);
+        let mut current = idx;
+        while let Some(parent) = self.parents[current] {
+            if !seen.insert(parent) {
+                break;
+            }
+            chain.push(parent);
+            current = parent;
+        }
+        chain.reverse();
+        chain
+    }
+
+    /// 祖先链 + idx + 沿子节点向下的延续（优先活动路径，否则取文件中最新的分支）
+    pub fn lineage(&self, idx: usize) -> Vec<usize> {
+        let mut order = self.chain_to(idx);
+        let mut seen: HashSet<usize> = order.iter().copied().collect();
+        let mut current = idx;
+        loop {
+            let children = &self.children[current];
+            let next = children
+                .iter()
+                .copied()
+                .find(|&c| self.active[c])
+                .or_else(|| children.last().copied());
+            match next {
+                Some(child) if seen.insert(child) => {
+                    order.push(child);
+                    current = child;
+                }
+                _ => break,
+            }
+        }
+        order
+    }
+}
+
+/// Thread 参数
+pub struct ThreadParams {
+    pub r#ref: String,
+    pub project: Option<String>,
+    /// 链上返回的消息类型（空 = 全部，anchor 始终保留）
+    pub types: Vec<String>,
+    pub max_content: usize,
+    pub max_total: usize,
+    /// 返回前脱敏（先于截断进行）
+    pub redactor: Option<Redactor>,
+}
+
+impl Default for ThreadParams {
+    fn default() -> Self {
+        let defaults = Defaults::default();
+        Self {
+            r#ref: String::new(),
+            project: None,
+            types: vec![],
+            max_content: defaults.max_content,
+            max_total: defaults.max_total,
+            redactor: None,
+        }
+    }
+}
+
+/// 返回以 ref 结尾的线性消息链
+pub fn thread(config: &Config, params: ThreadParams) -> Result<ThreadResponse, ErrorResponse> {
+    let parsed_ref = ParsedRef::parse(&params.r#ref).ok_or_else(|| ErrorResponse {
+        error: "ref_invalid".to_string(),
+        message: format!("无效的 ref 格式: {}", params.r#ref),
+        available: None,
+    })?;
+
+    let (_project_id, session_id, path) =
+        find_session_file(config, &parsed_ref.session_prefix, params.project.as_deref())?;
+    let records = load_records(&path)?;
+    let prefix = ref_prefix(&session_id);
+    let ref_of = |idx: usize| format!("{}:{}", prefix, records[idx].0);
+
+    let anchor_idx = records
+        .iter()
+        .position(|(line, _)| *line == parsed_ref.line)
+        .ok_or_else(|| ErrorResponse {
+            error: "ref_not_found".to_string(),
+            message: format!("ref 不存在: {}", params.r#ref),
+            available: None,
+        })?;
+
+    let record_refs: Vec<&MessageRecord> = records.iter().map(|(_, r)| r).collect();
+    let thread = SessionThread::build(&record_refs);
+
+    // 从 anchor 向根回溯累计预算，超出 max_total 时丢弃最早的消息
+    let mut messages = Vec::new();
+    let mut total_chars = 0;
+    let mut truncated_by_total = false;
+    let mut redactions = 0;
+    let mut redact = |text: &mut String| {
+        if let Some(redactor) = &params.redactor {
+            redactions += redactor.redact(text);
+        }
+    };
+    for &idx in thread.chain_to(anchor_idx).iter().rev() {
+        let record = &records[idx].1;
+        let (effective_type, subtype) = record.classify();
+        let is_anchor = idx == anchor_idx;
+        if !is_anchor && !params.types.is_empty() && !params.types.iter().any(|t| t == effective_type) {
+            continue;
+        }
+
+        let mut content = replace_images_with_placeholders(record);
+        redact(&mut content);
+        let (truncated_content, _) = truncate_content(&content, params.max_content);
+        let truncated_len = truncated_content.chars().count();
+        if !is_anchor && total_chars + truncated_len > params.max_total {
+            truncated_by_total = true;
+            break;
+        }
+        total_chars += truncated_len;
+
+        messages.push(ThreadMessage {
+            r#ref: ref_of(idx),
+            uuid: record.uuid.clone(),
+            parent_uuid: record.parent_uuid.clone(),
+            r#type: effective_type.to_string(),
+            subtype: subtype.to_string(),
+            timestamp: record.timestamp.clone(),
+            local_timestamp: config.defaults.timezone.localize(&record.timestamp),
+            content: truncated_content,
+            active: thread.is_active(idx),
+            alternatives: thread.siblings(idx).into_iter().map(ref_of).collect(),
+            is_anchor: is_anchor.then_some(true),
+        });
+    }
+    messages.reverse();
+
+    // summary 记录的 leafUuid 为分支末端提供标题
+    let summaries: HashMap<&str, &str> = records
+        .iter()
+        .filter_map(|(_, r)| Some((r.leaf_uuid.as_deref()?, r.summary.as_deref()?)))
+        .collect();
+    let leaves = thread
+        .leaves()
+        .into_iter()
+        .map(|idx| {
+            let record = &records[idx].1;
+            ThreadLeaf {
+                r#ref: ref_of(idx),
+                timestamp: record.timestamp.clone(),
+                active: thread.is_active(idx),
+                summary: summaries.get(record.uuid.as_str()).map(|s| {
+                    let mut summary = s.to_string();
+                    redact(&mut summary);
+                    summary
+                }),
+            }
+        })
+        .collect();
+
+    Ok(ThreadResponse {
+        anchor_ref: params.r#ref,
+        session: session_id,
+        active_leaf_ref: thread.active_leaf().map(ref_of),
+        on_active_path: thread.is_active(anchor_idx),
+        messages,
+        leaves,
+        truncated: truncated_by_total.then_some(true),
+        redactions: params.redactor.as_ref().map(|_| redactions),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(uuid: &str, parent: Option<&str>) -> MessageRecord {
+        serde_json::from_value(serde_json::json!({
+            "uuid": uuid,
+            "parentUuid": parent,
+            "type": "user",
+            "timestamp": "2026-05-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_active_path_and_dead_branch() {
+        // a → b → c（被回退） ; a → b → d → e（活动）
+        let records = [
+            record("a", None),
+            record("b", Some("a")),
+            record("c", Some("b")),
+            record("d", Some("b")),
+            record("e", Some("d")),
+        ];
+        let refs: Vec<&MessageRecord> = records.iter().collect();
+        let thread = SessionThread::build(&refs);
+
+        assert_eq!(thread.active_leaf(), Some(4));
+        assert_eq!(thread.chain_to(2), vec![0, 1, 2]);
+        assert!(!thread.is_active(2));
+        assert!(thread.is_active(3));
+        assert_eq!(thread.siblings(3), vec![2]);
+        assert_eq!(thread.leaves(), vec![2, 4]);
+        // 从废弃分支之前的节点向下延续时优先走活动路径
+        assert_eq!(thread.lineage(1), vec![0, 1, 3, 4]);
+    }
+}
@@ -0,0 +1,242 @@
+//! 时间表达式与时区
+//!
+//! since / until / before 接受 RFC 3339、本地日期或日期时间（`2026-05-01`、`2026-05-01 14:30`）、
+//! 相对时长（`30m`、`2h`、`3d`、`1w`，即多久以前）以及 `now` / `today` / `yesterday` / `last monday`。
+//! 不带偏移的时间按配置时区（`[defaults] timezone`，默认 UTC）解释；无法解析时返回 `invalid_time`，不再静默忽略
+
+use crate::types::ErrorResponse;
+use crate::utils::parse_iso_utc;
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Offset, SecondsFormat,
+    TimeZone as _, Utc, Weekday,
+};
+use serde::Serialize;
+use std::fmt;
+
+/// 解释与渲染时间所用的时区
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeZone {
+    #[default]
+    Utc,
+    /// 系统本地时区
+    Local,
+    /// 固定偏移（`+08:00`）
+    Fixed(FixedOffset),
+    /// IANA 时区（`Asia/Shanghai`），随夏令时变化
+    Named(chrono_tz::Tz),
+}
+
+impl TimeZone {
+    /// `UTC`、`local`、`+08:00` / `-05:30` 或 IANA 时区名
+    pub fn parse(s: &str) -> Result<Self, ErrorResponse> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("utc") || s == "Z" {
+            return Ok(Self::Utc);
+        }
+        if s.eq_ignore_ascii_case("local") {
+            return Ok(Self::Local);
+        }
+        if s.starts_with(['+', '-'])
+            && let Ok(offset) = s.parse::<FixedOffset>()
+        {
+            return Ok(Self::Fixed(offset));
+        }
+        s.parse::<chrono_tz::Tz>().map(Self::Named).map_err(|_| ErrorResponse {
+            error: "invalid_timezone".to_string(),
+            message: format!(
+                "无效的时区: {}（应为 UTC、local、+08:00 或 IANA 名称如 Asia/Shanghai）",
+                s
+            ),
+            available: None,
+        })
+    }
+
+    pub fn is_utc(&self) -> bool {
+        *self == Self::Utc
+    }
+
+    /// 某一时刻在本时区的墙上时间
+    pub fn local(&self, t: DateTime<Utc>) -> DateTime<FixedOffset> {
+        let offset = match self {
+            Self::Utc => Utc.fix(),
+            Self::Local => t.with_timezone(&chrono::Local).offset().fix(),
+            Self::Fixed(offset) => *offset,
+            Self::Named(tz) => t.with_timezone(tz).offset().fix(),
+        };
+        t.with_timezone(&offset)
+    }
+
+    /// 本时区的墙上时间 → UTC；夏令时重叠时取较早的时刻，落在跳过的时段时顺延一小时
+    pub fn to_utc(&self, naive: NaiveDateTime) -> Option<DateTime<Utc>> {
+        let resolve = |naive: NaiveDateTime| -> Option<DateTime<Utc>> {
+            match self {
+                Self::Utc => Some(naive.and_utc()),
+                Self::Local => chrono::Local.from_local_datetime(&naive).earliest().map(|t| t.to_utc()),
+                Self::Fixed(offset) => offset.from_local_datetime(&naive).earliest().map(|t| t.to_utc()),
+                Self::Named(tz) => tz.from_local_datetime(&naive).earliest().map(|t| t.to_utc()),
+            }
+        };
+        resolve(naive).or_else(|| resolve(naive + Duration::hours(1)))
+    }
+
+    /// 带本时区偏移的 RFC 3339（有毫秒时保留）
+    pub fn format(&self, t: DateTime<Utc>) -> String {
+        self.local(t).to_rfc3339_opts(SecondsFormat::AutoSi, true)
+    }
+
+    /// 记录时间戳在本时区的渲染；UTC 或时间戳无法解析时为 None（结果中省略 local_timestamp）
+    pub fn localize(&self, timestamp: &str) -> Option<String> {
+        if self.is_utc() {
+            return None;
+        }
+        parse_iso_utc(timestamp).map(|t| self.format(t))
+    }
+}
+
+impl fmt::Display for TimeZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Utc => f.write_str("UTC"),
+            Self::Local => f.write_str("local"),
+            Self::Fixed(offset) => write!(f, "{}", offset),
+            Self::Named(tz) => f.write_str(tz.name()),
+        }
+    }
+}
+
+impl Serialize for TimeZone {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// 解析时间表达式（见模块说明）；now 为相对表达式的基准时刻
+pub fn parse_time_expr(s: &str, tz: TimeZone, now: DateTime<Utc>) -> Result<DateTime<Utc>, ErrorResponse> {
+    let input = s.trim();
+    let lower = input.to_lowercase();
+    let midnight = |date: NaiveDate| tz.to_utc(date.and_time(NaiveTime::MIN));
+    let today = tz.local(now).date_naive();
+
+    let parsed = if let Ok(t) = DateTime::parse_from_rfc3339(input) {
+        Some(t.to_utc())
+    } else if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        midnight(date)
+    } else if let Some(naive) = [
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+    {
+        tz.to_utc(naive)
+    } else {
+        match lower.as_str() {
+            "now" => Some(now),
+            "today" => midnight(today),
+            "yesterday" => midnight(today - Duration::days(1)),
+            _ => match lower.strip_prefix("last ") {
+                Some(day) => day
+                    .trim()
+                    .parse::<Weekday>()
+                    .ok()
+                    .and_then(|weekday| midnight(last_weekday(today, weekday))),
+                None => parse_ago(&lower).and_then(|ago| now.checked_sub_signed(ago)),
+            },
+        }
+    };
+    parsed.ok_or_else(|| ErrorResponse {
+        error: "invalid_time".to_string(),
+        message: format!(
+            "无法解析时间: {}（支持 RFC 3339、YYYY-MM-DD[ HH:MM[:SS]]、30m / 2h / 3d / 1w、today、yesterday、last monday）",
+            input
+        ),
+        available: None,
+    })
+}
+
+/// 解析可选的时间参数（以当前时刻为基准）；空字符串视为未指定
+pub fn parse_time_arg(s: Option<&str>, tz: TimeZone) -> Result<Option<DateTime<Utc>>, ErrorResponse> {
+    s.filter(|s| !s.trim().is_empty())
+        .map(|s| parse_time_expr(s, tz, Utc::now()))
+        .transpose()
+}
+
+/// (since, until)
+pub type TimeWindow = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// 解析 since / until 时间窗口
+pub fn parse_time_window(since: Option<&str>, until: Option<&str>, tz: TimeZone) -> Result<TimeWindow, ErrorResponse> {
+    Ok((parse_time_arg(since, tz)?, parse_time_arg(until, tz)?))
+}
+
+/// 早于今天的最近一个指定星期几（今天是周一时 `last monday` 为七天前）
+fn last_weekday(today: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let back = (today.weekday().num_days_from_monday() + 6 - weekday.num_days_from_monday()) % 7 + 1;
+    today - Duration::days(back as i64)
+}
+
+/// `2h`、`3d`、`90 min`、`1w ago` 等相对时长
+fn parse_ago(s: &str) -> Option<Duration> {
+    let s = s.strip_suffix(" ago").unwrap_or(s).trim();
+    let split = s.find(|c: char| !c.is_ascii_digit())?;
+    let (n, unit) = s.split_at(split);
+    let n: i64 = n.parse().ok()?;
+    match unit.trim() {
+        "m" | "min" | "mins" | "minute" | "minutes" => Duration::try_minutes(n),
+        "h" | "hour" | "hours" => Duration::try_hours(n),
+        "d" | "day" | "days" => Duration::try_days(n),
+        "w" | "week" | "weeks" => Duration::try_weeks(n),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_expressions() {
+        let utc = |s: &str| parse_iso_utc(s).unwrap();
+        // 2026-05-06 是周三
+        let now = utc("2026-05-06T03:30:00Z");
+        let parse = |s: &str, tz: TimeZone| parse_time_expr(s, tz, now).map_err(|e| e.error);
+        let shanghai = TimeZone::parse("Asia/Shanghai").unwrap();
+
+        assert_eq!(
+            parse("2026-05-01T10:00:00+02:00", shanghai),
+            Ok(utc("2026-05-01T08:00:00Z"))
+        );
+        assert_eq!(parse("2026-05-01", TimeZone::Utc), Ok(utc("2026-05-01T00:00:00Z")));
+        assert_eq!(parse("2026-05-01", shanghai), Ok(utc("2026-04-30T16:00:00Z")));
+        assert_eq!(parse("2026-05-01 14:30", shanghai), Ok(utc("2026-05-01T06:30:00Z")));
+        assert_eq!(parse("2h", TimeZone::Utc), Ok(utc("2026-05-06T01:30:00Z")));
+        assert_eq!(parse("3 days ago", TimeZone::Utc), Ok(utc("2026-05-03T03:30:00Z")));
+        assert_eq!(parse("1w", TimeZone::Utc), Ok(utc("2026-04-29T03:30:00Z")));
+        assert_eq!(parse("yesterday", TimeZone::Utc), Ok(utc("2026-05-05T00:00:00Z")));
+        // 上海已是 5 月 6 日 11:30
+        assert_eq!(parse("today", shanghai), Ok(utc("2026-05-05T16:00:00Z")));
+        assert_eq!(parse("last monday", TimeZone::Utc), Ok(utc("2026-05-04T00:00:00Z")));
+        assert_eq!(parse("Last Wed", TimeZone::Utc), Ok(utc("2026-04-29T00:00:00Z")));
+        for bad in ["2026-13-01", "yesterdy", "last moonday", "5x", "99999999999999w"] {
+            assert_eq!(parse(bad, TimeZone::Utc), Err("invalid_time".to_string()), "{}", bad);
+        }
+        assert_eq!(parse_time_arg(Some(""), TimeZone::Utc).unwrap(), None);
+
+        // 夏令时跳过的时段顺延
+        let new_york = TimeZone::parse("America/New_York").unwrap();
+        assert_eq!(parse("2026-03-08 02:30", new_york), Ok(utc("2026-03-08T07:30:00Z")));
+
+        assert_eq!(
+            TimeZone::parse("+08:00")
+                .unwrap()
+                .localize("2026-05-01T00:00:00.250Z")
+                .unwrap(),
+            "2026-05-01T08:00:00.250+08:00"
+        );
+        assert_eq!(shanghai.to_string(), "Asia/Shanghai");
+        assert_eq!(TimeZone::Utc.localize("2026-05-01T00:00:00Z"), None);
+        assert_eq!(TimeZone::parse("Mars/Base").unwrap_err().error, "invalid_timezone");
+    }
+}
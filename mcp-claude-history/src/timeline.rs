@@ -0,0 +1,343 @@
+//! 时间线：按小时 / 天 / 周统计命中消息数
+//!
+//! 与 search 使用同一套过滤（pattern、types、subtypes、项目、会话、时间范围）和索引裁剪，
+//! 但每条命中只保留时间戳、会话与 uuid，不构造 SearchResult，因此不受 result_cap 截断。
+//! 桶按配置时区（`[defaults] timezone`，默认 UTC）划分，周从周一开始；只返回有命中的桶
+
+use crate::config::Config;
+use crate::index::{self, IndexQuery};
+use crate::search::{
+    LineMatcher, SearchParams, collect_jsonl_files, compile_pattern, get_project_dirs, scan_file, scan_file_indexed,
+};
+use crate::time::TimeZone;
+use crate::types::*;
+use crate::utils::*;
+use chrono::{DateTime, Datelike, Duration, NaiveTime, Timelike, Utc};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Instant;
+
+/// 桶大小
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BucketSize {
+    Hour,
+    #[default]
+    Day,
+    Week,
+}
+
+impl BucketSize {
+    pub fn parse(s: &str) -> Result<Self, ErrorResponse> {
+        match s {
+            "hour" => Ok(Self::Hour),
+            "day" => Ok(Self::Day),
+            "week" => Ok(Self::Week),
+            _ => Err(ErrorResponse {
+                error: "invalid_bucket".to_string(),
+                message: format!("无效的桶大小: {}", s),
+                available: Some(serde_json::json!(["hour", "day", "week"])),
+            }),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+            Self::Week => "week",
+        }
+    }
+
+    /// 桶起点与标签（按 tz 的墙上时间划分）
+    fn bucket(self, time: DateTime<Utc>, tz: TimeZone) -> (DateTime<Utc>, String) {
+        let local = tz.local(time).naive_local();
+        let (start, key) = match self {
+            Self::Hour => {
+                let start = local.date().and_hms_opt(local.hour(), 0, 0).unwrap_or(local);
+                (start, start.format("%Y-%m-%dT%H:00").to_string())
+            }
+            Self::Day => {
+                let start = local.date().and_time(NaiveTime::MIN);
+                (start, start.format("%Y-%m-%d").to_string())
+            }
+            Self::Week => {
+                let monday = local.date() - Duration::days(local.weekday().num_days_from_monday() as i64);
+                (monday.and_time(NaiveTime::MIN), monday.format("%G-W%V").to_string())
+            }
+        };
+        (tz.to_utc(start).unwrap_or(time), key)
+    }
+}
+
+/// 时间线参数
+pub struct TimelineParams {
+    /// 过滤条件，与 search 相同（分页、截断、排序、聚合字段不使用）
+    pub filter: SearchParams,
+    pub bucket: BucketSize,
+    /// 附带星期 × 小时热力图
+    pub heatmap: bool,
+}
+
+impl Default for TimelineParams {
+    fn default() -> Self {
+        Self {
+            filter: SearchParams::default(),
+            bucket: BucketSize::Day,
+            heatmap: false,
+        }
+    }
+}
+
+/// 一条命中：只保留分桶与去重所需的字段
+struct Hit {
+    session: usize,
+    line: usize,
+    uuid: String,
+    time: Option<DateTime<Utc>>,
+}
+
+/// 统计时间线
+pub fn timeline(config: &Config, params: TimelineParams) -> Result<TimelineResponse, ErrorResponse> {
+    let start = Instant::now();
+    let filter = &params.filter;
+
+    let project_dirs = get_project_dirs(config, &filter.projects, filter.all_projects)?;
+    let files = collect_jsonl_files(&project_dirs, &filter.sessions, filter.subagents);
+    let (regex, query) = compile_pattern(filter, config.defaults.timezone)?;
+    let index_query = query
+        .as_ref()
+        .and_then(|q| IndexQuery::from_query(q, filter.include_thinking));
+    let matcher = LineMatcher {
+        params: filter,
+        regex: regex.as_ref(),
+        query: query.as_ref(),
+    };
+
+    let file_results: Vec<_> = files
+        .par_iter()
+        .enumerate()
+        .map(|(i, (_, _, path))| {
+            let visit = |line: usize, record: MessageRecord| {
+                matcher.matches(&record).then(|| Hit {
+                    session: i,
+                    line,
+                    time: parse_iso_utc(&record.timestamp),
+                    uuid: record.uuid,
+                })
+            };
+            let file_index = index_query.as_ref().and_then(|_| index::load_usable(config, path));
+            match (index_query.as_ref(), file_index) {
+                (Some(index_query), Some(file_index)) => {
+                    let (lines, hits) =
+                        scan_file_indexed(path, &filter.lines, &file_index, index_query, usize::MAX, visit);
+                    (true, lines, hits)
+                }
+                _ => {
+                    let (lines, hits) = scan_file(path, &filter.lines, usize::MAX, visit);
+                    (false, lines, hits)
+                }
+            }
+        })
+        .collect();
+
+    let mut stats = TimelineStats::default();
+    let mut hits: Vec<Hit> = Vec::new();
+    for (indexed, lines, file_hits) in file_results {
+        stats.files_scanned += 1;
+        stats.index_hits += indexed as usize;
+        stats.lines_scanned += lines;
+        hits.extend(file_hits);
+    }
+
+    // 与 search 相同：按时间排序后按 UUID 去重，保留最早的一条
+    hits.sort_by(|a, b| {
+        a.time
+            .cmp(&b.time)
+            .then_with(|| files[a.session].1.cmp(&files[b.session].1))
+            .then_with(|| a.line.cmp(&b.line))
+    });
+    let mut seen_uuids = HashSet::new();
+    hits.retain(|h| h.uuid.is_empty() || seen_uuids.insert(h.uuid.clone()));
+
+    let mut buckets: BTreeMap<DateTime<Utc>, (String, Vec<Hit>)> = BTreeMap::new();
+    let mut heatmap = vec![vec![0usize; 24]; 7];
+    let mut sessions = HashSet::new();
+    let tz = config.defaults.timezone;
+    for hit in hits {
+        let Some(time) = hit.time else {
+            stats.untimed += 1;
+            continue;
+        };
+        stats.total_matches += 1;
+        sessions.insert(hit.session);
+        let local = tz.local(time);
+        heatmap[local.weekday().num_days_from_monday() as usize][local.hour() as usize] += 1;
+        let (bucket_start, key) = params.bucket.bucket(time, tz);
+        buckets
+            .entry(bucket_start)
+            .or_insert_with(|| (key, Vec::new()))
+            .1
+            .push(hit);
+    }
+    stats.sessions = sessions.len();
+
+    let buckets = buckets
+        .into_iter()
+        .map(|(bucket_start, (key, hits))| TimelineBucket {
+            key,
+            start: tz.format(bucket_start),
+            count: hits.len(),
+            sessions: bucket_sessions(config, &files, &hits),
+        })
+        .collect();
+
+    stats.time_ms = start.elapsed().as_millis() as u64;
+    Ok(TimelineResponse {
+        stats,
+        bucket: params.bucket.name().to_string(),
+        buckets,
+        heatmap: params.heatmap.then_some(heatmap),
+    })
+}
+
+/// 桶内按会话计数（hits 已按时间升序），按命中数降序、同数按首次命中先后
+fn bucket_sessions(
+    config: &Config,
+    files: &[(String, String, std::path::PathBuf)],
+    hits: &[Hit],
+) -> Vec<TimelineSession> {
+    let mut positions: HashMap<usize, usize> = HashMap::new();
+    let mut sessions: Vec<TimelineSession> = Vec::new();
+    for hit in hits {
+        if let Some(&i) = positions.get(&hit.session) {
+            sessions[i].count += 1;
+            continue;
+        }
+        let (project, session, path) = &files[hit.session];
+        positions.insert(hit.session, sessions.len());
+        sessions.push(TimelineSession {
+            session: session.clone(),
+            project: project.clone(),
+            root: config.root_label(path),
+            count: 1,
+            first_ref: format!("{}:{}", ref_prefix(session), hit.line),
+        });
+    }
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.count));
+    sessions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_timeline_buckets() {
+        let home = std::env::temp_dir().join(format!("mcp-timeline-test-{}", std::process::id()));
+        let project = home.join("projects").join("-p");
+        fs::create_dir_all(&project).unwrap();
+        let line = |uuid: &str, ts: &str, text: &str| {
+            format!(
+                r#"{{"uuid":"{}","type":"user","timestamp":"{}","message":{{"role":"user","content":"{}"}}}}"#,
+                uuid, ts, text
+            )
+        };
+        // 2026-05-04 是周一
+        let a = [
+            line("a1", "2026-05-04T09:10:00Z", "deploy staging"),
+            line("a2", "2026-05-04T09:40:00Z", "deploy prod"),
+            line("a3", "2026-05-04T10:05:00Z", "unrelated"),
+            line("a4", "2026-05-11T23:59:00Z", "deploy again"),
+        ];
+        let b = [
+            line("b1", "2026-05-04T09:20:00Z", "deploy from b"),
+            // 镜像消息按 uuid 去重
+            line("a2", "2026-05-04T09:40:00Z", "deploy prod"),
+            line("b2", "", "deploy untimed"),
+        ];
+        fs::write(project.join("aaaa1111-0000.jsonl"), a.join("\n")).unwrap();
+        fs::write(project.join("bbbb2222-0000.jsonl"), b.join("\n")).unwrap();
+        let config = Config::for_test(&home);
+        let filter = || SearchParams {
+            pattern: "deploy".to_string(),
+            projects: vec!["-p".to_string()],
+            ..Default::default()
+        };
+
+        let response = timeline(
+            &config,
+            TimelineParams {
+                filter: filter(),
+                bucket: BucketSize::Hour,
+                heatmap: true,
+            },
+        )
+        .unwrap();
+        assert_eq!((response.stats.total_matches, response.stats.untimed), (4, 1));
+        assert_eq!(response.stats.sessions, 2);
+        let keys: Vec<&str> = response.buckets.iter().map(|b| b.key.as_str()).collect();
+        assert_eq!(keys, ["2026-05-04T09:00", "2026-05-11T23:00"]);
+        let first = &response.buckets[0];
+        assert_eq!(first.count, 3);
+        assert_eq!(first.start, "2026-05-04T09:00:00Z");
+        assert_eq!(
+            (first.sessions[0].count, first.sessions[0].first_ref.as_str()),
+            (2, "aaaa1111:1")
+        );
+        assert_eq!(
+            (first.sessions[1].count, first.sessions[1].first_ref.as_str()),
+            (1, "bbbb2222:1")
+        );
+        let heatmap = response.heatmap.unwrap();
+        assert_eq!((heatmap[0][9], heatmap[0][23], heatmap[1][9]), (3, 1, 0));
+
+        let response = timeline(
+            &config,
+            TimelineParams {
+                filter: filter(),
+                bucket: BucketSize::Week,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let keys: Vec<(&str, usize)> = response.buckets.iter().map(|b| (b.key.as_str(), b.count)).collect();
+        assert_eq!(keys, [("2026-W19", 3), ("2026-W20", 1)]);
+        assert_eq!(response.buckets[1].start, "2026-05-11T00:00:00Z");
+        assert!(response.heatmap.is_none());
+
+        // 按配置时区分桶：UTC 周一 23:59 在 +08:00 已是周二
+        let config = Config {
+            defaults: crate::config::Defaults {
+                timezone: TimeZone::parse("+08:00").unwrap(),
+                ..Default::default()
+            },
+            ..config
+        };
+        let response = timeline(
+            &config,
+            TimelineParams {
+                filter: filter(),
+                heatmap: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let keys: Vec<(&str, &str)> = response
+            .buckets
+            .iter()
+            .map(|b| (b.key.as_str(), b.start.as_str()))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                ("2026-05-04", "2026-05-04T00:00:00+08:00"),
+                ("2026-05-12", "2026-05-12T00:00:00+08:00")
+            ]
+        );
+        assert_eq!(response.heatmap.unwrap()[1][7], 1);
+
+        assert_eq!(BucketSize::parse("month").unwrap_err().error, "invalid_bucket");
+        fs::remove_dir_all(&home).unwrap();
+    }
+}
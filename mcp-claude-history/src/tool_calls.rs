@@ -0,0 +1,271 @@
+//! 工具调用视图：把 assistant 消息里的 tool_use 与后续 user 消息里的 tool_result 按 id 配对
+//!
+//! 与 search 的占位符文本不同，这里返回完整的 input JSON、结果文本、is_error 和耗时
+
+use crate::config::{Config, Defaults};
+use crate::model::ContentBlock;
+use crate::reader::SessionReader;
+use crate::redact::Redactor;
+use crate::search::{collect_jsonl_files, get_project_dirs};
+use crate::types::*;
+use crate::utils::*;
+use rayon::prelude::*;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
+
+/// 工具调用查询参数
+pub struct ToolCallsParams {
+    pub projects: Vec<String>,
+    pub all_projects: bool,
+    pub sessions: Vec<String>,
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    pub until: Option<chrono::DateTime<chrono::Utc>>,
+    /// 工具名过滤（精确匹配，空 = 全部）
+    pub names: Vec<String>,
+    /// Some(true) 只要失败调用，Some(false) 只要成功调用
+    pub is_error: Option<bool>,
+    /// input 字段过滤，格式 `field=regex`，field 支持 `a.b.0` 形式的路径，多个条件为 AND
+    pub input_filters: Vec<String>,
+    pub subagents: bool,
+    pub offset: usize,
+    pub limit: Option<usize>,
+    /// 单条结果文本最大字符数
+    pub max_content: usize,
+    pub max_total: usize,
+    /// 返回前脱敏 input 与结果文本（先于截断进行）
+    pub redactor: Option<Redactor>,
+}
+
+impl Default for ToolCallsParams {
+    fn default() -> Self {
+        let defaults = Defaults::default();
+        Self {
+            projects: Vec::new(),
+            all_projects: false,
+            sessions: Vec::new(),
+            since: None,
+            until: None,
+            names: Vec::new(),
+            is_error: None,
+            input_filters: Vec::new(),
+            subagents: false,
+            offset: 0,
+            limit: None,
+            max_content: defaults.max_content,
+            max_total: defaults.max_total,
+            redactor: None,
+        }
+    }
+}
+
+/// 已编译的 input 字段过滤条件
+struct InputFilter {
+    path: Vec<String>,
+    regex: Regex,
+}
+
+impl InputFilter {
+    fn parse(raw: &str) -> Result<Self, ErrorResponse> {
+        let Some((field, pattern)) = raw.split_once('=').filter(|(f, _)| !f.is_empty()) else {
+            return Err(ErrorResponse {
+                error: "invalid_filter".to_string(),
+                message: format!("input 过滤条件应为 field=regex: {}", raw),
+                available: None,
+            });
+        };
+        let regex = Regex::new(pattern).map_err(|e| ErrorResponse {
+            error: "invalid_regex".to_string(),
+            message: format!("无效的正则表达式: {}", e),
+            available: None,
+        })?;
+        Ok(Self {
+            path: field.split('.').map(str::to_string).collect(),
+            regex,
+        })
+    }
+
+    fn matches(&self, input: &serde_json::Value) -> bool {
+        let mut value = input;
+        for key in &self.path {
+            let next = match value {
+                serde_json::Value::Array(arr) => key.parse::<usize>().ok().and_then(|i| arr.get(i)),
+                _ => value.get(key),
+            };
+            let Some(next) = next else {
+                return false;
+            };
+            value = next;
+        }
+        match value.as_str() {
+            Some(s) => self.regex.is_match(s),
+            None => self.regex.is_match(&value.to_string()),
+        }
+    }
+}
+
+/// 查询工具调用
+pub fn tool_calls(config: &Config, params: ToolCallsParams) -> Result<ToolCallsResponse, ErrorResponse> {
+    let start = Instant::now();
+
+    let filters = params
+        .input_filters
+        .iter()
+        .map(|f| InputFilter::parse(f))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let project_dirs = get_project_dirs(config, &params.projects, params.all_projects)?;
+    let files = collect_jsonl_files(&project_dirs, &params.sessions, params.subagents);
+
+    let file_results: Vec<Vec<ToolCall>> = files
+        .par_iter()
+        .map(|(project_id, session_id, path)| pair_tool_calls(project_id, session_id, path))
+        .collect();
+    let files_scanned = file_results.len();
+
+    let mut all_calls: Vec<ToolCall> = file_results
+        .into_iter()
+        .flatten()
+        .filter(|call| params.names.is_empty() || params.names.iter().any(|n| n == &call.name))
+        .filter(|call| match params.is_error {
+            Some(want) => call.result_ref.is_some() && call.is_error == want,
+            None => true,
+        })
+        .filter(|call| time_in_range(&call.timestamp, params.since.as_ref(), params.until.as_ref()))
+        .filter(|call| filters.iter().all(|f| f.matches(&call.input)))
+        .collect();
+
+    all_calls.sort_by(|a, b| {
+        a.timestamp
+            .cmp(&b.timestamp)
+            .then_with(|| a.session.cmp(&b.session))
+            .then_with(|| a.line.cmp(&b.line))
+    });
+    let total_calls = all_calls.len();
+
+    // 每条调用的元数据（ref、id、timestamp 等）约 300 字符，与 search 一致
+    const METADATA_OVERHEAD: usize = 300;
+    let mut calls = Vec::new();
+    let mut total_chars = 0;
+    let mut redactions = 0;
+    for mut call in all_calls
+        .into_iter()
+        .skip(params.offset)
+        .take(params.limit.unwrap_or(usize::MAX))
+    {
+        call.local_timestamp = config.defaults.timezone.localize(&call.timestamp);
+        if let Some(redactor) = &params.redactor {
+            redactions += redactor.redact_value(&mut call.input);
+            redactions += call.result.as_mut().map_or(0, |r| redactor.redact(r));
+        }
+        if let Some(result) = call.result.take() {
+            let (content, truncated) = truncate_content(&result, params.max_content);
+            call.result = Some(content);
+            call.result_truncated = truncated;
+        }
+        let size = call.input.to_string().chars().count()
+            + call.result.as_ref().map(|r| r.chars().count()).unwrap_or(0)
+            + METADATA_OVERHEAD;
+        if total_chars + size > params.max_total && !calls.is_empty() {
+            break;
+        }
+        total_chars += size;
+        calls.push(call);
+    }
+
+    let returned_count = calls.len();
+    let has_more = returned_count < total_calls.saturating_sub(params.offset);
+    Ok(ToolCallsResponse {
+        stats: ToolCallsStats {
+            files_scanned,
+            total_calls,
+            returned_count,
+            time_ms: start.elapsed().as_millis() as u64,
+        },
+        calls,
+        has_more,
+        next_offset: params.offset + returned_count,
+        redactions: params.redactor.as_ref().map(|_| redactions),
+    })
+}
+
+/// 扫描单个 session 文件，按 tool_use.id ↔ tool_result.tool_use_id 配对
+fn pair_tool_calls(project_id: &str, session_id: &str, path: &Path) -> Vec<ToolCall> {
+    let Ok(reader) = SessionReader::open(path) else {
+        return Vec::new();
+    };
+    let prefix = ref_prefix(session_id);
+
+    let mut calls: Vec<ToolCall> = Vec::new();
+    let mut by_id: HashMap<String, usize> = HashMap::new();
+
+    for (line_num, record) in reader {
+        for block in record.blocks() {
+            match block {
+                ContentBlock::ToolUse { id, name, input } => {
+                    by_id.insert(id.clone(), calls.len());
+                    calls.push(ToolCall {
+                        r#ref: format!("{}:{}", prefix, line_num),
+                        result_ref: None,
+                        session: session_id.to_string(),
+                        project: project_id.to_string(),
+                        line: line_num,
+                        tool_use_id: id.clone(),
+                        name: if name.is_empty() { "unknown" } else { name }.to_string(),
+                        input: input.clone(),
+                        timestamp: record.timestamp.clone(),
+                        local_timestamp: None,
+                        result: None,
+                        result_size: 0,
+                        result_truncated: false,
+                        is_error: false,
+                        latency_ms: None,
+                    });
+                }
+                ContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                    is_error,
+                } => {
+                    let Some(&idx) = by_id.get(tool_use_id) else {
+                        continue;
+                    };
+                    let call = &mut calls[idx];
+                    let text = extract_tool_result_texts(content).join("\n");
+                    call.result_size = text.chars().count();
+                    call.result = Some(text);
+                    call.result_ref = Some(format!("{}:{}", prefix, line_num));
+                    call.is_error = *is_error;
+                    call.latency_ms = match (parse_time(&call.timestamp), parse_time(&record.timestamp)) {
+                        (Some(start), Some(end)) => Some((end - start).num_milliseconds()),
+                        _ => None,
+                    };
+                }
+                _ => {}
+            }
+        }
+    }
+
+    calls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_filter_paths() {
+        let input = serde_json::json!({
+            "command": "git status --short",
+            "edits": [{"old_string": "foo"}],
+            "timeout": 5000,
+        });
+        assert!(InputFilter::parse("command=^git").unwrap().matches(&input));
+        assert!(!InputFilter::parse("command=^cargo").unwrap().matches(&input));
+        assert!(InputFilter::parse("edits.0.old_string=fo+").unwrap().matches(&input));
+        assert!(InputFilter::parse("timeout=^5000$").unwrap().matches(&input));
+        assert!(!InputFilter::parse("missing=.*").unwrap().matches(&input));
+        assert!(InputFilter::parse("=x").is_err());
+    }
+}
@@ -1,26 +1,14 @@
-use serde::{Deserialize, Serialize};
+use crate::tail::TailCursor;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
-/// JSONL 中的消息记录
-#[derive(Debug, Clone, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct MessageRecord {
-    pub uuid: String,
-    #[serde(rename = "type")]
-    pub msg_type: String,
-    pub timestamp: String,
-    #[serde(default)]
-    pub message: Option<serde_json::Value>,
-    /// 上下文压缩产生的摘要消息（raw type 为 user，但逻辑类型应为 summary）
-    #[serde(default)]
-    pub is_compact_summary: bool,
-    /// CLI 命令产生的 meta 消息
-    #[serde(default)]
-    pub is_meta: bool,
-}
+/// 消息记录已移至类型化模型，这里保留路径供 `use crate::types::*` 使用
+pub use crate::model::MessageRecord;
 
 /// 搜索结果中的单条消息
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct SearchResult {
     pub r#ref: String,
     pub session: String,
@@ -29,6 +17,9 @@ pub struct SearchResult {
     pub r#type: String,
     pub subtype: String,
     pub timestamp: String,
+    /// 按配置时区渲染的 timestamp（时区为 UTC 时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_timestamp: Option<String>,
     pub content: String,
     pub content_size: usize,
     pub truncated: bool,
@@ -36,43 +27,85 @@ pub struct SearchResult {
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub images: Vec<ImageInfo>,
     pub project: String,
+    /// 所在历史根目录（只配置了一个根时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+    /// BM25 相关性分数（仅 sort=relevance 时返回）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    /// subagent 会话的命中：父会话中启动它的 Task 调用的 ref（无法关联时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_ref: Option<String>,
     /// 匹配位置（字符偏移），用于截断时居中显示上下文
     #[serde(skip)]
     pub match_pos: Option<usize>,
 }
 
 /// 图片信息
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct ImageInfo {
     pub index: usize,
     pub size: usize,
 }
 
 /// 搜索统计
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct SearchStats {
     pub files_scanned: usize,
+    /// 通过持久化索引裁剪候选行的文件数（其余文件为线性扫描）
+    pub index_hits: usize,
     pub lines_scanned: usize,
     pub total_matches: usize,
+    /// 命中的会话数（仅 group_by=session 时返回，分页以会话为单位）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_groups: Option<usize>,
     pub returned_count: usize,
     pub time_ms: u64,
-    /// 是否触发了全局命中硬上限截断（GLOBAL_RESULT_CAP）；
+    /// 是否触发了全局命中硬上限截断（Defaults::result_cap）；
     /// 客户端看到 has_more=false + truncated_global=true 应理解为"翻完但被截，需缩小搜索"
     #[serde(skip_serializing_if = "Option::is_none")]
     pub truncated_global: Option<bool>,
 }
 
 /// 搜索响应
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct SearchResponse {
     pub stats: SearchStats,
     pub results: Vec<SearchResult>,
+    /// 按会话聚合的结果（仅 group_by=session 时返回，此时 results 为空）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<SessionGroup>>,
     pub has_more: bool,
     pub next_offset: usize,
+    /// 脱敏替换次数（未启用脱敏时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redactions: Option<usize>,
+}
+
+/// 一个会话内的全部命中（group_by=session）
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SessionGroup {
+    pub session: String,
+    pub project: String,
+    /// 所在历史根目录（只配置了一个根时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+    /// 会话内的命中数（受 result_cap 限制）
+    pub hit_count: usize,
+    /// 最早 / 最晚命中的 ref 与时间
+    pub first_ref: String,
+    pub last_ref: String,
+    pub first_timestamp: String,
+    pub last_timestamp: String,
+    /// 会话主题（首条 user 消息）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    /// 最相关的一条命中（BM25 最高，同分取最早），content 按 max_content 截断
+    pub best: SearchResult,
 }
 
 /// Get 响应
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 #[serde(untagged)]
 pub enum GetResponse {
     Success {
@@ -81,6 +114,12 @@ pub enum GetResponse {
         content: String,
         content_size: usize,
         image_count: usize,
+        /// 所在历史根目录（只配置了一个根时省略）
+        #[serde(skip_serializing_if = "Option::is_none")]
+        root: Option<String>,
+        /// 脱敏替换次数（未启用脱敏时省略）
+        #[serde(skip_serializing_if = "Option::is_none")]
+        redactions: Option<usize>,
     },
     TooLarge {
         error: String,
@@ -93,25 +132,40 @@ pub enum GetResponse {
         output: OutputInfo,
         content_size: usize,
         image_count: usize,
+        /// 所在历史根目录（只配置了一个根时省略）
+        #[serde(skip_serializing_if = "Option::is_none")]
+        root: Option<String>,
+        /// 脱敏替换次数（未启用脱敏时省略）
+        #[serde(skip_serializing_if = "Option::is_none")]
+        redactions: Option<usize>,
     },
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct OutputInfo {
     pub content: PathBuf,
     pub images: Vec<PathBuf>,
 }
 
 /// Context 响应
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct ContextResponse {
     pub anchor_ref: String,
+    /// 所在历史根目录（只配置了一个根时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+    /// anchor 位于 subagent 会话时，父会话中启动它的 Task 调用的 ref（跳回父会话）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_ref: Option<String>,
     pub messages: Vec<ContextMessage>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub truncated: Option<bool>,
+    /// 脱敏替换次数（未启用脱敏时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redactions: Option<usize>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct ContextMessage {
     pub r#ref: String,
     pub r#type: String,
@@ -119,25 +173,370 @@ pub struct ContextMessage {
     pub content: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_anchor: Option<bool>,
+    /// 消息中的 Task 调用（或其结果）启动的 subagent 会话首条消息的 ref（跳入子会话）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subagent_ref: Option<String>,
+}
+
+/// Thread 响应：以 anchor 结尾的线性消息链
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ThreadResponse {
+    pub anchor_ref: String,
+    pub session: String,
+    /// 活动叶子（文件中最后一条主线消息）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_leaf_ref: Option<String>,
+    /// anchor 是否在活动路径上（false 表示处于被编辑/回退废弃的分支）
+    pub on_active_path: bool,
+    pub messages: Vec<ThreadMessage>,
+    /// 会话中所有分支末端
+    pub leaves: Vec<ThreadLeaf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<bool>,
+    /// 脱敏替换次数（未启用脱敏时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redactions: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ThreadMessage {
+    pub r#ref: String,
+    pub uuid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_uuid: Option<String>,
+    pub r#type: String,
+    pub subtype: String,
+    pub timestamp: String,
+    /// 按配置时区渲染的 timestamp（时区为 UTC 时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_timestamp: Option<String>,
+    pub content: String,
+    /// 是否位于活动路径上
+    pub active: bool,
+    /// 同一父消息下的其他分支（ref）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alternatives: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_anchor: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ThreadLeaf {
+    pub r#ref: String,
+    pub timestamp: String,
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+}
+
+/// 工具调用查询响应
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ToolCallsResponse {
+    pub stats: ToolCallsStats,
+    pub calls: Vec<ToolCall>,
+    pub has_more: bool,
+    pub next_offset: usize,
+    /// 脱敏替换次数（未启用脱敏时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redactions: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ToolCallsStats {
+    pub files_scanned: usize,
+    pub total_calls: usize,
+    pub returned_count: usize,
+    pub time_ms: u64,
+}
+
+/// 一次配对后的工具调用：tool_use + 对应的 tool_result
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ToolCall {
+    /// tool_use 所在行
+    pub r#ref: String,
+    /// tool_result 所在行；None 表示未找到结果（被中断或仍在执行）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_ref: Option<String>,
+    pub session: String,
+    pub project: String,
+    #[serde(skip)]
+    pub line: usize,
+    pub tool_use_id: String,
+    pub name: String,
+    pub input: serde_json::Value,
+    pub timestamp: String,
+    /// 按配置时区渲染的 timestamp（时区为 UTC 时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_timestamp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    /// 结果文本原始字符数（截断前）
+    pub result_size: usize,
+    pub result_truncated: bool,
+    pub is_error: bool,
+    /// tool_result 与 tool_use 的时间戳差
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<i64>,
+}
+
+/// 文件触及查询响应
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct FilesResponse {
+    pub stats: FilesStats,
+    /// 按路径汇总的全部命中（不受 offset / limit 影响）
+    pub files: Vec<FileSummary>,
+    /// 逐次触及明细（按时间排序，分页）
+    pub touches: Vec<FileTouch>,
+    pub has_more: bool,
+    pub next_offset: usize,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct FilesStats {
+    pub files_scanned: usize,
+    pub total_touches: usize,
+    pub returned_count: usize,
+    pub time_ms: u64,
+}
+
+/// 单个路径的汇总
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct FileSummary {
+    pub path: String,
+    pub touches: usize,
+    /// 操作类型 → 次数
+    pub ops: BTreeMap<String, usize>,
+    /// 触及过该路径的会话（按首次触及顺序）
+    pub sessions: Vec<String>,
+    pub first_timestamp: String,
+    pub last_timestamp: String,
+    pub last_ref: String,
+}
+
+/// 一次文件触及：某条 tool_use 读 / 写 / 编辑 / 在命令中引用了该路径
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct FileTouch {
+    pub r#ref: String,
+    pub session: String,
+    pub project: String,
+    #[serde(skip)]
+    pub line: usize,
+    pub path: String,
+    /// read / write / edit / bash
+    pub op: String,
+    /// 工具名（Read、Edit、Bash ...）
+    pub tool: String,
+    pub timestamp: String,
+}
+
+/// 文件版本重建响应
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct FileVersionsResponse {
+    /// 解析出的完整文件路径
+    pub path: String,
+    /// 涉及的会话（按首次出现的时间顺序）
+    pub sessions: Vec<String>,
+    /// 版本总数（version 从 1 开始编号）
+    pub version_count: usize,
+    /// 每次相关调用的记录；生效的调用带 version
+    pub versions: Vec<FileVersion>,
+    /// 指定 diff 时两个版本之间的 unified diff
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    /// 内容超出 max_total，之后的版本省略了 content
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redactions: Option<usize>,
+}
+
+/// 一次涉及该文件的调用
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct FileVersion {
+    /// 生效后产生的版本号；未生效时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<usize>,
+    pub r#ref: String,
+    pub session: String,
+    /// write / edit / multi_edit / snapshot（Read 或 originalFile 观察到的内容）
+    pub op: String,
+    pub tool: String,
+    pub timestamp: String,
+    /// applied / failed / no_result / conflict / no_base
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lines: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+/// 用量统计响应
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct UsageResponse {
+    pub stats: UsageStats,
+    pub group_by: Vec<String>,
+    pub groups: Vec<UsageRow>,
+    pub total: UsageTotals,
+    /// 使用的价格表路径；None 表示未估算费用
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pricing_source: Option<String>,
+    /// 价格表中找不到的模型（费用按 0 计）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unpriced_models: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct UsageStats {
+    pub files_scanned: usize,
+    /// 去重后的 API 响应数
+    pub messages: usize,
+    pub time_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct UsageRow {
+    /// 分组维度 → 取值
+    pub group: BTreeMap<String, String>,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct UsageTotals {
+    pub messages: usize,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_input_tokens: u64,
+    pub cache_read_input_tokens: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+}
+
+impl UsageTotals {
+    pub fn add(&mut self, other: &UsageTotals) {
+        self.messages += other.messages;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+        if let Some(cost) = other.cost_usd {
+            *self.cost_usd.get_or_insert(0.0) += cost;
+        }
+    }
+}
+
+/// 时间线响应
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct TimelineResponse {
+    pub stats: TimelineStats,
+    /// 桶大小：hour / day / week
+    pub bucket: String,
+    /// 有命中的桶，按时间升序
+    pub buckets: Vec<TimelineBucket>,
+    /// 星期 × 小时命中数（7 行 24 列，第 0 行为周一；仅 heatmap=true 时返回）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heatmap: Option<Vec<Vec<usize>>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct TimelineStats {
+    pub files_scanned: usize,
+    /// 通过持久化索引裁剪候选行的文件数
+    pub index_hits: usize,
+    pub lines_scanned: usize,
+    /// 去重后的命中消息数（不含无法解析时间戳的消息）
+    pub total_matches: usize,
+    /// 有命中的会话数
+    pub sessions: usize,
+    /// 时间戳缺失或无法解析、未计入任何桶的命中数
+    pub untimed: usize,
+    pub time_ms: u64,
+}
+
+/// 一个时间桶
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct TimelineBucket {
+    /// 桶标签：hour `2026-05-01T13:00`、day `2026-05-01`、week `2026-W18`（ISO 周）
+    pub key: String,
+    /// 桶起点（RFC3339）
+    pub start: String,
+    pub count: usize,
+    /// 桶内有命中的会话，按命中数降序
+    pub sessions: Vec<TimelineSession>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct TimelineSession {
+    pub session: String,
+    pub project: String,
+    /// 所在历史根目录（只配置了一个根时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+    pub count: usize,
+    /// 桶内第一条命中的 ref，可直接用于 history_get / history_context
+    pub first_ref: String,
+}
+
+/// Export 响应
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ExportResponse {
+    pub session: String,
+    pub project: String,
+    pub format: String,
+    pub path: PathBuf,
+    /// 导出文件字节数
+    pub size: usize,
+    pub message_count: usize,
+    pub image_count: usize,
+    /// images=files 时写出的图片文件
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub image_files: Vec<PathBuf>,
+    /// 脱敏替换次数（未启用脱敏时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redactions: Option<usize>,
+}
+
+/// tail 启动响应
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct TailStartResponse {
+    pub watch_id: String,
+    /// 启动时跟踪的文件数（之后新建的会话文件会自动加入）
+    pub files: usize,
+    pub interval_ms: u64,
+}
+
+/// tail 停止响应
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct TailStopResponse {
+    pub watch_id: String,
+    pub messages_sent: usize,
+    /// 停止时的游标，传回 history_tail 的 cursors 参数即可从此处续读
+    pub cursors: BTreeMap<String, TailCursor>,
 }
 
 /// 项目信息
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct ProjectInfo {
+    /// 项目 ID；在多个历史根目录中重名时为 `root:project-id`
     pub id: String,
     pub path: String,
+    /// 所在历史根目录（只配置了一个根时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
     pub session_count: usize,
     pub last_activity: String,
 }
 
 /// 项目列表响应
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct ProjectsResponse {
     pub projects: Vec<ProjectInfo>,
 }
 
 /// 会话信息
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
 pub struct SessionInfo {
     pub id: String,
     pub ref_prefix: String,
@@ -147,13 +546,146 @@ pub struct SessionInfo {
     pub size_bytes: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub topic: Option<String>,
+    /// 消息数（user / assistant / summary / system，不含 progress 等内部记录）
+    pub message_count: usize,
+    /// 按子类型的消息数（human / tool_result / text / tool_use / thinking / summary ...）
+    pub message_counts: BTreeMap<String, usize>,
+    /// 按工具名的调用次数
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub tool_calls: BTreeMap<String, usize>,
+    /// 用到的模型（按首次出现顺序）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<String>,
+    /// token 总量（input + output + cache，按 message.id 去重）
+    pub total_tokens: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub git_branches: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cwds: Vec<String>,
+    pub compact_summary_count: usize,
+    pub subagent_count: usize,
+    /// subagent 会话及启动它们的 Task 调用（按启动顺序，无法关联的排在最后）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub subagents: Vec<SubagentInfo>,
+    /// 最近一次 compact 摘要的文本（超过 1000 字符截断）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latest_summary: Option<String>,
+}
+
+/// subagent 会话（`<session>/subagents/agent-*.jsonl`）与父会话的关联
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct SubagentInfo {
+    /// 子会话 ID（`agent-<id>`）
+    pub session: String,
+    /// 子会话首条消息的 ref
+    pub r#ref: String,
+    /// 父会话中 Task 调用所在消息的 ref；无法关联时省略
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_ref: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_use_id: Option<String>,
+    /// Task 输入的 description
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subagent_type: Option<String>,
 }
 
 /// 会话列表响应
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct SessionsResponse {
     pub project: String,
+    /// 过滤后（limit 之前）的会话数
+    pub total: usize,
     pub sessions: Vec<SessionInfo>,
+    /// 脱敏替换次数（未启用脱敏时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redactions: Option<usize>,
+}
+
+/// 索引构建响应
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexBuildResponse {
+    pub index_dir: PathBuf,
+    pub files_total: usize,
+    pub files_unchanged: usize,
+    /// 仅增量索引了追加行的文件数
+    pub files_appended: usize,
+    /// 整文件（重新）建索引的文件数
+    pub files_rebuilt: usize,
+    pub files_failed: usize,
+    pub lines_indexed: usize,
+    pub time_ms: u64,
+}
+
+/// 归档响应
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveResponse {
+    pub dry_run: bool,
+    /// gz / zst
+    pub format: String,
+    pub files_archived: usize,
+    pub files_failed: usize,
+    /// 已归档文件压缩前后的总字节数
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    pub files: Vec<ArchivedFile>,
+    pub time_ms: u64,
+}
+
+/// 单个会话文件的归档结果
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchivedFile {
+    pub project: String,
+    pub session: String,
+    /// 归档后的路径（失败时为原路径）
+    pub path: PathBuf,
+    pub bytes_before: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes_after: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// config show 响应：生效配置及各项来源
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigShowResponse {
+    /// 配置文件路径（MCP_CLAUDE_HISTORY_CONFIG 或 XDG 配置目录）
+    pub config_file: Option<PathBuf>,
+    pub config_file_exists: bool,
+    pub settings: BTreeMap<String, ConfigSetting>,
+}
+
+/// 单个配置项的生效值
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigSetting {
+    pub value: serde_json::Value,
+    /// default、env:NAME、file、cli:--flag（多个来源以逗号分隔）
+    pub source: String,
+}
+
+/// config validate 响应
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigValidateResponse {
+    pub config_file: PathBuf,
+    pub valid: bool,
+    pub errors: Vec<String>,
+    /// 不影响加载的问题（如目录不存在）
+    pub warnings: Vec<String>,
+}
+
+/// 索引状态响应
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexStatusResponse {
+    pub index_dir: PathBuf,
+    pub files_total: usize,
+    pub files_fresh: usize,
+    /// 文件有追加内容，前缀仍可用（搜索时尾部线性扫描）
+    pub files_appended: usize,
+    /// 文件被截断或改写，搜索回退线性扫描
+    pub files_stale: usize,
+    pub files_missing: usize,
+    pub index_bytes: u64,
 }
 
 /// 错误响应
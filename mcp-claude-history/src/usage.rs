@@ -0,0 +1,439 @@
+//! Token 用量与费用统计
+//!
+//! assistant 记录的 message.usage 按 message.id 去重（同一条 API 响应会按 content block
+//! 拆成多行重复写入 usage），再按 model / session / project / day 分组累加。
+//! 价格表是本地 JSON 文件：`{"模型名或前缀": {"input": 3.0, "output": 15.0, ...}}`，单位为美元 / 百万 token
+
+use crate::config::Config;
+use crate::reader::SessionReader;
+use crate::search::{collect_jsonl_files, get_project_dirs};
+use crate::time::TimeZone;
+use crate::types::*;
+use crate::utils::*;
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// 分组维度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageGroup {
+    Model,
+    Session,
+    Project,
+    Day,
+}
+
+impl UsageGroup {
+    pub fn parse(s: &str) -> Result<Self, ErrorResponse> {
+        match s {
+            "model" => Ok(Self::Model),
+            "session" => Ok(Self::Session),
+            "project" => Ok(Self::Project),
+            "day" => Ok(Self::Day),
+            _ => Err(ErrorResponse {
+                error: "invalid_group_by".to_string(),
+                message: format!("无效的分组维度: {}", s),
+                available: Some(serde_json::json!(["model", "session", "project", "day"])),
+            }),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Model => "model",
+            Self::Session => "session",
+            Self::Project => "project",
+            Self::Day => "day",
+        }
+    }
+}
+
+/// 输出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UsageFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl UsageFormat {
+    pub fn parse(s: &str) -> Result<Self, ErrorResponse> {
+        match s {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => Err(ErrorResponse {
+                error: "invalid_format".to_string(),
+                message: format!("无效的输出格式: {}", s),
+                available: Some(serde_json::json!(["json", "csv"])),
+            }),
+        }
+    }
+}
+
+/// 用量统计参数
+pub struct UsageParams {
+    pub projects: Vec<String>,
+    pub all_projects: bool,
+    pub sessions: Vec<String>,
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    pub until: Option<chrono::DateTime<chrono::Utc>>,
+    /// 分组维度（按顺序组合），空 = 只返回总计
+    pub group_by: Vec<UsageGroup>,
+    pub subagents: bool,
+    /// 显式指定的价格表；None 时使用 Config::pricing_file（不存在则不估算费用）
+    pub pricing: Option<PathBuf>,
+}
+
+impl Default for UsageParams {
+    fn default() -> Self {
+        Self {
+            projects: Vec::new(),
+            all_projects: false,
+            sessions: Vec::new(),
+            since: None,
+            until: None,
+            group_by: vec![UsageGroup::Model],
+            subagents: false,
+            pricing: None,
+        }
+    }
+}
+
+/// 单个模型的价格（美元 / 百万 token），缓存价格缺省按 Anthropic 的倍率推算
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    #[serde(default)]
+    pub cache_write: Option<f64>,
+    #[serde(default)]
+    pub cache_read: Option<f64>,
+}
+
+/// 价格表：key 为模型名或模型名前缀，取最长匹配
+pub struct Pricing {
+    source: String,
+    models: HashMap<String, ModelPrice>,
+}
+
+impl Pricing {
+    fn load(path: &Path) -> Result<Self, ErrorResponse> {
+        let invalid = |message: String| ErrorResponse {
+            error: "invalid_pricing".to_string(),
+            message,
+            available: None,
+        };
+        let text =
+            fs::read_to_string(path).map_err(|e| invalid(format!("无法读取价格表 {}: {}", path.display(), e)))?;
+        let models =
+            serde_json::from_str(&text).map_err(|e| invalid(format!("价格表格式错误 {}: {}", path.display(), e)))?;
+        Ok(Self {
+            source: path.display().to_string(),
+            models,
+        })
+    }
+
+    fn price_for(&self, model: &str) -> Option<&ModelPrice> {
+        self.models.get(model).or_else(|| {
+            self.models
+                .iter()
+                .filter(|(key, _)| model.starts_with(key.as_str()))
+                .max_by_key(|(key, _)| key.len())
+                .map(|(_, price)| price)
+        })
+    }
+
+    fn cost(&self, model: &str, tokens: &UsageTotals) -> Option<f64> {
+        let price = self.price_for(model)?;
+        let per_token = |count: u64, rate: f64| count as f64 * rate / 1_000_000.0;
+        Some(
+            per_token(tokens.input_tokens, price.input)
+                + per_token(tokens.output_tokens, price.output)
+                + per_token(
+                    tokens.cache_creation_input_tokens,
+                    price.cache_write.unwrap_or(price.input * 1.25),
+                )
+                + per_token(
+                    tokens.cache_read_input_tokens,
+                    price.cache_read.unwrap_or(price.input * 0.1),
+                ),
+        )
+    }
+}
+
+/// 一次去重后的 API 响应用量
+struct UsageEntry {
+    message_id: String,
+    model: String,
+    session: String,
+    project: String,
+    day: String,
+    tokens: UsageTotals,
+}
+
+impl UsageEntry {
+    fn key(&self, group: UsageGroup) -> &str {
+        match group {
+            UsageGroup::Model => &self.model,
+            UsageGroup::Session => &self.session,
+            UsageGroup::Project => &self.project,
+            UsageGroup::Day => &self.day,
+        }
+    }
+}
+
+/// 统计 token 用量
+pub fn usage(config: &Config, params: UsageParams) -> Result<UsageResponse, ErrorResponse> {
+    let start = Instant::now();
+
+    let pricing = match &params.pricing {
+        Some(path) => Some(Pricing::load(path)?),
+        None => match &config.pricing_file {
+            Some(path) if path.exists() => Some(Pricing::load(path)?),
+            _ => None,
+        },
+    };
+
+    let project_dirs = get_project_dirs(config, &params.projects, params.all_projects)?;
+    let files = collect_jsonl_files(&project_dirs, &params.sessions, params.subagents);
+
+    let file_results: Vec<Vec<UsageEntry>> = files
+        .par_iter()
+        .map(|(project_id, session_id, path)| {
+            read_usage(project_id, session_id, path, &params, config.defaults.timezone)
+        })
+        .collect();
+    let files_scanned = file_results.len();
+
+    // 同一 message.id 可能出现在多行甚至多个文件（resume 复制历史），只计一次
+    let mut seen = HashSet::new();
+    let entries: Vec<UsageEntry> = file_results
+        .into_iter()
+        .flatten()
+        .filter(|e| e.message_id.is_empty() || seen.insert(e.message_id.clone()))
+        .collect();
+
+    let mut response = aggregate(&entries, &params.group_by, pricing.as_ref());
+    response.stats = UsageStats {
+        files_scanned,
+        messages: entries.len(),
+        time_ms: start.elapsed().as_millis() as u64,
+    };
+    Ok(response)
+}
+
+/// 读取单个文件中 assistant 记录的 usage；按天分组用 tz 下的日期，与 timeline 分桶一致
+fn read_usage(project_id: &str, session_id: &str, path: &Path, params: &UsageParams, tz: TimeZone) -> Vec<UsageEntry> {
+    let Ok(reader) = SessionReader::open(path) else {
+        return Vec::new();
+    };
+
+    let mut entries: Vec<UsageEntry> = Vec::new();
+    let mut by_id: HashMap<String, usize> = HashMap::new();
+    // 快速预过滤，避免对每一行做完整 JSON 解析
+    for (_, record) in reader.filter_lines(|_, line| line.contains("\"usage\"")) {
+        if record.msg_type != "assistant" {
+            continue;
+        }
+        let Some(message) = record.message.as_ref() else {
+            continue;
+        };
+        let Some(usage) = message.usage else {
+            continue;
+        };
+        let message_id = message.id.as_deref().unwrap_or_default();
+        if !time_in_range(&record.timestamp, params.since.as_ref(), params.until.as_ref()) {
+            continue;
+        }
+
+        let entry = UsageEntry {
+            message_id: message_id.to_string(),
+            model: message.model.as_deref().unwrap_or("unknown").to_string(),
+            session: session_id.to_string(),
+            project: project_id.to_string(),
+            day: parse_time(&record.timestamp)
+                .map(|t| tz.local(t).date_naive().to_string())
+                .unwrap_or_else(|| "unknown".to_string()),
+            tokens: UsageTotals {
+                messages: 1,
+                input_tokens: usage.input_tokens,
+                output_tokens: usage.output_tokens,
+                cache_creation_input_tokens: usage.cache_creation_input_tokens,
+                cache_read_input_tokens: usage.cache_read_input_tokens,
+                cost_usd: None,
+            },
+        };
+        // 流式写入时后出现的行带有最终的 output_tokens，同一 message.id 以最后一行为准
+        match by_id.get(message_id) {
+            Some(&idx) if !message_id.is_empty() => entries[idx] = entry,
+            _ => {
+                by_id.insert(message_id.to_string(), entries.len());
+                entries.push(entry);
+            }
+        }
+    }
+    entries
+}
+
+/// 按分组维度累加；费用逐条计算后再求和，因此不按 model 分组时也能得到正确费用
+fn aggregate(entries: &[UsageEntry], group_by: &[UsageGroup], pricing: Option<&Pricing>) -> UsageResponse {
+    let mut groups: BTreeMap<Vec<String>, UsageTotals> = BTreeMap::new();
+    let mut total = UsageTotals::default();
+    let mut unpriced = BTreeSet::new();
+
+    for entry in entries {
+        let mut tokens = entry.tokens.clone();
+        if let Some(pricing) = pricing {
+            tokens.cost_usd = Some(pricing.cost(&entry.model, &entry.tokens).unwrap_or_else(|| {
+                unpriced.insert(entry.model.clone());
+                0.0
+            }));
+        }
+        let key = group_by.iter().map(|g| entry.key(*g).to_string()).collect();
+        groups.entry(key).or_default().add(&tokens);
+        total.add(&tokens);
+    }
+
+    let group_names: Vec<String> = group_by.iter().map(|g| g.name().to_string()).collect();
+    UsageResponse {
+        stats: UsageStats::default(),
+        group_by: group_names.clone(),
+        groups: if group_by.is_empty() {
+            Vec::new()
+        } else {
+            groups
+                .into_iter()
+                .map(|(key, totals)| UsageRow {
+                    group: group_names.iter().cloned().zip(key).collect(),
+                    totals,
+                })
+                .collect()
+        },
+        total,
+        pricing_source: pricing.map(|p| p.source.clone()),
+        unpriced_models: unpriced.into_iter().collect(),
+    }
+}
+
+/// 把分组结果渲染为 CSV（分组列 + token 列 + 费用列）
+pub fn render_csv(response: &UsageResponse) -> String {
+    fn field(s: &str) -> String {
+        if s.contains([',', '"', '\n']) {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s.to_string()
+        }
+    }
+
+    let mut header: Vec<String> = response.group_by.clone();
+    header.extend(
+        [
+            "messages",
+            "input_tokens",
+            "output_tokens",
+            "cache_creation_input_tokens",
+            "cache_read_input_tokens",
+            "cost_usd",
+        ]
+        .map(String::from),
+    );
+    let mut out = header.join(",");
+    out.push('\n');
+
+    let row = |keys: Vec<String>, t: &UsageTotals| {
+        let mut cols: Vec<String> = keys.iter().map(|k| field(k)).collect();
+        cols.push(t.messages.to_string());
+        cols.push(t.input_tokens.to_string());
+        cols.push(t.output_tokens.to_string());
+        cols.push(t.cache_creation_input_tokens.to_string());
+        cols.push(t.cache_read_input_tokens.to_string());
+        cols.push(t.cost_usd.map(|c| format!("{:.4}", c)).unwrap_or_default());
+        cols.join(",") + "\n"
+    };
+    for g in &response.groups {
+        let keys = response.group_by.iter().map(|name| g.group[name].clone()).collect();
+        out.push_str(&row(keys, &g.totals));
+    }
+    // 最后一行为总计，分组列填 "total"
+    let keys = if response.group_by.is_empty() {
+        Vec::new()
+    } else {
+        vec!["total".to_string(); response.group_by.len()]
+    };
+    out.push_str(&row(keys, &response.total));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(model: &str, day: &str, input: u64, output: u64) -> UsageEntry {
+        UsageEntry {
+            message_id: String::new(),
+            model: model.to_string(),
+            session: "s".to_string(),
+            project: "p".to_string(),
+            day: day.to_string(),
+            tokens: UsageTotals {
+                messages: 1,
+                input_tokens: input,
+                output_tokens: output,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_aggregate_with_prefix_pricing() {
+        let pricing = Pricing {
+            source: "test".to_string(),
+            models: serde_json::from_value(serde_json::json!({
+                "claude-opus": {"input": 15.0, "output": 75.0},
+                "claude-opus-4-1": {"input": 10.0, "output": 50.0},
+            }))
+            .unwrap(),
+        };
+        let entries = [
+            entry("claude-opus-4-1-20250805", "2026-05-01", 1_000_000, 0),
+            entry("claude-opus-4-20250514", "2026-05-01", 1_000_000, 0),
+            entry("claude-opus-4-20250514", "2026-05-02", 0, 1_000_000),
+            entry("gpt-x", "2026-05-02", 5, 5),
+        ];
+        let response = aggregate(&entries, &[UsageGroup::Day], Some(&pricing));
+
+        assert_eq!(response.groups.len(), 2);
+        assert_eq!(response.groups[0].group["day"], "2026-05-01");
+        // 最长前缀匹配：opus-4-1 用 10.0，其余 opus 用 15.0
+        assert_eq!(response.groups[0].totals.cost_usd, Some(25.0));
+        assert_eq!(response.groups[1].totals.cost_usd, Some(75.0));
+        assert_eq!(response.total.messages, 4);
+        assert_eq!(response.unpriced_models, vec!["gpt-x".to_string()]);
+
+        let csv = render_csv(&response);
+        assert!(csv.starts_with("day,messages,"));
+        assert!(csv.lines().last().unwrap().starts_with("total,4,"));
+    }
+
+    #[test]
+    fn test_day_in_configured_timezone() {
+        let tmp = std::env::temp_dir().join(format!("mcp-usage-test-{}", std::process::id()));
+        fs::create_dir_all(&tmp).unwrap();
+        let path = tmp.join("s.jsonl");
+        fs::write(
+            &path,
+            r#"{"type":"assistant","timestamp":"2026-05-01T23:30:00Z","message":{"id":"m1","model":"claude-x","usage":{"input_tokens":1,"output_tokens":1},"content":[]}}"#,
+        )
+        .unwrap();
+        let day = |tz| read_usage("p", "s", &path, &UsageParams::default(), tz)[0].day.clone();
+
+        assert_eq!(day(TimeZone::Utc), "2026-05-01");
+        // 东八区已是次日，与 timeline 的分桶一致
+        assert_eq!(day(TimeZone::parse("+08:00").unwrap()), "2026-05-02");
+
+        fs::remove_dir_all(&tmp).ok();
+    }
+}
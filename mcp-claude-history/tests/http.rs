@@ -0,0 +1,140 @@
+//! streamable HTTP 模式的集成测试：启动真实进程，用 rmcp 客户端通过 HTTP 调用工具
+
+use rmcp::ServiceExt;
+use rmcp::model::CallToolRequestParams;
+use rmcp::transport::StreamableHttpClientTransport;
+use rmcp::transport::streamable_http_client::StreamableHttpClientTransportConfig;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+/// 测试结束时结束服务器进程并清理临时目录
+struct Server {
+    child: Child,
+    home: PathBuf,
+    url: String,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        std::fs::remove_dir_all(&self.home).ok();
+    }
+}
+
+fn fixture(name: &str) -> PathBuf {
+    let home = std::env::temp_dir().join(format!("mcp-http-test-{}-{}", name, std::process::id()));
+    let project = home.join(".claude").join("projects").join("-tmp-http");
+    std::fs::create_dir_all(&project).unwrap();
+    std::fs::write(
+        project.join("aaaabbbb-1111.jsonl"),
+        concat!(
+            r#"{"uuid":"u1","type":"user","cwd":"/tmp/http","timestamp":"2026-05-01T00:00:00Z","#,
+            r#""message":{"role":"user","content":"hello over http"}}"#,
+            "\n"
+        ),
+    )
+    .unwrap();
+    home
+}
+
+/// 以 `--http 127.0.0.1:0` 启动，从 stderr 读取实际监听地址
+fn spawn(home: &Path, token: Option<&str>) -> Server {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_mcp-claude-history"));
+    command
+        .args(["--http", "127.0.0.1:0"])
+        .env("HOME", home)
+        // 开发者自己的 Claude 目录和配置文件不能影响测试（XDG_CONFIG_HOME 缺省时回落到 HOME/.config）
+        .env_remove("CLAUDE_CONFIG_DIR")
+        .env_remove("MCP_CLAUDE_HISTORY_CONFIG")
+        .env_remove("XDG_CONFIG_HOME")
+        .env_remove("MCP_CLAUDE_HISTORY_TOKEN")
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    if let Some(token) = token {
+        command.env("MCP_CLAUDE_HISTORY_TOKEN", token);
+    }
+    let mut child = command.spawn().unwrap();
+
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut line = String::new();
+    stderr.read_line(&mut line).unwrap();
+    let url = line
+        .trim()
+        .split_once("listening on ")
+        .map(|(_, url)| url.to_string())
+        .unwrap_or_else(|| panic!("unexpected startup output: {:?}", line));
+    Server {
+        child,
+        home: home.to_path_buf(),
+        url,
+    }
+}
+
+#[tokio::test]
+async fn test_http_tools_roundtrip() {
+    let server = spawn(&fixture("plain"), None);
+
+    let client = ().serve(StreamableHttpClientTransport::from_uri(server.url.as_str())).await.unwrap();
+    let tools = client.list_all_tools().await.unwrap();
+    assert!(tools.iter().any(|t| t.name == "history_search"));
+
+    let result = client
+        .call_tool(CallToolRequestParams::new("history_projects"))
+        .await
+        .unwrap();
+    let projects = result.structured_content.unwrap();
+    assert_eq!(projects["projects"][0]["id"], "-tmp-http");
+
+    // 同一服务器可同时服务多个客户端
+    let second = ().serve(StreamableHttpClientTransport::from_uri(server.url.as_str())).await.unwrap();
+    let result = second
+        .call_tool(
+            CallToolRequestParams::new("history_search").with_arguments(
+                serde_json::json!({"pattern": "over http", "project": "-tmp-http"})
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            ),
+        )
+        .await
+        .unwrap();
+    assert_eq!(result.structured_content.unwrap()["results"][0]["ref"], "aaaabbbb:1");
+
+    second.cancel().await.unwrap();
+    client.cancel().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_http_bearer_token() {
+    let server = spawn(&fixture("token"), Some("s3cret"));
+
+    // 未带 token / token 错误：401
+    let http = reqwest::Client::new();
+    for auth in [None, Some("Bearer wrong")] {
+        let mut request = http
+            .post(&server.url)
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json, text/event-stream")
+            .body(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+        if let Some(auth) = auth {
+            request = request.header("Authorization", auth);
+        }
+        let response = request.send().await.unwrap();
+        assert_eq!(response.status(), 401);
+        assert_eq!(response.headers()["www-authenticate"], "Bearer");
+    }
+
+    // 带正确 token 的客户端可正常调用
+    let transport = StreamableHttpClientTransport::from_config(
+        StreamableHttpClientTransportConfig::with_uri(server.url.as_str()).auth_header("s3cret"),
+    );
+    let client = ().serve(transport).await.unwrap();
+    let result = client
+        .call_tool(CallToolRequestParams::new("history_projects"))
+        .await
+        .unwrap();
+    assert_eq!(result.is_error, Some(false));
+    client.cancel().await.unwrap();
+}